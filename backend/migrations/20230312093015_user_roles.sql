@@ -0,0 +1,8 @@
+create table user_role (
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	role TEXT NOT NULL,
+	PRIMARY KEY (user_id, role)
+);
+
+insert into user_role (user_id, role)
+select user_id, 'user' from user_profile;
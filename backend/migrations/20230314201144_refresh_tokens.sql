@@ -0,0 +1,14 @@
+-- every login starts a token family, each refresh replaces the family's current token
+-- presenting a token that was already used revokes the whole family
+create table refresh_token (
+	token_hash TEXT PRIMARY KEY,
+	family_id uuid NOT NULL,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	issued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	expires_at TIMESTAMPTZ NOT NULL,
+	used_at TIMESTAMPTZ,
+	revoked_at TIMESTAMPTZ
+);
+
+create index refresh_token_family_idx on refresh_token (family_id);
+create index refresh_token_user_idx on refresh_token (user_id);
@@ -0,0 +1,34 @@
+-- applications that can get tokens from us
+create table oauth_client (
+	client_id TEXT PRIMARY KEY,
+	name TEXT NOT NULL,
+	-- NULL for public clients (cli, native apps) that can't keep a secret
+	client_secret_hash TEXT,
+	redirect_uris TEXT[] NOT NULL DEFAULT '{}',
+	allowed_scopes TEXT[] NOT NULL DEFAULT '{}',
+	grant_types TEXT[] NOT NULL DEFAULT '{}',
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+insert into oauth_client (client_id, name, allowed_scopes, grant_types)
+values (
+	'yogamat-cli',
+	'yogamat command line',
+	'{poses}',
+	'{urn:ietf:params:oauth:grant-type:device_code,refresh_token}'
+);
+
+-- RFC 8628 device authorization requests
+create table device_authorization (
+	device_code_hash TEXT PRIMARY KEY,
+	user_code TEXT NOT NULL UNIQUE,
+	client_id TEXT NOT NULL REFERENCES oauth_client (client_id) ON DELETE CASCADE,
+	scope TEXT NOT NULL DEFAULT '',
+	-- pending, approved, denied or consumed
+	status TEXT NOT NULL DEFAULT 'pending',
+	user_id uuid REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	interval_seconds INTEGER NOT NULL,
+	last_polled_at TIMESTAMPTZ,
+	expires_at TIMESTAMPTZ NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
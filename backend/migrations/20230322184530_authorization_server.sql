@@ -0,0 +1,32 @@
+-- authorization code flow for our registered clients
+-- a row is created when a logged in user arrives at /oauth/authorize, the code is set once
+-- they consent
+create table authorization_request (
+	request_id uuid PRIMARY KEY,
+	client_id TEXT NOT NULL REFERENCES oauth_client (client_id) ON DELETE CASCADE,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	redirect_uri TEXT NOT NULL,
+	scope TEXT NOT NULL,
+	state TEXT,
+	nonce TEXT,
+	code_challenge TEXT NOT NULL,
+	code_hash TEXT UNIQUE,
+	code_used_at TIMESTAMPTZ,
+	-- refresh token family issued for the code, revoked if the code is replayed
+	family_id uuid,
+	expires_at TIMESTAMPTZ NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+create table user_consent (
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	client_id TEXT NOT NULL REFERENCES oauth_client (client_id) ON DELETE CASCADE,
+	scope TEXT NOT NULL,
+	granted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (user_id, client_id)
+);
+
+-- refresh tokens held by registered clients remember who they belong to and what was granted
+alter table refresh_token
+	add column client_id TEXT REFERENCES oauth_client (client_id) ON DELETE CASCADE,
+	add column scope TEXT;
@@ -0,0 +1,13 @@
+-- the provider accounts a user logs in with, subject is the provider's stable id for the
+-- account (Google sub, GitHub numeric id), emails can change
+create table user_identity (
+	provider TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	email TEXT NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	last_login_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (provider, subject)
+);
+
+create index user_identity_user_idx on user_identity (user_id);
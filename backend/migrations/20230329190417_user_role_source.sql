@@ -0,0 +1,3 @@
+-- where a role came from, roles of a provider are replaced on every login with that provider
+-- while roles given here ('local') are left alone
+alter table user_role add column source TEXT NOT NULL DEFAULT 'local';
@@ -0,0 +1,15 @@
+-- security audit log, kept when the user is deleted
+create table auth_event (
+	event_id uuid NOT NULL PRIMARY KEY,
+	occurred_at timestamptz NOT NULL DEFAULT now(),
+	event_type TEXT NOT NULL,
+	user_id uuid REFERENCES user_profile (user_id) ON DELETE SET NULL,
+	provider TEXT,
+	client_id TEXT,
+	ip TEXT,
+	user_agent TEXT,
+	detail TEXT
+);
+
+create index auth_event_user_idx on auth_event (user_id, occurred_at DESC);
+create index auth_event_occurred_idx on auth_event (occurred_at DESC);
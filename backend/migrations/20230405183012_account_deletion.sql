@@ -0,0 +1,7 @@
+-- a deleted account is only marked, the purge removes it for good once purge_after has passed
+-- logging in again before that brings it back
+alter table user_profile
+	add column deleted_at TIMESTAMPTZ,
+	add column purge_after TIMESTAMPTZ;
+
+create index user_profile_purge_idx on user_profile (purge_after) where purge_after IS NOT NULL;
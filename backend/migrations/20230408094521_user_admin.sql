@@ -0,0 +1,8 @@
+-- a disabled user can't log in and their tokens stop working
+alter table user_profile
+	add column disabled_at TIMESTAMPTZ,
+	add column disabled_reason TEXT;
+
+-- the admin who did something to the user, NULL for the user's own events
+alter table auth_event
+	add column actor_id uuid REFERENCES user_profile (user_id) ON DELETE SET NULL;
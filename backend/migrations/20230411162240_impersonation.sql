@@ -0,0 +1,11 @@
+-- an admin acting as another user for support, the access token's sid is the impersonation_id
+create table impersonation (
+	impersonation_id uuid PRIMARY KEY,
+	admin_id uuid REFERENCES user_profile (user_id) ON DELETE SET NULL,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	expires_at TIMESTAMPTZ NOT NULL,
+	ended_at TIMESTAMPTZ
+);
+
+create index impersonation_user_idx on impersonation (user_id);
@@ -0,0 +1,84 @@
+create table pose (
+	pose_id SERIAL PRIMARY KEY,
+	name TEXT NOT NULL UNIQUE,
+	sanskrit_name TEXT NOT NULL,
+	category TEXT NOT NULL CHECK (category IN
+		('standing', 'seated', 'forward_bend', 'backbend', 'twist', 'inversion', 'balance', 'hip_opener', 'restorative')),
+	difficulty TEXT NOT NULL CHECK (difficulty IN ('beginner', 'intermediate', 'advanced')),
+	description TEXT NOT NULL,
+	cues TEXT[] NOT NULL DEFAULT '{}',
+	contraindications TEXT[] NOT NULL DEFAULT '{}',
+	-- key of the image in the media storage, NULL while there is none
+	image_ref TEXT,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+create index pose_category_idx on pose (category, difficulty);
+
+-- the three poses the api used to hard code and a few more to start with
+insert into pose (name, sanskrit_name, category, difficulty, description, cues, contraindications) values
+('updog', 'Urdhva Mukha Svanasana', 'backbend', 'beginner',
+	'Upward facing dog. From lying on the belly press into the hands and the tops of the feet, lifting the chest forward and up with the thighs off the floor.',
+	'{"shoulders away from the ears", "press the tops of the feet down", "lift through the sternum"}',
+	'{"back injury", "carpal tunnel syndrome", "pregnancy"}'),
+('downdog', 'Adho Mukha Svanasana', 'inversion', 'beginner',
+	'Downward facing dog. Hands and feet on the floor, hips lifted high so the body forms an inverted V.',
+	'{"spread the fingers wide", "lengthen the spine before straightening the legs", "heels reach toward the floor"}',
+	'{"carpal tunnel syndrome", "late pregnancy", "uncontrolled high blood pressure"}'),
+('yoganidrasana', 'Yoganidrasana', 'hip_opener', 'advanced',
+	'Yogic sleep pose. Lying on the back both legs are taken behind the head and the hands bind behind the back.',
+	'{"warm up the hips first", "keep the breath slow", "work one leg at a time"}',
+	'{"hip injury", "knee injury", "lower back injury"}'),
+('mountain', 'Tadasana', 'standing', 'beginner',
+	'Standing tall with the feet together and the weight even on both feet, the base of all standing poses.',
+	'{"ground through all four corners of the feet", "lengthen the crown of the head up", "soften the shoulders"}',
+	'{}'),
+('tree', 'Vrksasana', 'balance', 'beginner',
+	'Standing on one leg with the sole of the other foot on the inner thigh or calf, hands at the heart or overhead.',
+	'{"foot above or below the knee, never on it", "fix the gaze on one point", "press foot and leg into each other"}',
+	'{"low blood pressure", "dizziness"}'),
+('warrior one', 'Virabhadrasana I', 'standing', 'beginner',
+	'Lunge with the back foot turned out and the hips squared to the front, arms reaching overhead.',
+	'{"front knee over the ankle", "square the hips forward", "press the outer edge of the back foot down"}',
+	'{"knee injury", "shoulder injury"}'),
+('warrior two', 'Virabhadrasana II', 'standing', 'beginner',
+	'Wide stance with the front knee bent and the hips open to the side, arms stretched out at shoulder height.',
+	'{"front knee tracks the second toe", "shoulders over the hips", "gaze over the front hand"}',
+	'{"knee injury", "neck problems (look forward instead)"}'),
+('triangle', 'Utthita Trikonasana', 'standing', 'beginner',
+	'Wide stance with straight legs, the torso tilted sideways over the front leg and the arms in one line.',
+	'{"lengthen the side waist before tilting", "keep both legs straight, not locked", "open the chest to the side"}',
+	'{"low blood pressure", "neck injury"}'),
+('seated forward bend', 'Paschimottanasana', 'forward_bend', 'beginner',
+	'Seated with the legs straight in front, folding forward from the hips over the legs.',
+	'{"fold from the hips, not the waist", "bend the knees if the back rounds", "lengthen on the inhale, fold on the exhale"}',
+	'{"back injury", "asthma"}'),
+('bridge', 'Setu Bandha Sarvangasana', 'backbend', 'beginner',
+	'Lying on the back with the knees bent, lifting the hips and the chest up.',
+	'{"knees stay hip width apart", "press into the feet and the arms", "keep the neck still"}',
+	'{"neck injury"}'),
+('seated twist', 'Ardha Matsyendrasana', 'twist', 'intermediate',
+	'Seated with one leg crossed over the other, turning the torso toward the bent knee.',
+	'{"lengthen on the inhale, twist on the exhale", "both sit bones stay on the floor", "the twist starts in the belly"}',
+	'{"back or spine injury", "pregnancy"}'),
+('crow', 'Bakasana', 'balance', 'intermediate',
+	'Arm balance with the knees resting high on the back of the upper arms and the feet lifted off the floor.',
+	'{"look forward, not down", "round the upper back", "shift the weight forward slowly"}',
+	'{"wrist injury", "carpal tunnel syndrome", "pregnancy"}'),
+('headstand', 'Salamba Sirsasana', 'inversion', 'advanced',
+	'Balancing upside down on the forearms and the top of the head with the legs straight up.',
+	'{"most of the weight in the forearms", "engage the core before lifting the legs", "come down before tiring"}',
+	'{"neck injury", "high blood pressure", "glaucoma", "menstruation"}'),
+('pigeon', 'Eka Pada Rajakapotasana', 'hip_opener', 'intermediate',
+	'One shin forward across the mat and the other leg stretched back, hips sinking toward the floor.',
+	'{"keep the hips level", "flex the front foot", "support the front hip with a block if needed"}',
+	'{"knee injury", "sacroiliac injury"}'),
+('child''s pose', 'Balasana', 'restorative', 'beginner',
+	'Kneeling and folding forward, the forehead resting on the floor and the arms forward or alongside the body.',
+	'{"knees wide or together", "let the breath move into the back", "rest the forehead on a block if needed"}',
+	'{"knee injury", "late pregnancy (knees wide)"}'),
+('corpse', 'Savasana', 'restorative', 'beginner',
+	'Lying flat on the back, completely relaxed, usually at the end of a practice.',
+	'{"let the feet fall open", "palms face up", "release control of the breath"}',
+	'{}');
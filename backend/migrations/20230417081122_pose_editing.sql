@@ -0,0 +1,9 @@
+-- every change bumps the version, writers send the version they read in If-Match
+alter table pose
+	add column version INT NOT NULL DEFAULT 1,
+	-- deleted poses stay for whatever still refers to them, they just aren't listed anymore
+	add column deleted_at TIMESTAMPTZ;
+
+-- a deleted pose's name can be used again
+alter table pose drop constraint pose_name_key;
+create unique index pose_name_idx on pose (lower(name)) where deleted_at IS NULL;
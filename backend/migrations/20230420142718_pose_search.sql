@@ -0,0 +1,15 @@
+create extension if not exists unaccent;
+
+-- unaccent() is only stable, a generated column needs an immutable function
+create or replace function immutable_unaccent(text) returns text
+	language sql immutable parallel safe strict
+	as $$ select public.unaccent('public.unaccent'::regdictionary, $1) $$;
+
+-- 'simple' and not 'english', the Sanskrit names must not be stemmed
+alter table pose add column search tsvector generated always as (
+	setweight(to_tsvector('simple', immutable_unaccent(name)), 'A') ||
+	setweight(to_tsvector('simple', immutable_unaccent(sanskrit_name)), 'A') ||
+	setweight(to_tsvector('simple', immutable_unaccent(description)), 'B')
+) stored;
+
+create index pose_search_idx on pose using gin (search);
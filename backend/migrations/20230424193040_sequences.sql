@@ -0,0 +1,27 @@
+create table sequence (
+	sequence_id uuid PRIMARY KEY,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	description TEXT NOT NULL DEFAULT '',
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+create index sequence_user_idx on sequence (user_id);
+
+-- a pose held for some seconds or some breaths, on one side or both
+create table sequence_step (
+	step_id uuid PRIMARY KEY,
+	sequence_id uuid NOT NULL REFERENCES sequence (sequence_id) ON DELETE CASCADE,
+	position INT NOT NULL,
+	-- poses are only ever soft deleted, a sequence keeps working
+	pose_id INT NOT NULL REFERENCES pose (pose_id),
+	duration_seconds INT CHECK (duration_seconds > 0),
+	breaths INT CHECK (breaths > 0),
+	side TEXT CHECK (side IN ('left', 'right', 'both')),
+	notes TEXT NOT NULL DEFAULT '',
+	-- deferred, reordering swaps positions within one statement
+	UNIQUE (sequence_id, position) DEFERRABLE INITIALLY DEFERRED
+);
+
+create index sequence_step_pose_idx on sequence_step (pose_id);
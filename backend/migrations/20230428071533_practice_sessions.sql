@@ -0,0 +1,28 @@
+-- a practice the user did, following one of their sequences or ad hoc
+create table practice_session (
+	practice_id uuid PRIMARY KEY,
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	-- the history stays when the sequence goes
+	sequence_id uuid REFERENCES sequence (sequence_id) ON DELETE SET NULL,
+	started_at TIMESTAMPTZ NOT NULL,
+	ended_at TIMESTAMPTZ NOT NULL,
+	-- perceived effort, 1 (easy) to 10 (all out)
+	effort INT CHECK (effort BETWEEN 1 AND 10),
+	notes TEXT NOT NULL DEFAULT '',
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	CHECK (ended_at >= started_at)
+);
+
+create index practice_session_user_idx on practice_session (user_id, started_at);
+
+-- what was actually held, in the order it was done
+create table practice_step (
+	practice_id uuid NOT NULL REFERENCES practice_session (practice_id) ON DELETE CASCADE,
+	position INT NOT NULL,
+	pose_id INT NOT NULL REFERENCES pose (pose_id),
+	actual_seconds INT NOT NULL CHECK (actual_seconds >= 0),
+	side TEXT CHECK (side IN ('left', 'right', 'both')),
+	PRIMARY KEY (practice_id, position)
+);
+
+create index practice_step_pose_idx on practice_step (pose_id);
@@ -0,0 +1,10 @@
+-- days, weeks and streaks are counted in the user's timezone
+alter table user_profile add column timezone TEXT NOT NULL DEFAULT 'UTC';
+
+-- the last computed /me/stats, dropped whenever the user logs practice
+create table practice_stats_cache (
+	user_id uuid PRIMARY KEY REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	timezone TEXT NOT NULL,
+	stats TEXT NOT NULL,
+	computed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,25 @@
+-- what a user keeps for themselves about poses of the catalog
+create table pose_favorite (
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	pose_id INT NOT NULL REFERENCES pose (pose_id),
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (user_id, pose_id)
+);
+
+-- tags are the user's own words, lowercase
+create table pose_tag (
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	pose_id INT NOT NULL REFERENCES pose (pose_id),
+	tag TEXT NOT NULL,
+	PRIMARY KEY (user_id, pose_id, tag)
+);
+
+create index pose_tag_user_tag_idx on pose_tag (user_id, tag);
+
+create table pose_note (
+	user_id uuid NOT NULL REFERENCES user_profile (user_id) ON DELETE CASCADE,
+	pose_id INT NOT NULL REFERENCES pose (pose_id),
+	note TEXT NOT NULL,
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (user_id, pose_id)
+);
@@ -0,0 +1,17 @@
+-- uploaded images, the files are in the media storage under media/<media_id>/
+create table media (
+	media_id uuid PRIMARY KEY,
+	-- of the original, sniffed from its bytes
+	content_type TEXT NOT NULL,
+	byte_size INT NOT NULL,
+	width INT NOT NULL,
+	height INT NOT NULL,
+	-- widths of the jpeg thumbnails stored next to the original
+	thumbnail_widths INT[] NOT NULL,
+	uploaded_by uuid REFERENCES user_profile (user_id) ON DELETE SET NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- image_ref was never filled, a pose's image is an upload now
+alter table pose drop column image_ref;
+alter table pose add column image_id uuid REFERENCES media (media_id);
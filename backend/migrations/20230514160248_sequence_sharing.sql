@@ -0,0 +1,8 @@
+-- private: the owner only, unlisted: anyone with the link, public: listed for everybody too
+alter table sequence add column visibility TEXT NOT NULL DEFAULT 'private'
+	CHECK (visibility IN ('private', 'unlisted', 'public'));
+-- kept in the clear, the owner can look up the link any time and it only reads one sequence;
+-- replaced to break old links, none while private
+alter table sequence add column share_token TEXT UNIQUE;
+
+create index sequence_public_idx on sequence (updated_at) WHERE visibility = 'public';
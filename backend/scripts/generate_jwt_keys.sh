@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+set -x
+set -eo pipefail
+
+# generates the key pair the backend signs its own access tokens with
+# run from the backend directory, the paths match jwt.*_key_path in configuration/base.yaml
+KEY_DIR="${KEY_DIR:=keys}"
+mkdir -p "${KEY_DIR}"
+
+if [[ "${JWT_ALGORITHM}" == "RS256" ]]
+then
+openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 -out "${KEY_DIR}/jwt_private.pem"
+else
+openssl genpkey -algorithm ed25519 -out "${KEY_DIR}/jwt_private.pem"
+fi
+openssl pkey -in "${KEY_DIR}/jwt_private.pem" -pubout -out "${KEY_DIR}/jwt_public.pem"
+
+>&2 echo "jwt keys written to ${KEY_DIR}"
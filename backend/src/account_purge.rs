@@ -0,0 +1,21 @@
+// Removes deleted accounts once their grace period is over, runs next to the server.
+
+use std::time::Duration;
+
+use actix_web::web;
+
+use crate::database::YogaDatabase;
+
+pub async fn run(db: web::Data<YogaDatabase>, every: Duration) {
+    let mut interval = actix_web::rt::time::interval(every);
+    loop {
+        interval.tick().await;
+        match db.purge_deleted_users().await {
+            Ok(user_ids) if !user_ids.is_empty() => {
+                tracing::info!("purged {} deleted accounts", user_ids.len())
+            }
+            Ok(_) => {}
+            Err(error) => tracing::error!("account purge failed {}", error),
+        }
+    }
+}
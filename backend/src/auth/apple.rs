@@ -0,0 +1,67 @@
+// Sign in with Apple
+// https://developer.apple.com/documentation/sign_in_with_apple/sign_in_with_apple_rest_api
+// Apple speaks OpenID Connect with two twists:
+// - the client secret is a short lived ES256 JWT we sign with the key (.p8) from the developer
+//   account, so it is made again for every code exchange
+// - asking for the name or email needs response_mode=form_post, Apple POSTs the code and state
+//   to the redirect url instead of redirecting the browser
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+use serde::Serialize;
+
+use super::oidc::OidcProvider;
+use super::OidcClient;
+
+pub const APPLE_ISSUER: &str = "https://appleid.apple.com";
+const CLIENT_SECRET_LIFETIME_SECONDS: u64 = 300;
+
+pub struct AppleProvider {
+    // the Services ID is the client id, the client has no secret
+    pub oidc: OidcProvider,
+    pub team_id: String,
+    pub key_id: String,
+    pub signing_key: EncodingKey,
+    pub auth_url: AuthUrl,
+    pub token_url: TokenUrl,
+    pub redirect_url: RedirectUrl,
+}
+
+#[derive(Serialize)]
+struct ClientSecretClaims<'a> {
+    iss: &'a str,
+    iat: u64,
+    exp: u64,
+    aud: &'a str,
+    sub: &'a str,
+}
+
+impl AppleProvider {
+    fn client_secret(&self) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = jsonwebtoken::get_current_timestamp();
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+        jsonwebtoken::encode(
+            &header,
+            &ClientSecretClaims {
+                iss: &self.team_id,
+                iat: now,
+                exp: now + CLIENT_SECRET_LIFETIME_SECONDS,
+                aud: APPLE_ISSUER,
+                sub: &self.oidc.client_id,
+            },
+            &self.signing_key,
+        )
+    }
+
+    // The client to exchange a code with, carrying a freshly signed secret.
+    pub fn token_client(&self) -> Result<OidcClient, jsonwebtoken::errors::Error> {
+        Ok(OidcClient::new(
+            ClientId::new(self.oidc.client_id.clone()),
+            Some(ClientSecret::new(self.client_secret()?)),
+            self.auth_url.clone(),
+            Some(self.token_url.clone()),
+        )
+        .set_redirect_uri(self.redirect_url.clone()))
+    }
+}
@@ -0,0 +1,135 @@
+// The security audit log, one auth_event row per login, logout, refresh, revocation, ...
+// Writing an event never fails the request, a database error is only logged.
+
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{http::header, web, FromRequest, HttpRequest};
+use uuid::Uuid;
+
+use crate::database::{NewAuthEvent, YogaDatabase};
+use crate::rate_limit::RateLimiter;
+
+#[derive(strum_macros::AsRefStr, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum AuthEventKind {
+    Login,
+    LoginFailed,
+    // the state a provider sent back isn't the one we sent
+    StateMismatch,
+    Logout,
+    Refresh,
+    RefreshReused,
+    TokenRevoked,
+    ClientAuthFailed,
+    AccountDeleted,
+    // logged in again within the grace period
+    AccountRestored,
+    // admin actions, the admin is the event's actor
+    UserDisabled,
+    UserEnabled,
+    ForcedLogout,
+    RoleGranted,
+    RoleRevoked,
+    ImpersonationStarted,
+    ImpersonationStopped,
+}
+
+// Where a request came from. The ip is the one rate limiting uses, so X-Forwarded-For is only
+// believed when configured.
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for ClientInfo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let ip = match req.app_data::<web::Data<RateLimiter>>() {
+            Some(limiter) => limiter.client_ip(req),
+            None => req.peer_addr().map(|address| address.ip().to_string()),
+        };
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(512).collect());
+        ready(Ok(ClientInfo { ip, user_agent }))
+    }
+}
+
+pub struct AuthEvent<'a> {
+    kind: AuthEventKind,
+    user_id: Option<Uuid>,
+    actor_id: Option<Uuid>,
+    provider: Option<&'a str>,
+    client_id: Option<&'a str>,
+    detail: Option<&'a str>,
+}
+
+impl<'a> AuthEvent<'a> {
+    pub fn new(kind: AuthEventKind) -> Self {
+        AuthEvent {
+            kind,
+            user_id: None,
+            actor_id: None,
+            provider: None,
+            client_id: None,
+            detail: None,
+        }
+    }
+
+    pub fn user(mut self, user_id: Option<Uuid>) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    // the admin acting on the user
+    pub fn actor(mut self, actor_id: Uuid) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn provider(mut self, provider: Option<&'a str>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    pub fn client(mut self, client_id: Option<&'a str>) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    pub fn detail(mut self, detail: &'a str) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    pub async fn record(self, db: &YogaDatabase, client_info: &ClientInfo) {
+        tracing::info!(
+            "auth event {} user {:?} actor {:?} provider {:?} client {:?}",
+            self.kind.as_ref(),
+            self.user_id,
+            self.actor_id,
+            self.provider,
+            self.client_id
+        );
+        let inserted = db
+            .insert_auth_event(NewAuthEvent {
+                event_type: self.kind.as_ref(),
+                user_id: self.user_id,
+                actor_id: self.actor_id,
+                provider: self.provider,
+                client_id: self.client_id,
+                ip: client_info.ip.as_deref(),
+                user_agent: client_info.user_agent.as_deref(),
+                detail: self.detail,
+            })
+            .await;
+        if let Err(error) = inserted {
+            tracing::error!("failed to record auth event {}", error);
+        }
+    }
+}
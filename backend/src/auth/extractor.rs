@@ -0,0 +1,247 @@
+use actix_web::dev::Payload;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+use crate::auth::jwt::PrincipalKind;
+use crate::auth::Role;
+use crate::database::YogaDatabase;
+use crate::YogaAppData;
+
+// A user authenticated by one of our own access tokens.
+// The token is taken from `Authorization: Bearer <token>` or, as the frontend sends it,
+// the `x-auth-token` header.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub session_id: Uuid,
+    pub roles: Vec<Role>,
+    // the registered client acting for the user, None for our own frontend
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+    // the admin acting as this user, the session_id is then the impersonation's
+    pub impersonator: Option<Uuid>,
+}
+
+impl AuthenticatedUser {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+
+    pub fn require_role(&self, role: Role) -> Result<(), AuthError> {
+        if self.has_role(role) {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+
+    // The admin api is for admins themselves, not for a registered client acting for one,
+    // whatever scopes the admin granted it.
+    pub fn require_admin(&self) -> Result<(), AuthError> {
+        if self.client_id.is_some() {
+            return Err(AuthError::Forbidden);
+        }
+        self.require_role(Role::Admin)
+    }
+
+    pub fn require_any_role(&self, roles: &[Role]) -> Result<(), AuthError> {
+        if roles.iter().any(|role| self.has_role(*role)) {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+
+    // Deleting the account and handing out tokens are for the user themselves, never for an
+    // admin acting as them.
+    pub fn not_impersonated(&self) -> Result<(), AuthError> {
+        match self.impersonator {
+            Some(_) => Err(AuthError::Forbidden),
+            None => Ok(()),
+        }
+    }
+
+    // Our own frontend isn't limited by scopes, registered clients only get what was granted.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match (&self.client_id, &self.scope) {
+            (None, _) => true,
+            (Some(_), Some(granted)) => granted.split_whitespace().any(|s| s == scope),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("missing access token")]
+    MissingToken,
+    #[error("invalid access token")]
+    InvalidToken,
+    #[error("insufficient permissions")]
+    Forbidden,
+    #[error("account disabled")]
+    Disabled,
+    #[error("app data not configured")]
+    NoAppData,
+    #[error("could not check the account")]
+    Unavailable,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken | AuthError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden | AuthError::Disabled => StatusCode::FORBIDDEN,
+            AuthError::NoAppData | AuthError::Unavailable => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.body(self.to_string())
+    }
+}
+
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        if let Some(token) = value.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")) {
+            return Some(token.trim());
+        }
+    }
+    req.headers()
+        .get("x-auth-token")
+        .and_then(|value| value.to_str().ok())
+}
+
+fn authenticate(req: &HttpRequest) -> Result<Principal, AuthError> {
+    let app_data = req
+        .app_data::<web::Data<YogaAppData>>()
+        .ok_or(AuthError::NoAppData)?;
+    let token = bearer_token(req).ok_or(AuthError::MissingToken)?;
+    let claims = app_data
+        .token_issuer
+        .verify_access_token(token)
+        .map_err(|error| {
+            tracing::info!("rejected access token {}", error);
+            AuthError::InvalidToken
+        })?;
+    match claims.principal {
+        PrincipalKind::User => {
+            let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+            let impersonator = claims.impersonator().map_err(|_| AuthError::InvalidToken)?;
+            Ok(Principal::User(AuthenticatedUser {
+                user_id,
+                session_id: claims.sid.ok_or(AuthError::InvalidToken)?,
+                roles: claims.roles,
+                impersonator,
+                client_id: claims.client_id,
+                scope: claims.scope,
+            }))
+        }
+        PrincipalKind::Client => Ok(Principal::Client(AuthenticatedClient {
+            client_id: claims.client_id.ok_or(AuthError::InvalidToken)?,
+            scope: claims.scope.unwrap_or_default(),
+        })),
+    }
+}
+
+// An access token outlives a logout, a disabled account or a revoked session by up to its
+// lifetime, so the user and the session (refresh token family) are looked up on every request.
+async fn check_standing(
+    db: Option<web::Data<YogaDatabase>>,
+    user: AuthenticatedUser,
+) -> Result<AuthenticatedUser, AuthError> {
+    let db = db.ok_or(AuthError::NoAppData)?;
+    let standing = db
+        .get_user_standing(user.user_id, Some(user.session_id))
+        .await
+        .map_err(|_| AuthError::Unavailable)?;
+    match standing {
+        Some(standing) if standing.disabled => Err(AuthError::Disabled),
+        Some(standing) if standing.deleted || standing.session_revoked => Err(AuthError::InvalidToken),
+        Some(_) => Ok(user),
+        None => Err(AuthError::InvalidToken),
+    }
+}
+
+// Whoever the access token was issued to, a user or a machine client.
+// Routes that machine clients may call take a Principal and decide, everything else takes an
+// AuthenticatedUser which turns machine clients away.
+#[derive(Debug, Clone)]
+pub enum Principal {
+    User(AuthenticatedUser),
+    Client(AuthenticatedClient),
+}
+
+impl Principal {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match self {
+            Principal::User(user) => user.has_scope(scope),
+            Principal::Client(client) => client.has_scope(scope),
+        }
+    }
+}
+
+// A machine client authenticated with the client_credentials grant.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedClient {
+    pub client_id: String,
+    pub scope: String,
+}
+
+impl AuthenticatedClient {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|s| s == scope)
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Principal, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let principal = authenticate(req);
+        let db = req.app_data::<web::Data<YogaDatabase>>().cloned();
+        Box::pin(async move {
+            match principal? {
+                Principal::User(user) => Ok(Principal::User(check_standing(db, user).await?)),
+                Principal::Client(client) => Ok(Principal::Client(client)),
+            }
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<AuthenticatedUser, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let principal = authenticate(req);
+        let db = req.app_data::<web::Data<YogaDatabase>>().cloned();
+        Box::pin(async move {
+            match principal? {
+                Principal::User(user) => check_standing(db, user).await,
+                Principal::Client(_) => Err(AuthError::Forbidden),
+            }
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedClient {
+    type Error = AuthError;
+    type Future = Ready<Result<AuthenticatedClient, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(match authenticate(req) {
+            Ok(Principal::Client(client)) => Ok(client),
+            Ok(Principal::User(_)) => Err(AuthError::Forbidden),
+            Err(error) => Err(error),
+        })
+    }
+}
@@ -0,0 +1,111 @@
+// FusionAuth as an OpenID Connect provider
+// https://fusionauth.io/docs/v1/tech/oauth/endpoints
+// The id_token says who logged in, /oauth2/userinfo adds the application registration and its
+// roles. FusionAuth roles are mapped onto ours by the role_mapping in the configuration.
+// Logging out of FusionAuth is a browser redirect to /oauth2/logout, which ends the SSO session.
+
+use std::collections::{HashMap, HashSet};
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use super::oidc::{end_session_url, verify_id_token};
+use super::{OidcClient, Role, VerifyTokenError};
+
+pub struct FusionProvider {
+    pub client: OidcClient,
+    // the application id
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+    // the tenant's issuer setting, the iss of every token
+    pub issuer: String,
+    pub jwks_url: String,
+    pub userinfo_url: String,
+    pub logout_url: String,
+    // FusionAuth application role -> our role
+    pub role_mapping: HashMap<String, Role>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FusionClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct FusionUserInfo {
+    sub: String,
+    // only present when the user is registered for the application
+    #[serde(rename = "applicationId")]
+    application_id: Option<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FusionError {
+    #[error("reqwest error")]
+    ReqwestError(#[from] reqwest::Error),
+    #[error("fusionauth userinfo returned {0}")]
+    Status(reqwest::StatusCode),
+    #[error("userinfo subject doesn't match the id_token")]
+    SubjectMismatch,
+}
+
+impl FusionProvider {
+    // Applications without an id token signing key sign with the client secret (HS256),
+    // otherwise the key is in the tenant's JWKS.
+    pub async fn verify_id_token(&self, id_token: &str) -> Result<FusionClaims, VerifyTokenError> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[&self.issuer]);
+        match header.alg {
+            Algorithm::HS256 => {
+                let key = DecodingKey::from_secret(self.client_secret.expose_secret().as_bytes());
+                Ok(jsonwebtoken::decode::<FusionClaims>(id_token, &key, &validation)?.claims)
+            }
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+                verify_id_token(id_token, &self.jwks_url, &validation).await
+            }
+            _ => Err(VerifyTokenError::UnsupportedKey),
+        }
+    }
+
+    // Our roles for the user's registration with this application. A user who isn't registered
+    // gets none, they keep only what was granted to them here.
+    pub async fn fetch_roles(&self, access_token: &str, subject: &str) -> Result<Vec<Role>, FusionError> {
+        let response = reqwest::Client::new()
+            .get(&self.userinfo_url)
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(FusionError::Status(response.status()));
+        }
+        let userinfo = response.json::<FusionUserInfo>().await?;
+        // OpenID Connect Core 5.3.2, the sub must be the one of the id_token
+        if userinfo.sub != subject {
+            return Err(FusionError::SubjectMismatch);
+        }
+        if userinfo.application_id.as_deref() != Some(self.client_id.as_str()) {
+            tracing::info!("fusionauth user {} is not registered for the application", subject);
+            return Ok(Vec::new());
+        }
+        // several FusionAuth roles can map onto the same role of ours
+        let roles: HashSet<Role> = userinfo
+            .roles
+            .iter()
+            .filter_map(|role| self.role_mapping.get(role).copied())
+            .collect();
+        Ok(roles.into_iter().collect())
+    }
+
+    // Where to send the browser to end the FusionAuth session as well as ours.
+    pub fn logout_redirect(&self, id_token: Option<&str>, post_logout_redirect_uri: &str) -> String {
+        end_session_url(&self.logout_url, &self.client_id, id_token, post_logout_redirect_uri)
+    }
+}
@@ -0,0 +1,341 @@
+// GitHub OAuth apps
+// https://docs.github.com/en/apps/oauth-apps/building-oauth-apps/authorizing-oauth-apps
+// GitHub is not OpenID Connect, there is no id_token. Who logged in comes from the REST api,
+// /user for the numeric id and /user/emails for the primary verified email.
+// All urls come from configuration so a local mock of these endpoints can stand in for GitHub.
+
+use std::collections::HashMap;
+
+use oauth2::basic::BasicClient;
+use oauth2::http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+use oauth2::{HttpRequest, HttpResponse};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+const USER_AGENT: &str = "yogamat";
+const GITHUB_ACCEPT: &str = "application/vnd.github+json";
+
+pub struct GitHubProvider {
+    pub client: BasicClient,
+    // https://api.github.com or the mock
+    pub api_url: String,
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GitHubIdentity {
+    // the numeric user id, logins can be renamed
+    pub id: u64,
+    pub login: String,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: u64,
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GitHubError {
+    #[error("reqwest error")]
+    ReqwestError(#[from] reqwest::Error),
+    #[error("github account has no verified primary email")]
+    NoVerifiedEmail,
+    #[error("github api returned {0}")]
+    Status(reqwest::StatusCode),
+}
+
+// GitHub's token endpoint answers errors with 200 OK, and with a form encoded body unless it
+// honours the Accept header. The oauth2 crate expects RFC 6749, json with a 400 for errors,
+// so the response is rewritten before the crate parses it.
+pub async fn github_http_client(
+    request: HttpRequest,
+) -> Result<HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+    let mut response = oauth2::reqwest::async_http_client(request).await?;
+
+    let form_encoded = response
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("application/x-www-form-urlencoded"))
+        .unwrap_or(false);
+    if form_encoded {
+        let mut fields = serde_json::Map::new();
+        for (key, value) in url::form_urlencoded::parse(&response.body) {
+            // numbers come back as strings in a form
+            let value = match value.parse::<u64>() {
+                Ok(number) if key.ends_with("expires_in") => serde_json::Value::from(number),
+                _ => serde_json::Value::from(value.into_owned()),
+            };
+            fields.insert(key.into_owned(), value);
+        }
+        if let Ok(body) = serde_json::to_vec(&fields) {
+            response.body = body;
+            response
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
+    }
+
+    if response.status_code == StatusCode::OK {
+        let is_error = serde_json::from_slice::<HashMap<String, serde_json::Value>>(&response.body)
+            .map(|fields| fields.contains_key("error"))
+            .unwrap_or(false);
+        if is_error {
+            response.status_code = StatusCode::BAD_REQUEST;
+        }
+    }
+    Ok(response)
+}
+
+impl GitHubProvider {
+    pub async fn fetch_identity(&self, access_token: &str) -> Result<GitHubIdentity, GitHubError> {
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}/user", self.api_url))
+            .bearer_auth(access_token)
+            .header(reqwest::header::ACCEPT, GITHUB_ACCEPT)
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(GitHubError::Status(response.status()));
+        }
+        let user = response.json::<GitHubUser>().await?;
+
+        let response = client
+            .get(format!("{}/user/emails", self.api_url))
+            .bearer_auth(access_token)
+            .header(reqwest::header::ACCEPT, GITHUB_ACCEPT)
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(GitHubError::Status(response.status()));
+        }
+        let emails = response.json::<Vec<GitHubEmail>>().await?;
+        let email = emails
+            .into_iter()
+            .find(|email| email.primary && email.verified)
+            .ok_or(GitHubError::NoVerifiedEmail)?;
+
+        Ok(GitHubIdentity {
+            id: user.id,
+            login: user.login,
+            email: email.email,
+        })
+    }
+
+    // GitHub has no RFC 7009 revocation endpoint. Deleting the app's grant revokes every token
+    // the user gave us, the user has to authorize the app again on the next login.
+    // https://docs.github.com/en/rest/apps/oauth-applications#delete-an-app-authorization
+    pub async fn revoke_grant(&self, access_token: &str) -> Result<(), GitHubError> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/applications/{}/grant", self.api_url, self.client_id))
+            .basic_auth(&self.client_id, Some(self.client_secret.expose_secret()))
+            .header(reqwest::header::ACCEPT, GITHUB_ACCEPT)
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .json(&serde_json::json!({ "access_token": access_token }))
+            .send()
+            .await?;
+        // 204 when deleted, 404 when the grant was already gone
+        match response.status() {
+            status if status.is_success() || status == reqwest::StatusCode::NOT_FOUND => Ok(()),
+            status => Err(GitHubError::Status(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse as Response, HttpServer};
+    use oauth2::basic::BasicErrorResponseType;
+    use oauth2::{
+        AuthUrl, AuthorizationCode, ClientId, ClientSecret, RequestTokenError, TokenResponse,
+        TokenUrl,
+    };
+
+    const CLIENT_ID: &str = "mock-client";
+    const CLIENT_SECRET: &str = "mock-secret";
+
+    fn bearer(request: &HttpRequest) -> Option<&str> {
+        request
+            .headers()
+            .get("authorization")?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")
+    }
+
+    // "good" is a user with a verified primary email, "unverified" one without
+    async fn user(request: HttpRequest) -> Response {
+        match bearer(&request) {
+            Some("good") | Some("unverified") => {
+                Response::Ok().json(serde_json::json!({ "id": 583231, "login": "octocat" }))
+            }
+            _ => Response::Unauthorized().finish(),
+        }
+    }
+
+    async fn emails(request: HttpRequest) -> Response {
+        let verified = match bearer(&request) {
+            Some("good") => true,
+            Some("unverified") => false,
+            _ => return Response::Unauthorized().finish(),
+        };
+        Response::Ok().json(serde_json::json!([
+            { "email": "old@example.com", "primary": false, "verified": true },
+            { "email": "octocat@example.com", "primary": true, "verified": verified },
+        ]))
+    }
+
+    // GitHub's token endpoint, form encoded and 200 OK even for errors
+    async fn access_token(form: web::Form<HashMap<String, String>>) -> Response {
+        let body = match form.get("code").map(String::as_str) {
+            Some("good") => "access_token=gho_good&scope=user%3Aemail&token_type=bearer",
+            _ => {
+                "error=bad_verification_code&error_description=The+code+passed+is+incorrect+or+\
+                expired.&error_uri=https%3A%2F%2Fdocs.github.com"
+            }
+        };
+        Response::Ok()
+            .content_type("application/x-www-form-urlencoded; charset=utf-8")
+            .body(body)
+    }
+
+    async fn delete_grant(
+        request: HttpRequest,
+        path: web::Path<String>,
+        body: web::Json<HashMap<String, String>>,
+    ) -> Response {
+        let credentials = format!(
+            "Basic {}",
+            base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                format!("{}:{}", CLIENT_ID, CLIENT_SECRET)
+            )
+        );
+        let authorized = request
+            .headers()
+            .get("authorization")
+            .map(|value| value.as_bytes() == credentials.as_bytes())
+            .unwrap_or(false);
+        if path.as_str() != CLIENT_ID || !authorized {
+            return Response::Unauthorized().finish();
+        }
+        match body.get("access_token").map(String::as_str) {
+            Some("good") => Response::NoContent().finish(),
+            _ => Response::NotFound().finish(),
+        }
+    }
+
+    // the endpoints GitHub's api and token url serve, on a port of its own per test
+    fn mock_github() -> String {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/user", web::get().to(user))
+                .route("/user/emails", web::get().to(emails))
+                .route("/login/oauth/access_token", web::post().to(access_token))
+                .route("/applications/{client_id}/grant", web::delete().to(delete_grant))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    fn provider(url: &str, client_secret: &str) -> GitHubProvider {
+        let client = BasicClient::new(
+            ClientId::new(CLIENT_ID.to_string()),
+            Some(ClientSecret::new(client_secret.to_string())),
+            AuthUrl::new(format!("{}/login/oauth/authorize", url)).unwrap(),
+            Some(TokenUrl::new(format!("{}/login/oauth/access_token", url)).unwrap()),
+        );
+        GitHubProvider {
+            client,
+            api_url: url.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Secret::new(client_secret.to_string()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn identity_has_the_primary_verified_email() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        let identity = github.fetch_identity("good").await.unwrap();
+        assert_eq!(identity.id, 583231);
+        assert_eq!(identity.login, "octocat");
+        assert_eq!(identity.email, "octocat@example.com");
+    }
+
+    #[actix_web::test]
+    async fn identity_without_verified_primary_email_is_refused() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        let error = github.fetch_identity("unverified").await.unwrap_err();
+        assert!(matches!(error, GitHubError::NoVerifiedEmail));
+    }
+
+    #[actix_web::test]
+    async fn identity_with_a_bad_token_is_the_api_status() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        let error = github.fetch_identity("expired").await.unwrap_err();
+        assert!(matches!(error, GitHubError::Status(status) if status == 401));
+    }
+
+    #[actix_web::test]
+    async fn form_encoded_token_is_read() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        let token = github
+            .client
+            .exchange_code(AuthorizationCode::new("good".to_string()))
+            .request_async(github_http_client)
+            .await
+            .unwrap();
+        assert_eq!(token.access_token().secret(), "gho_good");
+    }
+
+    #[actix_web::test]
+    async fn form_encoded_error_with_200_is_an_error() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        let result = github
+            .client
+            .exchange_code(AuthorizationCode::new("stale".to_string()))
+            .request_async(github_http_client)
+            .await;
+        match result {
+            Err(RequestTokenError::ServerResponse(error)) => assert_eq!(
+                error.error(),
+                &BasicErrorResponseType::Extension("bad_verification_code".to_string())
+            ),
+            other => panic!("expected the server's error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[actix_web::test]
+    async fn grant_is_deleted_with_the_client_credentials() {
+        let github = provider(&mock_github(), CLIENT_SECRET);
+        github.revoke_grant("good").await.unwrap();
+        // already gone is as good as deleted
+        github.revoke_grant("gone").await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn grant_deletion_with_wrong_credentials_fails() {
+        let github = provider(&mock_github(), "wrong-secret");
+        let error = github.revoke_grant("good").await.unwrap_err();
+        assert!(matches!(error, GitHubError::Status(status) if status == 401));
+    }
+}
@@ -0,0 +1,326 @@
+// Access tokens minted by this backend.
+// The cookie handed to the frontend used to be the provider's (Google/FusionAuth) access token,
+// now it is a short lived JWT signed with our own key carrying our user id, roles and session id.
+// The public half of the key is published as a JWKS at /.well-known/jwks.json so other
+// services can verify the tokens without asking us.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use openssl::pkey::PKey;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::Role;
+use crate::configuration::JwtSettings;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PrincipalKind {
+    #[default]
+    User,
+    // a machine client using the client_credentials grant, there is no user
+    Client,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessClaims {
+    pub iss: String,
+    // our user_profile.user_id, or the client_id for machine clients
+    pub sub: String,
+    pub iat: usize,
+    pub exp: usize,
+    #[serde(default)]
+    pub principal: PrincipalKind,
+    // session id, shared by every token issued for one login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    // set when the token was issued to one of our registered clients rather than our frontend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // RFC 8693 actor, the admin impersonating the user in sub
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Actor {
+    pub sub: String,
+}
+
+// OpenID Connect id_token for our registered clients
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub iat: usize,
+    pub exp: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+impl AccessClaims {
+    pub fn user_id(&self) -> Result<Uuid, TokenError> {
+        Uuid::parse_str(&self.sub).map_err(|_| TokenError::InvalidSubject)
+    }
+
+    pub fn impersonator(&self) -> Result<Option<Uuid>, TokenError> {
+        match &self.act {
+            Some(actor) => Uuid::parse_str(&actor.sub)
+                .map(Some)
+                .map_err(|_| TokenError::InvalidSubject),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub alg: String,
+    pub kid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenError {
+    #[error("unsupported jwt algorithm {0}, use EdDSA or RS256")]
+    UnsupportedAlgorithm(String),
+    #[error("couldn't read jwt key file")]
+    KeyFile(#[from] std::io::Error),
+    #[error("openssl error")]
+    OpenSsl(#[from] openssl::error::ErrorStack),
+    #[error("jsonwebtoken error")]
+    JsonwebToken(#[from] jsonwebtoken::errors::Error),
+    #[error("token subject is not a user id")]
+    InvalidSubject,
+}
+
+pub struct TokenIssuer {
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    key_id: String,
+    issuer: String,
+    access_token_ttl: usize,
+    refresh_token_ttl_days: i64,
+    jwks: JwkSet,
+}
+
+impl TokenIssuer {
+    pub fn new(settings: &JwtSettings) -> Result<Self, TokenError> {
+        let private_pem = std::fs::read(&settings.private_key_path)?;
+        let public_pem = std::fs::read(&settings.public_key_path)?;
+        let public_key = PKey::public_key_from_pem(&public_pem)?;
+
+        let (algorithm, encoding_key, decoding_key, jwk) = match settings.algorithm.as_str() {
+            "EdDSA" => {
+                let jwk = Jwk {
+                    kty: "OKP".to_string(),
+                    key_use: "sig".to_string(),
+                    alg: "EdDSA".to_string(),
+                    kid: settings.key_id.clone(),
+                    n: None,
+                    e: None,
+                    crv: Some("Ed25519".to_string()),
+                    x: Some(URL_SAFE_NO_PAD.encode(public_key.raw_public_key()?)),
+                };
+                (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(&private_pem)?,
+                    DecodingKey::from_ed_pem(&public_pem)?,
+                    jwk,
+                )
+            }
+            "RS256" => {
+                let rsa = public_key.rsa()?;
+                let jwk = Jwk {
+                    kty: "RSA".to_string(),
+                    key_use: "sig".to_string(),
+                    alg: "RS256".to_string(),
+                    kid: settings.key_id.clone(),
+                    n: Some(URL_SAFE_NO_PAD.encode(rsa.n().to_vec())),
+                    e: Some(URL_SAFE_NO_PAD.encode(rsa.e().to_vec())),
+                    crv: None,
+                    x: None,
+                };
+                (
+                    Algorithm::RS256,
+                    EncodingKey::from_rsa_pem(&private_pem)?,
+                    DecodingKey::from_rsa_pem(&public_pem)?,
+                    jwk,
+                )
+            }
+            other => return Err(TokenError::UnsupportedAlgorithm(other.to_string())),
+        };
+
+        Ok(Self {
+            algorithm,
+            encoding_key,
+            decoding_key,
+            key_id: settings.key_id.clone(),
+            issuer: settings.issuer.clone(),
+            access_token_ttl: settings.access_token_ttl_minutes as usize * 60,
+            refresh_token_ttl_days: settings.refresh_token_ttl_days,
+            jwks: JwkSet { keys: vec![jwk] },
+        })
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    // as it appears in the alg header, for the discovery document
+    pub fn algorithm_name(&self) -> &'static str {
+        match self.algorithm {
+            Algorithm::RS256 => "RS256",
+            _ => "EdDSA",
+        }
+    }
+
+    // seconds, for the expires_in of token responses and the cookie lifetime
+    pub fn access_token_ttl(&self) -> usize {
+        self.access_token_ttl
+    }
+
+    pub fn refresh_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::days(self.refresh_token_ttl_days)
+    }
+
+    pub fn issue_access_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        roles: Vec<Role>,
+    ) -> Result<String, TokenError> {
+        self.issue_delegated_access_token(user_id, session_id, roles, None, None)
+    }
+
+    // A user's token held by one of our registered clients, limited to the granted scope.
+    pub fn issue_delegated_access_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        roles: Vec<Role>,
+        client_id: Option<&str>,
+        scope: Option<&str>,
+    ) -> Result<String, TokenError> {
+        let now = jsonwebtoken::get_current_timestamp() as usize;
+        let claims = AccessClaims {
+            iss: self.issuer.clone(),
+            sub: user_id.to_string(),
+            iat: now,
+            exp: now + self.access_token_ttl,
+            principal: PrincipalKind::User,
+            sid: Some(session_id),
+            roles,
+            client_id: client_id.map(str::to_string),
+            scope: scope.map(str::to_string),
+            act: None,
+        };
+        self.sign(&claims)
+    }
+
+    // An admin acting as the user, the session is the impersonation and ends at `expires_at`
+    // (seconds since the epoch), there is no refresh.
+    pub fn issue_impersonation_token(
+        &self,
+        user_id: Uuid,
+        impersonation_id: Uuid,
+        roles: Vec<Role>,
+        admin_id: Uuid,
+        expires_at: usize,
+    ) -> Result<String, TokenError> {
+        let claims = AccessClaims {
+            iss: self.issuer.clone(),
+            sub: user_id.to_string(),
+            iat: jsonwebtoken::get_current_timestamp() as usize,
+            exp: expires_at,
+            principal: PrincipalKind::User,
+            sid: Some(impersonation_id),
+            roles,
+            client_id: None,
+            scope: None,
+            act: Some(Actor { sub: admin_id.to_string() }),
+        };
+        self.sign(&claims)
+    }
+
+    // client_credentials grant, the client acts for itself
+    pub fn issue_client_access_token(&self, client_id: &str, scope: &str) -> Result<String, TokenError> {
+        let now = jsonwebtoken::get_current_timestamp() as usize;
+        let claims = AccessClaims {
+            iss: self.issuer.clone(),
+            sub: client_id.to_string(),
+            iat: now,
+            exp: now + self.access_token_ttl,
+            principal: PrincipalKind::Client,
+            sid: None,
+            roles: Vec::new(),
+            client_id: Some(client_id.to_string()),
+            scope: Some(scope.to_string()),
+            act: None,
+        };
+        self.sign(&claims)
+    }
+
+    pub fn issue_id_token(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        nonce: Option<String>,
+        email: Option<String>,
+    ) -> Result<String, TokenError> {
+        let now = jsonwebtoken::get_current_timestamp() as usize;
+        let claims = IdClaims {
+            iss: self.issuer.clone(),
+            sub: user_id.to_string(),
+            aud: client_id.to_string(),
+            iat: now,
+            exp: now + self.access_token_ttl,
+            nonce,
+            email_verified: email.as_ref().map(|_| true),
+            email,
+        };
+        self.sign(&claims)
+    }
+
+    pub fn verify_access_token(&self, token: &str) -> Result<AccessClaims, TokenError> {
+        let mut validation = Validation::new(self.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        let token = jsonwebtoken::decode::<AccessClaims>(token, &self.decoding_key, &validation)?;
+        Ok(token.claims)
+    }
+
+    fn sign<T: Serialize>(&self, claims: &T) -> Result<String, TokenError> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.key_id.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &self.encoding_key)?)
+    }
+}
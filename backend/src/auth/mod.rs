@@ -1,37 +1,53 @@
-use oauth2::{ExtraTokenFields, Client, basic::{BasicErrorResponse, BasicTokenType, BasicTokenIntrospectionResponse, BasicRevocationErrorResponse, BasicClient}, StandardRevocableToken, StandardTokenResponse};
+pub mod apple;
+pub mod audit;
+pub mod extractor;
+pub mod fusion;
+pub mod github;
+pub mod jwt;
+pub mod oidc;
+pub mod opaque_token;
+pub mod providers;
+
+use oauth2::{ExtraTokenFields, Client, basic::{BasicErrorResponse, BasicTokenType, BasicTokenIntrospectionResponse, BasicRevocationErrorResponse}, StandardRevocableToken, StandardTokenResponse};
 use serde::{Deserialize, Serialize};
 
+// OpenID Connect providers answer the token request with an id_token next to the access token
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct GoogleAuth {
+pub struct IdTokenFields {
     pub id_token: String,
 }
 
-impl ExtraTokenFields for GoogleAuth {}
+impl ExtraTokenFields for IdTokenFields {}
 
-pub type GoogleClient = Client<
+pub type OidcClient = Client<
     BasicErrorResponse,
-    GoogleTokenResponse,
+    OidcTokenResponse,
     BasicTokenType,
     BasicTokenIntrospectionResponse,
     StandardRevocableToken,
     BasicRevocationErrorResponse,
 >;
 
-pub type GoogleTokenResponse = StandardTokenResponse<GoogleAuth, BasicTokenType>;
+pub type OidcTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
 
 pub enum AuthClientType {
-    Google(GoogleClient),
-    Basic(BasicClient),
+    Google(OidcClient),
+    GitHub(github::GitHubProvider),
+    Fusion(fusion::FusionProvider),
+    Oidc(oidc::OidcProvider),
+    // boxed, the signing key makes it much bigger than the others
+    Apple(Box<apple::AppleProvider>),
 }
 
-#[derive(strum_macros::EnumString, Eq, Hash, PartialEq, Debug, Serialize, Deserialize)]
-pub enum AuthName {
-    #[strum(serialize="google")]
-    Google,
-    #[strum(serialize="github")]
-    GitHub,
-    #[strum(serialize="fusion")]
-    Fusion,
+#[derive(strum_macros::EnumString, strum_macros::AsRefStr, Clone, Copy, Eq, Hash, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[strum(serialize="user")]
+    User,
+    #[strum(serialize="editor")]
+    Editor,
+    #[strum(serialize="admin")]
+    Admin,
 }
 
 #[derive(Deserialize, Debug)]
@@ -58,6 +74,10 @@ pub enum VerifyTokenError {
     NoKid,
     #[error("id-token header kid not found in jwks")]
     KidNotFound,
+    #[error("jwks key type is not supported")]
+    UnsupportedKey,
+    #[error("id_token issuer doesn't match")]
+    IssuerMismatch,
     #[error("jsonwebtoken error")]
     JsonwebTokenError(#[from] jsonwebtoken::errors::Error),
 }
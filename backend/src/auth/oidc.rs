@@ -0,0 +1,180 @@
+// Verifying the id_token of an OpenID Connect provider
+// https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation
+// The provider signs id_tokens with one of the keys published at its jwks_uri, the kid in the
+// token header says which one. The caller's Validation decides the accepted algorithms, the
+// issuer and the audience, the header is never trusted for that.
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use url::Url;
+
+use super::{OidcClient, VerifyTokenError};
+use crate::configuration::ProviderKind;
+
+// Microsoft's multi-tenant endpoints publish this issuer, the tenant is the tid of the token
+const TENANT_PLACEHOLDER: &str = "{tenantid}";
+// personal Microsoft accounts, Microsoft verified their email addresses
+const MICROSOFT_CONSUMERS_TENANT: &str = "9188040d-6c67-4c5b-b112-36a304b66dad";
+
+// Microsoft, GitLab, Apple and any provider configured as kind oidc
+pub struct OidcProvider {
+    pub client: OidcClient,
+    pub kind: ProviderKind,
+    pub client_id: String,
+    pub issuer: String,
+    pub jwks_url: String,
+    pub scopes: Vec<String>,
+    // end_session_endpoint, the provider's session is left alone without one
+    pub logout_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OidcClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "bool_or_string")]
+    pub email_verified: bool,
+    // Microsoft, the user's tenant and whether the tenant verified the email domain
+    pub tid: Option<String>,
+    #[serde(default, deserialize_with = "bool_or_string")]
+    pub xms_edov: bool,
+}
+
+// Apple sends booleans as "true" and "false"
+fn bool_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+    Ok(match BoolOrString::deserialize(deserializer)? {
+        BoolOrString::Bool(value) => value,
+        BoolOrString::String(value) => value == "true",
+    })
+}
+
+#[derive(Deserialize)]
+struct JwksDocument {
+    keys: Vec<JwksKey>,
+}
+
+// only the fields we need, providers add x5c, x5t, use, ...
+#[derive(Deserialize)]
+struct JwksKey {
+    kid: Option<String>,
+    kty: String,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+pub async fn verify_id_token<C: DeserializeOwned>(
+    id_token: &str,
+    jwks_url: &str,
+    validation: &Validation,
+) -> Result<C, VerifyTokenError> {
+    let header = jsonwebtoken::decode_header(id_token)?;
+    let token_kid = match header.kid {
+        Some(kid) => kid,
+        None => {
+            tracing::error!("id_token has no kid in header");
+            return Err(VerifyTokenError::NoKid);
+        }
+    };
+
+    let jwks = reqwest::get(jwks_url).await?.json::<JwksDocument>().await?;
+    let key = jwks
+        .keys
+        .iter()
+        .find(|key| key.kid.as_deref() == Some(token_kid.as_str()))
+        .ok_or(VerifyTokenError::KidNotFound)?;
+    let decoding_key = match (key.kty.as_str(), &key.n, &key.e) {
+        ("RSA", Some(modulus), Some(exponent)) => DecodingKey::from_rsa_components(modulus, exponent)?,
+        _ => return Err(VerifyTokenError::UnsupportedKey),
+    };
+
+    match jsonwebtoken::decode::<C>(id_token, &decoding_key, validation) {
+        Ok(token) => Ok(token.claims),
+        Err(err) => {
+            tracing::error!("jsonwebtoken error {}", err);
+            Err(VerifyTokenError::JsonwebTokenError(err))
+        }
+    }
+}
+
+impl OidcProvider {
+    pub async fn verify_id_token(&self, id_token: &str) -> Result<OidcClaims, VerifyTokenError> {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[&self.client_id]);
+        let per_tenant = self.issuer.contains(TENANT_PLACEHOLDER);
+        if !per_tenant {
+            validation.set_issuer(&[&self.issuer]);
+        }
+        let claims: OidcClaims = verify_id_token(id_token, &self.jwks_url, &validation).await?;
+        if per_tenant {
+            let expected = claims
+                .tid
+                .as_deref()
+                .map(|tid| self.issuer.replace(TENANT_PLACEHOLDER, tid));
+            if expected.as_deref() != Some(claims.iss.as_str()) {
+                tracing::error!("id_token issuer {} doesn't match its tenant", claims.iss);
+                return Err(VerifyTokenError::IssuerMismatch);
+            }
+        }
+        Ok(claims)
+    }
+
+    // The email if the provider vouches for it, only then can it link to an existing user.
+    // Entra ID tenant admins can put any email on an account, it only counts when the tenant
+    // verified the domain (xms_edov) or the account is a personal one.
+    pub fn verified_email(&self, claims: &OidcClaims) -> Option<String> {
+        let verified = match self.kind {
+            ProviderKind::Microsoft => {
+                claims.email_verified
+                    || claims.xms_edov
+                    || claims.tid.as_deref() == Some(MICROSOFT_CONSUMERS_TENANT)
+            }
+            _ => claims.email_verified,
+        };
+        if verified {
+            claims.email.clone()
+        } else {
+            None
+        }
+    }
+
+    pub fn logout_redirect(&self, id_token: Option<&str>, post_logout_redirect_uri: &str) -> Option<String> {
+        self.logout_url.as_ref().map(|logout_url| {
+            end_session_url(logout_url, &self.client_id, id_token, post_logout_redirect_uri)
+        })
+    }
+}
+
+// OpenID Connect RP-Initiated Logout, the browser goes to the provider to end its session and
+// comes back to post_logout_redirect_uri.
+pub fn end_session_url(
+    logout_url: &str,
+    client_id: &str,
+    id_token: Option<&str>,
+    post_logout_redirect_uri: &str,
+) -> String {
+    match Url::parse(logout_url) {
+        Ok(mut url) => {
+            {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("client_id", client_id);
+                query.append_pair("post_logout_redirect_uri", post_logout_redirect_uri);
+                if let Some(id_token) = id_token {
+                    query.append_pair("id_token_hint", id_token);
+                }
+            }
+            url.into()
+        }
+        Err(error) => {
+            tracing::error!("invalid logout_url {}", error);
+            post_logout_redirect_uri.to_string()
+        }
+    }
+}
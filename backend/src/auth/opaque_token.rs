@@ -0,0 +1,15 @@
+// Random tokens that mean nothing by themselves (refresh tokens, codes, secrets).
+// Only a hash is stored in the database so a leaked table doesn't leak usable tokens.
+// They are 256 bits of randomness, a fast hash is enough, unlike passwords.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+pub fn new_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("openssl rand_bytes failed");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn hash_opaque_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(openssl::sha::sha256(token.as_bytes()))
+}
@@ -0,0 +1,325 @@
+// Builds the login providers from the oauth_providers in configuration.
+// A provider is only enabled when its client id (and secret, except Apple) is in the environment
+// as <NAME>_CLIENT_ID and <NAME>_CLIENT_SECRET, the name upper cased with - replaced by _.
+
+use std::collections::HashMap;
+
+use jsonwebtoken::EncodingKey;
+use oauth2::basic::BasicClient;
+use oauth2::{AuthUrl, ClientId, ClientSecret, IntrospectionUrl, RedirectUrl, RevocationUrl, TokenUrl};
+use secrecy::Secret;
+
+use super::apple::{AppleProvider, APPLE_ISSUER};
+use super::fusion::FusionProvider;
+use super::github::GitHubProvider;
+use super::oidc::OidcProvider;
+use super::{AuthClientType, OidcClient, Role};
+use crate::configuration::{ApplicationSettings, OAuthProvider, ProviderKind};
+
+const MICROSOFT_LOGIN: &str = "https://login.microsoftonline.com";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProviderSetupError {
+    #[error("{0} is not configured")]
+    Missing(&'static str),
+    #[error("invalid url")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("couldn't read the key file")]
+    KeyFile(#[from] std::io::Error),
+    #[error("invalid key")]
+    InvalidKey(#[from] jsonwebtoken::errors::Error),
+    #[error("{0} doesn't use a client secret")]
+    NoClientSecret(&'static str),
+}
+
+// The endpoints of a provider, configured or the provider's well known ones.
+struct Endpoints {
+    oauth_url: String,
+    token_url: String,
+    issuer: Option<String>,
+    jwks_url: Option<String>,
+    logout_url: Option<String>,
+}
+
+fn endpoints(provider: &OAuthProvider) -> Result<Endpoints, ProviderSetupError> {
+    let defaults = match provider.kind {
+        ProviderKind::Google => Endpoints {
+            oauth_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            issuer: None,
+            jwks_url: None,
+            logout_url: None,
+        },
+        ProviderKind::Microsoft => {
+            let tenant = provider.tenant.as_deref().unwrap_or("common");
+            // the shared endpoints sign tokens with the issuer of the user's own tenant
+            let issuer_tenant = match tenant {
+                "common" | "organizations" | "consumers" => "{tenantid}",
+                tenant => tenant,
+            };
+            Endpoints {
+                oauth_url: format!("{}/{}/oauth2/v2.0/authorize", MICROSOFT_LOGIN, tenant),
+                token_url: format!("{}/{}/oauth2/v2.0/token", MICROSOFT_LOGIN, tenant),
+                issuer: Some(format!("{}/{}/v2.0", MICROSOFT_LOGIN, issuer_tenant)),
+                jwks_url: Some(format!("{}/{}/discovery/v2.0/keys", MICROSOFT_LOGIN, tenant)),
+                logout_url: Some(format!("{}/{}/oauth2/v2.0/logout", MICROSOFT_LOGIN, tenant)),
+            }
+        }
+        ProviderKind::GitLab => {
+            let base_url = provider
+                .base_url
+                .as_deref()
+                .unwrap_or("https://gitlab.com")
+                .trim_end_matches('/');
+            Endpoints {
+                oauth_url: format!("{}/oauth/authorize", base_url),
+                token_url: format!("{}/oauth/token", base_url),
+                issuer: Some(base_url.to_string()),
+                jwks_url: Some(format!("{}/oauth/discovery/keys", base_url)),
+                logout_url: None,
+            }
+        }
+        ProviderKind::Apple => Endpoints {
+            oauth_url: format!("{}/auth/authorize", APPLE_ISSUER),
+            token_url: format!("{}/auth/token", APPLE_ISSUER),
+            issuer: Some(APPLE_ISSUER.to_string()),
+            jwks_url: Some(format!("{}/auth/keys", APPLE_ISSUER)),
+            logout_url: None,
+        },
+        ProviderKind::GitHub | ProviderKind::Fusion | ProviderKind::Oidc => Endpoints {
+            oauth_url: provider.oauth_url.clone().ok_or(ProviderSetupError::Missing("oauth_url"))?,
+            token_url: provider.token_url.clone().ok_or(ProviderSetupError::Missing("token_url"))?,
+            issuer: None,
+            jwks_url: None,
+            logout_url: None,
+        },
+    };
+    // anything in configuration wins
+    Ok(Endpoints {
+        oauth_url: provider.oauth_url.clone().unwrap_or(defaults.oauth_url),
+        token_url: provider.token_url.clone().unwrap_or(defaults.token_url),
+        issuer: provider.issuer.clone().or(defaults.issuer),
+        jwks_url: provider.jwks_url.clone().or(defaults.jwks_url),
+        logout_url: provider.logout_url.clone().or(defaults.logout_url),
+    })
+}
+
+fn client_credential(provider: &OAuthProvider, suffix: &str) -> Option<String> {
+    let key = format!(
+        "{}_{}",
+        provider.name.to_uppercase().replace('-', "_"),
+        suffix
+    );
+    match std::env::var(&key) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            tracing::error!("couldn't get {} from environment", key);
+            None
+        }
+    }
+}
+
+fn oidc_client(
+    application: &ApplicationSettings,
+    endpoints: &Endpoints,
+    client_id: &str,
+    client_secret: Option<&str>,
+) -> Result<OidcClient, ProviderSetupError> {
+    Ok(OidcClient::new(
+        ClientId::new(client_id.to_string()),
+        client_secret.map(|secret| ClientSecret::new(secret.to_string())),
+        AuthUrl::new(endpoints.oauth_url.clone())?,
+        Some(TokenUrl::new(endpoints.token_url.clone())?),
+    )
+    .set_redirect_uri(RedirectUrl::new(application.oauth_redirect_url.clone())?))
+}
+
+fn oidc_provider(
+    application: &ApplicationSettings,
+    provider: &OAuthProvider,
+    endpoints: &Endpoints,
+    client_id: &str,
+    client_secret: Option<&str>,
+    default_scopes: &[&str],
+) -> Result<OidcProvider, ProviderSetupError> {
+    let scopes = if provider.scopes.is_empty() {
+        default_scopes.iter().map(|scope| scope.to_string()).collect()
+    } else {
+        provider.scopes.clone()
+    };
+    Ok(OidcProvider {
+        client: oidc_client(application, endpoints, client_id, client_secret)?,
+        kind: provider.kind,
+        client_id: client_id.to_string(),
+        issuer: endpoints.issuer.clone().ok_or(ProviderSetupError::Missing("issuer"))?,
+        jwks_url: endpoints.jwks_url.clone().ok_or(ProviderSetupError::Missing("jwks_url"))?,
+        scopes,
+        logout_url: endpoints.logout_url.clone(),
+    })
+}
+
+fn setup_provider(
+    application: &ApplicationSettings,
+    provider: &OAuthProvider,
+) -> Result<Option<AuthClientType>, ProviderSetupError> {
+    let client_id = match client_credential(provider, "CLIENT_ID") {
+        Some(client_id) => client_id,
+        None => return Ok(None),
+    };
+    let endpoints = endpoints(provider)?;
+
+    // Apple has no static secret
+    if provider.kind == ProviderKind::Apple {
+        let team_id = provider.team_id.clone().ok_or(ProviderSetupError::Missing("team_id"))?;
+        let key_id = provider.key_id.clone().ok_or(ProviderSetupError::Missing("key_id"))?;
+        let key_path = provider
+            .private_key_path
+            .as_ref()
+            .ok_or(ProviderSetupError::Missing("private_key_path"))?;
+        let signing_key = EncodingKey::from_ec_pem(&std::fs::read(key_path)?)?;
+        let oidc = oidc_provider(application, provider, &endpoints, &client_id, None, &["name", "email"])?;
+        return Ok(Some(AuthClientType::Apple(Box::new(AppleProvider {
+            oidc,
+            team_id,
+            key_id,
+            signing_key,
+            auth_url: AuthUrl::new(endpoints.oauth_url.clone())?,
+            token_url: TokenUrl::new(endpoints.token_url.clone())?,
+            redirect_url: RedirectUrl::new(application.oauth_redirect_url.clone())?,
+        }))));
+    }
+
+    let client_secret = match client_credential(provider, "CLIENT_SECRET") {
+        Some(client_secret) => client_secret,
+        None => return Ok(None),
+    };
+    let client = match provider.kind {
+        ProviderKind::Google => {
+            let mut google_client = oidc_client(application, &endpoints, &client_id, Some(&client_secret))?;
+            if let Some(revoke_url) = &provider.revoke_url {
+                google_client = google_client.set_revocation_uri(RevocationUrl::new(revoke_url.clone())?);
+            }
+            if let Some(introspection_url) = &provider.introspection_url {
+                google_client = google_client.set_introspection_uri(IntrospectionUrl::new(introspection_url.clone())?);
+            }
+            AuthClientType::Google(google_client)
+        }
+        ProviderKind::GitHub => {
+            let client = BasicClient::new(
+                ClientId::new(client_id.clone()),
+                Some(ClientSecret::new(client_secret.clone())),
+                AuthUrl::new(endpoints.oauth_url.clone())?,
+                Some(TokenUrl::new(endpoints.token_url.clone())?),
+            )
+            .set_redirect_uri(RedirectUrl::new(application.oauth_redirect_url.clone())?);
+            let api_url = provider
+                .api_url
+                .clone()
+                .unwrap_or_else(|| "https://api.github.com".to_string());
+            AuthClientType::GitHub(GitHubProvider {
+                client,
+                api_url,
+                client_id,
+                client_secret: Secret::new(client_secret),
+            })
+        }
+        ProviderKind::Fusion => {
+            let mut client = oidc_client(application, &endpoints, &client_id, Some(&client_secret))?;
+            if let Some(revoke_url) = &provider.revoke_url {
+                client = client.set_revocation_uri(RevocationUrl::new(revoke_url.clone())?);
+            }
+            if let Some(introspection_url) = &provider.introspection_url {
+                client = client.set_introspection_uri(IntrospectionUrl::new(introspection_url.clone())?);
+            }
+            let mut role_mapping = HashMap::new();
+            for (fusion_role, role) in provider.role_mapping.iter() {
+                match Role::try_from(role.as_str()) {
+                    Ok(role) => {
+                        role_mapping.insert(fusion_role.clone(), role);
+                    }
+                    Err(_) => tracing::error!("unknown role {} in {} role_mapping", role, provider.name),
+                }
+            }
+            AuthClientType::Fusion(FusionProvider {
+                client,
+                client_id,
+                client_secret: Secret::new(client_secret),
+                issuer: endpoints.issuer.ok_or(ProviderSetupError::Missing("issuer"))?,
+                jwks_url: endpoints.jwks_url.ok_or(ProviderSetupError::Missing("jwks_url"))?,
+                userinfo_url: provider
+                    .userinfo_url
+                    .clone()
+                    .ok_or(ProviderSetupError::Missing("userinfo_url"))?,
+                logout_url: endpoints.logout_url.ok_or(ProviderSetupError::Missing("logout_url"))?,
+                role_mapping,
+            })
+        }
+        ProviderKind::Microsoft | ProviderKind::GitLab | ProviderKind::Oidc => {
+            AuthClientType::Oidc(oidc_provider(
+                application,
+                provider,
+                &endpoints,
+                &client_id,
+                Some(&client_secret),
+                &["openid", "email", "profile"],
+            )?)
+        }
+        // set up above, without a client secret
+        ProviderKind::Apple => return Err(ProviderSetupError::NoClientSecret("apple")),
+    };
+    Ok(Some(client))
+}
+
+pub fn setup_auth_providers(application: &ApplicationSettings) -> HashMap<String, AuthClientType> {
+    let mut clients = HashMap::new();
+    for provider in application.oauth_providers.iter() {
+        match setup_provider(application, provider) {
+            Ok(Some(client)) => {
+                tracing::info!("login provider {} enabled", provider.name);
+                clients.insert(provider.name.clone(), client);
+            }
+            Ok(None) => {}
+            Err(error) => tracing::error!("login provider {} not enabled: {}", provider.name, error),
+        }
+    }
+    clients
+}
+
+// What the login page shows for an enabled provider.
+pub struct LoginProvider {
+    pub name: String,
+    pub display_name: String,
+    pub icon: Option<String>,
+}
+
+fn default_display_name(provider: &OAuthProvider) -> String {
+    match provider.kind {
+        ProviderKind::Google => "Google".to_string(),
+        ProviderKind::GitHub => "GitHub".to_string(),
+        ProviderKind::Fusion => "FusionAuth".to_string(),
+        ProviderKind::Microsoft => "Microsoft".to_string(),
+        ProviderKind::GitLab => "GitLab".to_string(),
+        ProviderKind::Apple => "Apple".to_string(),
+        ProviderKind::Oidc => provider.name.clone(),
+    }
+}
+
+// The enabled providers in the order of the configuration.
+pub fn login_providers(
+    application: &ApplicationSettings,
+    clients: &HashMap<String, AuthClientType>,
+) -> Vec<LoginProvider> {
+    application
+        .oauth_providers
+        .iter()
+        .filter(|provider| clients.contains_key(&provider.name))
+        .map(|provider| LoginProvider {
+            name: provider.name.clone(),
+            display_name: provider
+                .display_name
+                .clone()
+                .unwrap_or_else(|| default_display_name(provider)),
+            icon: provider.icon.clone(),
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+// Registers an app that can use us as its authorization server.
+// cargo run --bin register_client -- <client_id> <name> <redirect_uri,...> <scope,...> [public|machine]
+// public clients (native apps, spa) have no secret,
+// machine clients call the api for themselves with the client_credentials grant.
+// The client secret is printed once, only its hash is stored.
+
+use backend::auth::opaque_token::{hash_opaque_token, new_opaque_token};
+use backend::configuration::get_configuration;
+use backend::database::{OAuthClient, YogaDatabase};
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 5 {
+        eprintln!("usage: register_client <client_id> <name> <redirect_uri,...> <scope,...> [public|machine]");
+        std::process::exit(1);
+    }
+    let kind = args.get(5).map(String::as_str).unwrap_or("confidential");
+    let public = kind == "public";
+    let grant_types = match kind {
+        "machine" => vec!["client_credentials".to_string()],
+        _ => vec!["authorization_code".to_string(), "refresh_token".to_string()],
+    };
+
+    let configuration = get_configuration().expect("Failed to read configuration.");
+    let database = YogaDatabase::new(configuration.database);
+
+    let client_secret = if public { None } else { Some(new_opaque_token()) };
+    let client = OAuthClient {
+        client_id: args[1].clone(),
+        name: args[2].clone(),
+        client_secret_hash: client_secret.as_deref().map(hash_opaque_token),
+        redirect_uris: split_list(&args[3]),
+        allowed_scopes: split_list(&args[4]),
+        grant_types,
+    };
+    database
+        .insert_oauth_client(&client)
+        .await
+        .expect("Failed to insert client.");
+
+    println!("client_id: {}", client.client_id);
+    if let Some(secret) = client_secret {
+        println!("client_secret: {}", secret);
+    }
+    Ok(())
+}
@@ -4,13 +4,13 @@ use actix_web::{
     cookie::{self, Key},
     http, web, App, HttpServer,
 };
-use backend::{configuration::{get_configuration, ApplicationSettings}, database::YogaDatabase, auth::{AuthClientType, AuthName}};
+use backend::{configuration::get_configuration, database::YogaDatabase, auth::providers::{login_providers, setup_auth_providers}};
 use backend::YogaAppData;
-use oauth2::{basic::BasicClient, RevocationUrl, IntrospectionUrl};
-use oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
-use std::collections::HashMap;
+use backend::rate_limit::{middleware::RateLimit, RateLimiter};
+use backend::csrf::{Csrf, CSRF_HEADER};
+use backend::media::Media;
 use tracing_actix_web::TracingLogger;
-use backend::auth::GoogleClient;
+use backend::auth::jwt::TokenIssuer;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -27,14 +27,37 @@ async fn main() -> std::io::Result<()> {
     let db = web::Data::new(database);
 
     let clients = setup_auth_providers(&configuration.application);
+    let login_providers = login_providers(&configuration.application, &clients);
+
+    let rate_limiter = web::Data::new(RateLimiter::new(configuration.application.rate_limit.clone()).await);
+
+    let token_issuer = TokenIssuer::new(&configuration.jwt).expect("Failed to load jwt keys.");
+
+    let media = web::Data::new(
+        Media::new(&configuration.media).expect("Failed to set up media storage."),
+    );
 
     let yoga_data = web::Data::new(YogaAppData {
         oauth_clients: clients,
+        login_providers,
         host: configuration.application.host.clone(),
         port: configuration.application.port.clone(),
         after_login_url: configuration.application.after_login_url,
+        device_verification_url: configuration.application.device_verification_url,
+        login_url: configuration.application.login_url,
+        consent_url: configuration.application.consent_url,
+        token_issuer,
+        deletion_grace_days: configuration.application.account_deletion.grace_days,
+        impersonation_minutes: configuration.application.impersonation_minutes,
     });
 
+    actix_web::rt::spawn(backend::account_purge::run(
+        db.clone(),
+        std::time::Duration::from_secs(
+            configuration.application.account_deletion.purge_interval_minutes.max(1) * 60,
+        ),
+    ));
+
     let bind_address = (
         configuration.application.host,
         configuration.application.port.parse::<u16>().unwrap(),
@@ -63,22 +86,89 @@ async fn main() -> std::io::Result<()> {
                 http::header::ACCESS_CONTROL_ALLOW_HEADERS,
                 http::header::ACCESS_CONTROL_ALLOW_METHODS,
                 http::header::CONTENT_TYPE,
+                http::header::IF_MATCH,
                 http::header::HeaderName::from_lowercase(b"x-auth-token").unwrap(),
+                http::header::HeaderName::from_static(CSRF_HEADER),
             ])
-            .allowed_methods(vec!["GET", "POST", "PATCH"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+            .expose_headers(vec![http::header::ETAG, http::header::LOCATION])
             .max_age(3600);
         App::new()
             .wrap(TracingLogger::default())
             .service(
                 web::scope("/api/v1")
+                    .service(backend::routes::oauth::login_providers)
                     .service(backend::routes::oauth::request_login_uri)
                     .service(backend::routes::oauth::oauth_login_redirect)
+                    .service(backend::routes::oauth::oauth_form_post_redirect)
                     .service(backend::routes::oauth::logout)
+                    .service(backend::routes::tokens::refresh)
+                    .service(backend::routes::tokens::csrf)
+                    .service(backend::routes::device::device_request)
+                    .service(backend::routes::device::decide_device_request)
+                    .service(backend::routes::authorize::consent_request)
+                    .service(backend::routes::authorize::decide_consent)
                     .service(backend::routes::health_check)
                     .service(backend::routes::poses::look_at_poses)
+                    .service(backend::routes::poses::look_at_pose)
+                    .service(backend::routes::poses::create_pose)
+                    .service(backend::routes::poses::update_pose)
+                    .service(backend::routes::poses::delete_pose)
+                    .service(backend::routes::media::upload_pose_image)
+                    .service(backend::routes::media::delete_pose_image)
+                    .service(backend::routes::media::serve_media)
+                    .service(backend::routes::annotations::add_favorite)
+                    .service(backend::routes::annotations::remove_favorite)
+                    .service(backend::routes::annotations::set_tags)
+                    .service(backend::routes::annotations::set_note)
+                    .service(backend::routes::annotations::delete_note)
+                    .service(backend::routes::annotations::my_tags)
+                    .service(backend::routes::interchange::import_sequences)
+                    .service(backend::routes::sequences::list_sequences)
+                    .service(backend::routes::sequences::create_sequence)
+                    .service(backend::routes::sequences::get_sequence)
+                    .service(backend::routes::sequences::replace_sequence)
+                    .service(backend::routes::sequences::reorder_sequence)
+                    .service(backend::routes::sequences::duplicate_sequence)
+                    .service(backend::routes::sequences::delete_sequence)
+                    .service(backend::routes::interchange::export_sequence)
+                    .service(backend::routes::sharing::share_sequence)
+                    .service(backend::routes::sharing::revoke_share_token)
+                    .service(backend::routes::sharing::public_sequences)
+                    .service(backend::routes::sharing::shared_sequence)
+                    .service(backend::routes::sharing::copy_shared_sequence)
+                    .service(backend::routes::practice::log_practice)
+                    .service(backend::routes::practice::practice_log)
+                    .service(backend::routes::stats::my_stats)
+                    .service(backend::routes::stats::set_timezone)
+                    .service(backend::routes::security_events::my_security_events)
+                    .service(backend::routes::account::delete_me)
+                    .service(backend::routes::account::export_me)
+                    .service(backend::routes::security_events::auth_events)
+                    .service(backend::routes::admin::list_users)
+                    .service(backend::routes::admin::get_user)
+                    .service(backend::routes::admin::disable_user)
+                    .service(backend::routes::admin::enable_user)
+                    .service(backend::routes::admin::force_logout)
+                    .service(backend::routes::admin::grant_role)
+                    .service(backend::routes::admin::revoke_role)
+                    .service(backend::routes::impersonation::start_impersonation)
+                    .service(backend::routes::impersonation::impersonation_status)
+                    .service(backend::routes::impersonation::stop_impersonation)
+            )
+            .service(
+                web::scope("/oauth")
+                    .service(backend::routes::device::device_code)
+                    .service(backend::routes::oauth_token::token)
+                    .service(backend::routes::authorize::authorize)
+                    .service(backend::routes::authorize::userinfo)
             )
+            .service(backend::routes::well_known::jwks)
+            .service(backend::routes::well_known::openid_configuration)
             .app_data(yoga_data.clone())
             .app_data(db.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(media.clone())
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), Key::from(&[0; 64]))
                     .session_lifecycle(
@@ -86,70 +176,11 @@ async fn main() -> std::io::Result<()> {
                     )
                     .build(),
             )
+            .wrap(Csrf)
+            .wrap(RateLimit::new(rate_limiter.clone()))
             .wrap(cors)
     })
     .bind(bind_address)?
     .run()
     .await
 }
-
-fn setup_auth_providers(application: &ApplicationSettings) -> HashMap<AuthName, AuthClientType> {
-    let mut clients = HashMap::new();
-    for provider in application.oauth_providers.iter() {
-        let client_id_key = format!("{}_CLIENT_ID", provider.name.to_uppercase());
-        let client_id = match std::env::var(client_id_key.clone()) {
-            Ok(value) => Some(value),
-            Err(_) => {
-                tracing::error!("couldn't get {} from environment", client_id_key);
-                None
-            }
-        };
-        let client_secret_key = format!("{}_CLIENT_SECRET", provider.name.to_uppercase());
-        let client_secret = match std::env::var(client_secret_key.clone()) {
-            Ok(value) => Some(value),
-            Err(_) => {
-                tracing::error!("couldn't get {} from environment", client_secret_key);
-                None
-            }
-        };
-        if let (Some(id), Some(secret)) = (client_id, client_secret) {
-            let auth_name: AuthName = match provider.name.as_str().try_into() {
-                Ok(name) => name,
-                Err(_) => {
-                    tracing::error!("invalid auth provider name");
-                    continue;
-                }
-            };
-            match auth_name {
-                AuthName::Google => {
-                    let google_client = GoogleClient::new(
-                        ClientId::new(id),
-                        Some(ClientSecret::new(secret)),
-                        AuthUrl::new(provider.oauth_url.clone()).unwrap(),
-                        Some(TokenUrl::new(provider.token_url.clone()).unwrap()),
-                    ).set_redirect_uri(
-                            RedirectUrl::new(application.oauth_redirect_url.clone()).unwrap(),
-                        )
-                        .set_revocation_uri(RevocationUrl::new(provider.revoke_url.clone()).unwrap())
-                        .set_introspection_uri(IntrospectionUrl::new(provider.introspection_url.clone()).unwrap());
-                    clients.insert(auth_name, AuthClientType::Google(google_client));
-                },
-                AuthName::GitHub | AuthName::Fusion => {
-                    let client = BasicClient::new(
-                        ClientId::new(id),
-                        Some(ClientSecret::new(secret)),
-                        AuthUrl::new(provider.oauth_url.clone()).unwrap(),
-                        Some(TokenUrl::new(provider.token_url.clone()).unwrap()),
-                    )
-                        .set_redirect_uri(
-                            RedirectUrl::new(application.oauth_redirect_url.clone()).unwrap(),
-                        )
-                        .set_revocation_uri(RevocationUrl::new(provider.revoke_url.clone()).unwrap())
-                        .set_introspection_uri(IntrospectionUrl::new(provider.introspection_url.clone()).unwrap());
-                    clients.insert(auth_name, AuthClientType::Basic(client));
-                }
-            }
-        }
-    }
-    clients
-}
@@ -4,6 +4,8 @@
 // Config lets you set a set of default parameters and then extend them via merging in
 // configuration from a variety of sources
 
+use std::collections::HashMap;
+
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{postgres::{PgSslMode, PgConnectOptions}, ConnectOptions};
@@ -14,19 +16,110 @@ pub struct ApplicationSettings {
     pub host: String,
     pub oauth_redirect_url: String,
     pub after_login_url: String,
+    // frontend page where a logged in user enters a device's user code
+    pub device_verification_url: String,
+    // frontend pages used when we are the authorization server for our other apps
+    pub login_url: String,
+    pub consent_url: String,
     pub allowed_origins: Vec<String>,
     pub oauth_providers: Vec<OAuthProvider>,
+    pub rate_limit: RateLimitSettings,
+    pub account_deletion: AccountDeletionSettings,
+    // how long an admin can act as another user before having to start over
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub impersonation_minutes: i64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct AccountDeletionSettings {
+    // a deleted account can still be brought back by logging in for this long
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub grace_days: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub purge_interval_minutes: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    // path prefixes limited per ip address
+    pub paths: Vec<String>,
+    pub per_ip: BucketSettings,
+    // per client_id at the token endpoint
+    pub per_account: BucketSettings,
+    // failed client authentications in a row before an account is locked out, every failure
+    // after that doubles the lockout up to lockout_max_seconds
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_after_failures: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_base_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_max_seconds: u64,
+    // only behind a proxy that sets X-Forwarded-For, otherwise anyone can pick their ip
+    pub trust_forwarded_for: bool,
+    // shared buckets for several instances, in memory without it
+    pub redis_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct BucketSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refill_per_minute: u32,
+}
+
+// What a login provider speaks, the name of a provider is free
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Google,
+    GitHub,
+    Fusion,
+    Microsoft,
+    GitLab,
+    Apple,
+    // any other OpenID Connect provider, everything comes from configuration
+    Oidc,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct OAuthProvider {
+    // used in urls, the session and user_identity, the client id and secret come from the
+    // environment as <NAME>_CLIENT_ID and <NAME>_CLIENT_SECRET
     pub name: String,
-    pub oauth_url: String,
-    pub token_url: String,
-    pub revoke_url: String,
-    pub introspection_url: String,
-    //pub client_secret: String,
-    //pub client_id: String,
+    pub kind: ProviderKind,
+    // shown on the login button, defaults to the provider's usual name
+    pub display_name: Option<String>,
+    // image url for the login button
+    pub icon: Option<String>,
+    // Google, Microsoft, GitLab and Apple have defaults for the urls
+    pub oauth_url: Option<String>,
+    pub token_url: Option<String>,
+    pub revoke_url: Option<String>,
+    pub introspection_url: Option<String>,
+    // REST api base url, for providers whose identity doesn't come in an id_token (GitHub)
+    pub api_url: Option<String>,
+    // OpenID Connect, the id_token's iss and where its signing keys are published
+    pub issuer: Option<String>,
+    pub jwks_url: Option<String>,
+    pub userinfo_url: Option<String>,
+    // RP-initiated logout, the browser is sent there on logout
+    pub logout_url: Option<String>,
+    // the provider's role name -> our role name
+    #[serde(default)]
+    pub role_mapping: HashMap<String, String>,
+    // scopes to ask for instead of the provider's defaults
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    // GitLab, https://gitlab.com or a self-hosted instance
+    pub base_url: Option<String>,
+    // Microsoft, "common", "organizations", "consumers" or a tenant id
+    pub tenant: Option<String>,
+    // Apple, the client secret is a JWT we sign with the key from the developer account
+    pub team_id: Option<String>,
+    pub key_id: Option<String>,
+    pub private_key_path: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -40,10 +133,67 @@ pub struct DatabaseSettings {
     pub require_ssl: bool,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct JwtSettings {
+    // "EdDSA" or "RS256"
+    pub algorithm: String,
+    // PEM files, relative to the directory the server is started in
+    pub private_key_path: String,
+    pub public_key_path: String,
+    // published as `kid` in the JWKS and the token headers
+    pub key_id: String,
+    pub issuer: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub access_token_ttl_minutes: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refresh_token_ttl_days: i64,
+}
+
+// Where uploaded images go and what is accepted
+#[derive(serde::Deserialize, Clone)]
+pub struct MediaSettings {
+    pub storage: MediaStorageKind,
+    // local storage, relative to the directory the server is started in
+    pub local_path: String,
+    pub s3: Option<S3Settings>,
+    // our own address as browsers reach it, signed urls served by us start with it
+    pub public_url: String,
+    // signs the urls served by us, APP_MEDIA__SIGNING_KEY, random per start without
+    pub signing_key: Option<Secret<String>>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub url_ttl_minutes: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_upload_bytes: usize,
+    // of either side, bigger images aren't decoded at all
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_dimension: u32,
+    pub thumbnail_widths: Vec<u32>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaStorageKind {
+    Local,
+    S3,
+}
+
+// any S3 compatible storage, a local MinIO too, buckets are addressed by path
+#[derive(serde::Deserialize, Clone)]
+pub struct S3Settings {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    // APP_MEDIA__S3__ACCESS_KEY and APP_MEDIA__S3__SECRET_KEY
+    pub access_key: Secret<String>,
+    pub secret_key: Secret<String>,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
+    pub jwt: JwtSettings,
+    pub media: MediaSettings,
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -123,7 +273,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
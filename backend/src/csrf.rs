@@ -0,0 +1,142 @@
+// Cross site request forgery protection for the cookie authenticated api, double submit:
+// every /api/v1 response hands out a random token in the csrf_token cookie (readable by our
+// frontend), state changing requests have to send it back in the x-csrf-token header.
+// Another site can make the browser send our cookies but can neither read them nor set the header.
+// Exempt are
+// - Bearer requests, the token isn't sent by the browser on its own
+// - requests without cookies, there is nothing ambient to abuse (mobile and cli clients)
+// - the form_post login redirect, the provider posts it and the OAuth state protects it
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+
+use crate::auth::opaque_token::new_opaque_token;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+const PROTECTED_PREFIX: &str = "/api/v1";
+const EXEMPT_PATHS: [&str; 1] = ["/api/v1/oauth-redirect"];
+
+// the token of this request, the one it came with or the one its response will set
+#[derive(Clone)]
+struct CsrfToken(String);
+
+pub fn csrf_token(request: &HttpRequest) -> Option<String> {
+    request.extensions().get::<CsrfToken>().map(|token| token.0.clone())
+}
+
+fn csrf_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(CSRF_COOKIE, token)
+        .path("/")
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn is_bearer(request: &ServiceRequest) -> bool {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("Bearer "))
+        .unwrap_or(false)
+}
+
+fn needs_check(request: &ServiceRequest) -> bool {
+    !is_safe(request.method())
+        && !is_bearer(request)
+        && request.headers().contains_key(header::COOKIE)
+        && !EXEMPT_PATHS.contains(&request.path())
+}
+
+fn header_matches(request: &ServiceRequest, expected: Option<&str>) -> bool {
+    let presented = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (presented, expected) {
+        (Some(presented), Some(expected)) => {
+            presented.len() == expected.len()
+                && openssl::memcmp::eq(presented.as_bytes(), expected.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+pub struct Csrf;
+
+impl<S, B> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            if !request.path().starts_with(PROTECTED_PREFIX) {
+                return service
+                    .call(request)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            }
+
+            let existing = request.cookie(CSRF_COOKIE).map(|cookie| cookie.value().to_string());
+            if needs_check(&request) && !header_matches(&request, existing.as_deref()) {
+                tracing::warn!("csrf token missing or wrong on {} {}", request.method(), request.path());
+                return Ok(request
+                    .into_response(HttpResponse::Forbidden().body("csrf token missing or invalid"))
+                    .map_into_right_body());
+            }
+
+            let (token, issued) = match existing {
+                Some(token) => (token, false),
+                None => (new_opaque_token(), true),
+            };
+            request.extensions_mut().insert(CsrfToken(token.clone()));
+
+            let mut response = service.call(request).await?;
+            if issued {
+                if let Err(error) = response.response_mut().add_cookie(&csrf_cookie(token)) {
+                    tracing::error!("failed to set csrf cookie {}", error);
+                }
+            }
+            Ok(response.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+#[derive(serde::Serialize)]
+pub struct UserProfileRecord {
+    pub user_id: Uuid,
+    pub email: String,
+    pub timezone: String,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub purge_after: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RoleGrant {
+    pub role: String,
+    pub source: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentRecord {
+    pub client_id: String,
+    pub scope: String,
+    pub granted_at: DateTime<Utc>,
+}
+
+// one row per refresh token, the token itself is never stored
+#[derive(serde::Serialize)]
+pub struct RefreshTokenRecord {
+    pub family_id: Uuid,
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl YogaDatabase {
+    pub async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfileRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            UserProfileRecord,
+            "SELECT user_id, email, timezone, deleted_at, purge_after FROM user_profile WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_role_grants(&self, user_id: Uuid) -> Result<Vec<RoleGrant>, sqlx::Error> {
+        sqlx::query_as!(
+            RoleGrant,
+            "SELECT role, source FROM user_role WHERE user_id = $1 ORDER BY role",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_consents(&self, user_id: Uuid) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            ConsentRecord,
+            "SELECT client_id, scope, granted_at FROM user_consent WHERE user_id = $1 ORDER BY granted_at",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_refresh_token_records(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<RefreshTokenRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            RefreshTokenRecord,
+            r#"SELECT family_id, client_id, scope, issued_at, expires_at, used_at, revoked_at
+            FROM refresh_token WHERE user_id = $1 ORDER BY issued_at"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Marks the account deleted and takes away everything that lets anyone act as the user:
+    // refresh tokens, consents given to clients, pending authorizations.
+    // False if the account was already deleted.
+    pub async fn soft_delete_user(
+        &self,
+        user_id: Uuid,
+        purge_after: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            r#"UPDATE user_profile SET deleted_at = now(), purge_after = $2
+            WHERE user_id = $1 AND deleted_at IS NULL"#,
+            user_id,
+            purge_after
+        )
+        .execute(&mut transaction)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE refresh_token SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!("DELETE FROM user_consent WHERE user_id = $1", user_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query!("DELETE FROM authorization_request WHERE user_id = $1", user_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query!(
+            "DELETE FROM device_authorization WHERE user_id = $1 AND status <> 'consumed'",
+            user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // Brings back an account deleted within the grace period, true if it was deleted.
+    pub async fn restore_user(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let restored = sqlx::query!(
+            r#"UPDATE user_profile SET deleted_at = NULL, purge_after = NULL
+            WHERE user_id = $1 AND deleted_at IS NOT NULL"#,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(restored.rows_affected() > 0)
+    }
+
+    // Removes the accounts whose grace period is over. Everything of the user goes with the
+    // user_profile row (ON DELETE CASCADE), the audit log keeps the events without the user,
+    // the ip address or the user agent.
+    pub async fn purge_deleted_users(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let user_ids: Vec<Uuid> = sqlx::query!(
+            "SELECT user_id FROM user_profile WHERE purge_after <= now() FOR UPDATE SKIP LOCKED"
+        )
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|row| row.user_id)
+        .collect();
+        if user_ids.is_empty() {
+            return Ok(user_ids);
+        }
+        sqlx::query!(
+            "UPDATE auth_event SET ip = NULL, user_agent = NULL WHERE user_id = ANY($1)",
+            &user_ids
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!("DELETE FROM user_profile WHERE user_id = ANY($1)", &user_ids)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(user_ids)
+    }
+}
@@ -0,0 +1,194 @@
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+// one user's favorite flag, tags and note on one pose
+pub struct PoseAnnotations {
+    pub favorite: bool,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub poses: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct PoseAnnotationRecord {
+    pub pose_id: i32,
+    pub pose_name: String,
+    pub favorite: bool,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+}
+
+impl YogaDatabase {
+    pub async fn get_pose_annotations(
+        &self,
+        user_id: Uuid,
+        pose_id: i32,
+    ) -> Result<PoseAnnotations, sqlx::Error> {
+        sqlx::query_as!(
+            PoseAnnotations,
+            r#"SELECT
+            EXISTS (SELECT 1 FROM pose_favorite WHERE user_id = $1 AND pose_id = $2) AS "favorite!",
+            ARRAY(SELECT tag FROM pose_tag WHERE user_id = $1 AND pose_id = $2 ORDER BY tag)
+                AS "tags!",
+            (SELECT note FROM pose_note WHERE user_id = $1 AND pose_id = $2) AS note"#,
+            user_id,
+            pose_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn set_favorite(
+        &self,
+        user_id: Uuid,
+        pose_id: i32,
+        favorite: bool,
+    ) -> Result<(), sqlx::Error> {
+        let result = if favorite {
+            sqlx::query!(
+                r#"INSERT INTO pose_favorite (user_id, pose_id) VALUES ($1, $2)
+                ON CONFLICT DO NOTHING"#,
+                user_id,
+                pose_id
+            )
+            .execute(&self.pool)
+            .await
+        } else {
+            sqlx::query!(
+                "DELETE FROM pose_favorite WHERE user_id = $1 AND pose_id = $2",
+                user_id,
+                pose_id
+            )
+            .execute(&self.pool)
+            .await
+        };
+        result.map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // Replaces the user's tags on the pose.
+    pub async fn set_pose_tags(
+        &self,
+        user_id: Uuid,
+        pose_id: i32,
+        tags: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM pose_tag WHERE user_id = $1 AND pose_id = $2",
+            user_id,
+            pose_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO pose_tag (user_id, pose_id, tag)
+            SELECT $1, $2, tag FROM UNNEST($3::text[]) AS new_tags (tag)
+            ON CONFLICT DO NOTHING"#,
+            user_id,
+            pose_id,
+            tags
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list_user_tags(&self, user_id: Uuid) -> Result<Vec<TagCount>, sqlx::Error> {
+        sqlx::query_as!(
+            TagCount,
+            r#"SELECT t.tag, COUNT(*) AS "poses!" FROM pose_tag t
+            JOIN pose p ON p.pose_id = t.pose_id
+            WHERE t.user_id = $1 AND p.deleted_at IS NULL
+            GROUP BY t.tag ORDER BY t.tag"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn set_pose_note(
+        &self,
+        user_id: Uuid,
+        pose_id: i32,
+        note: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO pose_note (user_id, pose_id, note) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, pose_id) DO UPDATE SET note = $3, updated_at = now()"#,
+            user_id,
+            pose_id,
+            note
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    pub async fn delete_pose_note(&self, user_id: Uuid, pose_id: i32) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM pose_note WHERE user_id = $1 AND pose_id = $2",
+            user_id,
+            pose_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    // Every pose the user has a favorite, tag or note on, deleted ones included.
+    pub async fn list_pose_annotations(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<PoseAnnotationRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            PoseAnnotationRecord,
+            r#"SELECT p.pose_id, p.name AS pose_name,
+            EXISTS (SELECT 1 FROM pose_favorite f WHERE f.user_id = $1 AND f.pose_id = p.pose_id)
+                AS "favorite!",
+            ARRAY(SELECT t.tag FROM pose_tag t WHERE t.user_id = $1 AND t.pose_id = p.pose_id
+                ORDER BY t.tag) AS "tags!",
+            (SELECT n.note FROM pose_note n WHERE n.user_id = $1 AND n.pose_id = p.pose_id) AS note
+            FROM pose p
+            WHERE p.pose_id IN (
+                SELECT pose_id FROM pose_favorite WHERE user_id = $1
+                UNION SELECT pose_id FROM pose_tag WHERE user_id = $1
+                UNION SELECT pose_id FROM pose_note WHERE user_id = $1
+            )
+            ORDER BY p.name"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+}
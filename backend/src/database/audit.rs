@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+pub struct NewAuthEvent<'a> {
+    pub event_type: &'a str,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub provider: Option<&'a str>,
+    pub client_id: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub detail: Option<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+pub struct AuthEventRecord {
+    pub event_id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub event_type: String,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub provider: Option<String>,
+    pub client_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+// Every field narrows the events down, None matches everything.
+#[derive(Default)]
+pub struct AuthEventFilter {
+    pub user_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub provider: Option<String>,
+    pub ip: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl YogaDatabase {
+    pub async fn insert_auth_event(&self, event: NewAuthEvent<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO auth_event
+            (event_id, event_type, user_id, actor_id, provider, client_id, ip, user_agent, detail)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+            Uuid::new_v4(),
+            event.event_type,
+            event.user_id,
+            event.actor_id,
+            event.provider,
+            event.client_id,
+            event.ip,
+            event.user_agent,
+            event.detail
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // Newest first.
+    pub async fn list_auth_events(
+        &self,
+        filter: &AuthEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuthEventRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            AuthEventRecord,
+            r#"SELECT event_id, occurred_at, event_type, user_id, actor_id, provider, client_id, ip,
+            user_agent, detail
+            FROM auth_event
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+            AND ($2::text IS NULL OR event_type = $2)
+            AND ($3::text IS NULL OR provider = $3)
+            AND ($4::text IS NULL OR ip = $4)
+            AND ($5::timestamptz IS NULL OR occurred_at >= $5)
+            AND ($6::timestamptz IS NULL OR occurred_at < $6)
+            ORDER BY occurred_at DESC, event_id
+            LIMIT $7 OFFSET $8"#,
+            filter.user_id,
+            filter.event_type,
+            filter.provider,
+            filter.ip,
+            filter.from,
+            filter.to,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+}
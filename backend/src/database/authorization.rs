@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{YogaDatabase, YogaDatabaseError};
+
+pub struct NewAuthorizationRequest<'a> {
+    pub client_id: &'a str,
+    pub user_id: Uuid,
+    pub redirect_uri: &'a str,
+    pub scope: &'a str,
+    pub state: Option<&'a str>,
+    pub nonce: Option<&'a str>,
+    pub code_challenge: &'a str,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct AuthorizationRequest {
+    pub request_id: Uuid,
+    pub client_id: String,
+    pub client_name: String,
+    pub user_id: Uuid,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+}
+
+pub enum CodeRedemption {
+    Valid(AuthorizationRequest),
+    // the code was already exchanged, the tokens issued for it have been revoked
+    Reused,
+    Invalid,
+}
+
+impl YogaDatabase {
+    pub async fn insert_authorization_request(
+        &self,
+        request: NewAuthorizationRequest<'_>,
+    ) -> Result<Uuid, sqlx::Error> {
+        let request_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"INSERT INTO authorization_request
+            (request_id, client_id, user_id, redirect_uri, scope, state, nonce, code_challenge, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+            request_id,
+            request.client_id,
+            request.user_id,
+            request.redirect_uri,
+            request.scope,
+            request.state,
+            request.nonce,
+            request.code_challenge,
+            request.expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(request_id)
+    }
+
+    // A request still waiting for the user's consent.
+    pub async fn get_authorization_request(
+        &self,
+        request_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<AuthorizationRequest>, sqlx::Error> {
+        sqlx::query_as!(
+            AuthorizationRequest,
+            r#"SELECT r.request_id, r.client_id, c.name AS client_name, r.user_id, r.redirect_uri,
+            r.scope, r.state, r.nonce, r.code_challenge
+            FROM authorization_request r JOIN oauth_client c ON c.client_id = r.client_id
+            WHERE r.request_id = $1 AND r.user_id = $2 AND r.code_hash IS NULL AND r.expires_at > now()"#,
+            request_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Attaches the authorization code to a request, the code is only good until `expires_at`.
+    pub async fn set_authorization_code(
+        &self,
+        request_id: Uuid,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE authorization_request SET code_hash = $1, expires_at = $2 WHERE request_id = $3",
+            code_hash,
+            expires_at,
+            request_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    pub async fn delete_authorization_request(&self, request_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM authorization_request WHERE request_id = $1", request_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                e
+            })?;
+        Ok(())
+    }
+
+    pub async fn redeem_authorization_code(
+        &self,
+        code_hash: &str,
+        client_id: &str,
+    ) -> Result<CodeRedemption, YogaDatabaseError> {
+        let mut transaction = self.pool.begin().await?;
+        let request = sqlx::query!(
+            r#"SELECT r.request_id, r.client_id, c.name AS client_name, r.user_id, r.redirect_uri,
+            r.scope, r.state, r.nonce, r.code_challenge, r.code_used_at, r.family_id, r.expires_at
+            FROM authorization_request r JOIN oauth_client c ON c.client_id = r.client_id
+            WHERE r.code_hash = $1 FOR UPDATE OF r"#,
+            code_hash
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+
+        let request = match request {
+            Some(request) if request.client_id == client_id => request,
+            _ => return Ok(CodeRedemption::Invalid),
+        };
+        if request.code_used_at.is_some() {
+            // RFC 6749 4.1.2, a code used twice revokes what was issued for it
+            if let Some(family_id) = request.family_id {
+                sqlx::query!(
+                    "UPDATE refresh_token SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+                    family_id
+                )
+                .execute(&mut transaction)
+                .await?;
+                transaction.commit().await?;
+            }
+            return Ok(CodeRedemption::Reused);
+        }
+        if request.expires_at < Utc::now() {
+            return Ok(CodeRedemption::Invalid);
+        }
+
+        sqlx::query!(
+            "UPDATE authorization_request SET code_used_at = now() WHERE request_id = $1",
+            request.request_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(CodeRedemption::Valid(AuthorizationRequest {
+            request_id: request.request_id,
+            client_id: request.client_id,
+            client_name: request.client_name,
+            user_id: request.user_id,
+            redirect_uri: request.redirect_uri,
+            scope: request.scope,
+            state: request.state,
+            nonce: request.nonce,
+            code_challenge: request.code_challenge,
+        }))
+    }
+
+    pub async fn set_authorization_family(
+        &self,
+        request_id: Uuid,
+        family_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE authorization_request SET family_id = $1 WHERE request_id = $2",
+            family_id,
+            request_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // True if the user already agreed to give the client every scope in `scope`.
+    pub async fn has_consent(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let granted = sqlx::query!(
+            "SELECT scope FROM user_consent WHERE user_id = $1 AND client_id = $2",
+            user_id,
+            client_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(match granted {
+            Some(granted) => scope
+                .split_whitespace()
+                .all(|requested| granted.scope.split_whitespace().any(|s| s == requested)),
+            None => false,
+        })
+    }
+
+    pub async fn save_consent(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO user_consent (user_id, client_id, scope) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, client_id) DO UPDATE SET scope = $3, granted_at = now()"#,
+            user_id,
+            client_id,
+            scope
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+}
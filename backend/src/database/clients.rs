@@ -0,0 +1,60 @@
+use super::YogaDatabase;
+
+pub struct OAuthClient {
+    pub client_id: String,
+    pub name: String,
+    pub client_secret_hash: Option<String>,
+    pub redirect_uris: Vec<String>,
+    pub allowed_scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+}
+
+impl OAuthClient {
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|g| g == grant_type)
+    }
+
+    pub fn allows_scopes(&self, scope: &str) -> bool {
+        scope
+            .split_whitespace()
+            .all(|requested| self.allowed_scopes.iter().any(|s| s == requested))
+    }
+}
+
+impl YogaDatabase {
+    pub async fn insert_oauth_client(&self, client: &OAuthClient) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO oauth_client
+            (client_id, name, client_secret_hash, redirect_uris, allowed_scopes, grant_types)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            client.client_id,
+            client.name,
+            client.client_secret_hash,
+            &client.redirect_uris,
+            &client.allowed_scopes,
+            &client.grant_types
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_oauth_client(&self, client_id: &str) -> Result<Option<OAuthClient>, sqlx::Error> {
+        sqlx::query_as!(
+            OAuthClient,
+            r#"SELECT client_id, name, client_secret_hash, redirect_uris, allowed_scopes, grant_types
+            FROM oauth_client WHERE client_id = $1"#,
+            client_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{YogaDatabase, YogaDatabaseError};
+
+// A pending request as shown to the user approving it.
+pub struct DeviceAuthorization {
+    pub user_code: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub scope: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub enum DevicePoll {
+    Pending,
+    // polled faster than the interval, the interval has been increased
+    SlowDown,
+    Approved { user_id: Uuid, scope: String },
+    Denied,
+    Expired,
+    Invalid,
+}
+
+impl YogaDatabase {
+    // False when the user_code is taken, by a pending request or an old one.
+    pub async fn insert_device_authorization(
+        &self,
+        device_code_hash: &str,
+        user_code: &str,
+        client_id: &str,
+        scope: &str,
+        interval_seconds: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"INSERT INTO device_authorization
+            (device_code_hash, user_code, client_id, scope, interval_seconds, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_code) DO NOTHING"#,
+            device_code_hash,
+            user_code,
+            client_id,
+            scope,
+            interval_seconds,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_pending_device_authorization(
+        &self,
+        user_code: &str,
+    ) -> Result<Option<DeviceAuthorization>, sqlx::Error> {
+        sqlx::query_as!(
+            DeviceAuthorization,
+            r#"SELECT d.user_code, d.client_id, c.name AS client_name, d.scope, d.expires_at
+            FROM device_authorization d JOIN oauth_client c ON c.client_id = d.client_id
+            WHERE d.user_code = $1 AND d.status = 'pending' AND d.expires_at > now()"#,
+            user_code
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Records the user's decision, returns false if there was no pending request for the code.
+    pub async fn decide_device_authorization(
+        &self,
+        user_code: &str,
+        user_id: Uuid,
+        approve: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let status = if approve { "approved" } else { "denied" };
+        let result = sqlx::query!(
+            r#"UPDATE device_authorization SET status = $1, user_id = $2
+            WHERE user_code = $3 AND status = 'pending' AND expires_at > now()"#,
+            status,
+            user_id,
+            user_code
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn poll_device_authorization(
+        &self,
+        device_code_hash: &str,
+        client_id: &str,
+    ) -> Result<DevicePoll, YogaDatabaseError> {
+        let mut transaction = self.pool.begin().await?;
+        let current = sqlx::query!(
+            r#"SELECT client_id, status, user_id, scope, interval_seconds, last_polled_at, expires_at
+            FROM device_authorization WHERE device_code_hash = $1 FOR UPDATE"#,
+            device_code_hash
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+
+        let current = match current {
+            Some(current) if current.client_id == client_id => current,
+            _ => return Ok(DevicePoll::Invalid),
+        };
+        let now = Utc::now();
+        if current.expires_at < now {
+            return Ok(DevicePoll::Expired);
+        }
+
+        let too_fast = match current.last_polled_at {
+            Some(last) => now < last + chrono::Duration::seconds(current.interval_seconds as i64),
+            None => false,
+        };
+        if too_fast {
+            // RFC 8628 3.5, the client must add 5 seconds to its interval
+            sqlx::query!(
+                r#"UPDATE device_authorization
+                SET interval_seconds = interval_seconds + 5, last_polled_at = now()
+                WHERE device_code_hash = $1"#,
+                device_code_hash
+            )
+            .execute(&mut transaction)
+            .await?;
+            transaction.commit().await?;
+            return Ok(DevicePoll::SlowDown);
+        }
+
+        let poll = match (current.status.as_str(), current.user_id) {
+            ("pending", _) => DevicePoll::Pending,
+            ("approved", Some(user_id)) => DevicePoll::Approved { user_id, scope: current.scope },
+            ("denied", _) => DevicePoll::Denied,
+            // consumed, a device code is only good for one set of tokens
+            _ => DevicePoll::Invalid,
+        };
+        let status = match poll {
+            DevicePoll::Approved { .. } => "consumed",
+            _ => current.status.as_str(),
+        };
+        sqlx::query!(
+            "UPDATE device_authorization SET status = $1, last_polled_at = now() WHERE device_code_hash = $2",
+            status,
+            device_code_hash
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(poll)
+    }
+}
@@ -0,0 +1,123 @@
+use uuid::Uuid;
+
+use super::{YogaDatabase, YogaDatabaseError};
+use crate::auth::Role;
+
+#[derive(serde::Serialize)]
+pub struct UserIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_login_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl YogaDatabase {
+    // The user_profile a provider account belongs to.
+    // A new provider account with the (verified) email of an existing user is linked to that
+    // user, otherwise a new user is created.
+    pub async fn user_for_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+        email: &str,
+    ) -> Result<Uuid, YogaDatabaseError> {
+        let mut transaction = self.pool.begin().await?;
+        let existing = sqlx::query!(
+            r#"UPDATE user_identity SET last_login_at = now(), email = $3
+            WHERE provider = $1 AND subject = $2 RETURNING user_id"#,
+            provider,
+            subject,
+            email
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        if let Some(existing) = existing {
+            transaction.commit().await?;
+            return Ok(existing.user_id);
+        }
+
+        let user_id = match sqlx::query!("SELECT user_id FROM user_profile WHERE email = $1", email)
+            .fetch_optional(&mut transaction)
+            .await?
+        {
+            Some(user) => user.user_id,
+            None => {
+                let new_id = Uuid::new_v4();
+                sqlx::query!(
+                    "INSERT INTO user_profile (user_id, email) VALUES ($1, $2)",
+                    new_id,
+                    email
+                )
+                .execute(&mut transaction)
+                .await?;
+                sqlx::query!(
+                    "INSERT INTO user_role (user_id, role) VALUES ($1, $2)",
+                    new_id,
+                    Role::User.as_ref()
+                )
+                .execute(&mut transaction)
+                .await?;
+                new_id
+            }
+        };
+        sqlx::query!(
+            "INSERT INTO user_identity (provider, subject, user_id, email) VALUES ($1, $2, $3, $4)",
+            provider,
+            subject,
+            user_id,
+            email
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(user_id)
+    }
+
+    pub async fn get_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error> {
+        sqlx::query_as!(
+            UserIdentity,
+            r#"SELECT provider, subject, email, created_at, last_login_at
+            FROM user_identity WHERE user_id = $1 ORDER BY created_at"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Replaces the roles a provider granted the user with `roles`. Roles granted here, or by
+    // another provider, are kept, the user keeps a role as long as anyone grants it.
+    pub async fn sync_provider_roles(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        roles: &[Role],
+    ) -> Result<(), YogaDatabaseError> {
+        let roles: Vec<String> = roles.iter().map(|role| role.as_ref().to_string()).collect();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM user_role WHERE user_id = $1 AND source = $2 AND NOT (role = ANY($3))",
+            user_id,
+            provider,
+            &roles
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO user_role (user_id, role, source)
+            SELECT $1, role, $2 FROM UNNEST($3::text[]) AS role
+            ON CONFLICT (user_id, role) DO NOTHING"#,
+            user_id,
+            provider,
+            &roles
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+#[derive(serde::Serialize)]
+pub struct ImpersonationRecord {
+    pub impersonation_id: Uuid,
+    pub admin_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+impl YogaDatabase {
+    pub async fn start_impersonation(
+        &self,
+        admin_id: Uuid,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, sqlx::Error> {
+        let impersonation_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"INSERT INTO impersonation (impersonation_id, admin_id, user_id, expires_at)
+            VALUES ($1, $2, $3, $4)"#,
+            impersonation_id,
+            admin_id,
+            user_id,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(impersonation_id)
+    }
+
+    pub async fn get_impersonation(
+        &self,
+        impersonation_id: Uuid,
+    ) -> Result<Option<ImpersonationRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            ImpersonationRecord,
+            r#"SELECT impersonation_id, admin_id, user_id, started_at, expires_at, ended_at
+            FROM impersonation WHERE impersonation_id = $1"#,
+            impersonation_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Its tokens stop working. False if it had already ended.
+    pub async fn end_impersonation(&self, impersonation_id: Uuid) -> Result<bool, sqlx::Error> {
+        let ended = sqlx::query!(
+            "UPDATE impersonation SET ended_at = now() WHERE impersonation_id = $1 AND ended_at IS NULL",
+            impersonation_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(ended.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,39 @@
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+pub struct MediaRecord {
+    pub media_id: Uuid,
+    pub content_type: String,
+    pub byte_size: i32,
+    pub width: i32,
+    pub height: i32,
+    pub thumbnail_widths: Vec<i32>,
+}
+
+pub struct NewMedia<'a> {
+    pub media_id: Uuid,
+    pub content_type: &'a str,
+    pub byte_size: i32,
+    pub width: i32,
+    pub height: i32,
+    pub thumbnail_widths: &'a [i32],
+    pub uploaded_by: Uuid,
+}
+
+impl YogaDatabase {
+    pub async fn get_media(&self, media_ids: &[Uuid]) -> Result<Vec<MediaRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            MediaRecord,
+            r#"SELECT media_id, content_type, byte_size, width, height, thumbnail_widths
+            FROM media WHERE media_id = ANY($1)"#,
+            media_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+}
@@ -1,6 +1,45 @@
+mod accounts;
+mod annotations;
+mod audit;
+mod authorization;
+mod clients;
+mod device;
+mod identities;
+mod impersonation;
+mod media;
+mod poses;
+mod practice;
+mod refresh_tokens;
+mod sequences;
+mod stats;
+mod users;
+
+pub use accounts::{ConsentRecord, RefreshTokenRecord, RoleGrant, UserProfileRecord};
+pub use annotations::{PoseAnnotationRecord, PoseAnnotations, TagCount};
+pub use audit::{AuthEventFilter, AuthEventRecord, NewAuthEvent};
+pub use authorization::{AuthorizationRequest, CodeRedemption, NewAuthorizationRequest};
+pub use clients::OAuthClient;
+pub use device::{DeviceAuthorization, DevicePoll};
+pub use identities::UserIdentity;
+pub use impersonation::ImpersonationRecord;
+pub use media::{MediaRecord, NewMedia};
+pub use poses::{
+    NewPose, PoseChanges, PoseFilter, PoseMatch, PoseNameMatch, PoseRecord, PoseSort,
+    PoseWriteError,
+};
+pub use practice::{NewPractice, NewPracticeStep, PracticeRecord, PracticeStepRecord};
+pub use refresh_tokens::RefreshOutcome;
+pub use sequences::{
+    NewSequenceStep, PublicSequenceSummary, SequenceRecord, SequenceStepRecord, SequenceSummary,
+    SharedSequenceRecord,
+};
+pub use stats::{CategoryStats, PoseStats, PracticeStats, WeekStats};
+pub use users::{ActiveSession, UserStanding, UserSummary};
+
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use uuid::Uuid;
 
+use crate::auth::Role;
 use crate::configuration::DatabaseSettings;
 
 pub struct YogaDatabase {
@@ -34,15 +73,59 @@ impl YogaDatabase {
         Ok(result.map(|r| r.user_id))
     }
 
+    pub async fn get_user_email(&self, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        let result = sqlx::query!("SELECT email FROM user_profile WHERE user_id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                e
+            })?;
+        Ok(result.map(|r| r.email))
+    }
+
     pub async fn insert_new_user(&self, email: &str) -> Result<Uuid, sqlx::Error> {
         let new_id = Uuid::new_v4();
+        let mut transaction = self.pool.begin().await?;
         sqlx::query!("INSERT INTO user_profile (user_id, email) VALUES ($1, $2)", new_id, email)
-            .execute(&self.pool)
+            .execute(&mut transaction)
             .await
             .map_err(|e| {
                 tracing::error!("Failed to execute query: {:?}", e);
                 e
             })?;
+        sqlx::query!(
+            "INSERT INTO user_role (user_id, role) VALUES ($1, $2)",
+            new_id,
+            Role::User.as_ref()
+        )
+        .execute(&mut transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        transaction.commit().await?;
         Ok(new_id)
     }
+
+    pub async fn get_user_roles(&self, user_id: Uuid) -> Result<Vec<Role>, sqlx::Error> {
+        let rows = sqlx::query!("SELECT role FROM user_role WHERE user_id = $1", user_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                e
+            })?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|r| match r.role.as_str().try_into() {
+                Ok(role) => Some(role),
+                Err(_) => {
+                    tracing::error!("unknown role {} in user_role", r.role);
+                    None
+                }
+            })
+            .collect())
+    }
 }
@@ -0,0 +1,415 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{MediaRecord, NewMedia, YogaDatabase};
+
+pub struct PoseRecord {
+    pub pose_id: i32,
+    pub name: String,
+    pub sanskrit_name: String,
+    pub category: String,
+    pub difficulty: String,
+    pub description: String,
+    pub cues: Vec<String>,
+    pub contraindications: Vec<String>,
+    pub image_id: Option<Uuid>,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(strum_macros::AsRefStr, strum_macros::EnumString, Clone, Copy, PartialEq, Debug, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum PoseSort {
+    #[default]
+    Name,
+    SanskritName,
+    Difficulty,
+    // best match first, by name without a search
+    Relevance,
+}
+
+#[derive(Default)]
+pub struct PoseFilter {
+    // a to_tsquery expression
+    pub query: Option<String>,
+    pub category: Option<String>,
+    pub difficulty: Option<String>,
+    pub sort: PoseSort,
+    // whose favorites and tags to show and filter by, None for machine clients
+    pub user_id: Option<Uuid>,
+    pub favorites_only: bool,
+    pub tag: Option<String>,
+}
+
+// a pose with the key it was sorted by and the filter's user's favorite flag and tags
+pub struct PoseMatch {
+    pub pose: PoseRecord,
+    pub sort_key: String,
+    pub favorite: bool,
+    pub tags: Vec<String>,
+}
+
+// a name as given and the catalog pose it stands for
+pub struct PoseNameMatch {
+    pub name: String,
+    pub pose_id: i32,
+}
+
+pub struct NewPose<'a> {
+    pub name: &'a str,
+    pub sanskrit_name: &'a str,
+    pub category: &'a str,
+    pub difficulty: &'a str,
+    pub description: &'a str,
+    pub cues: &'a [String],
+    pub contraindications: &'a [String],
+}
+
+// None leaves the field as it is
+#[derive(Default)]
+pub struct PoseChanges<'a> {
+    pub name: Option<&'a str>,
+    pub sanskrit_name: Option<&'a str>,
+    pub category: Option<&'a str>,
+    pub difficulty: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub cues: Option<&'a [String]>,
+    pub contraindications: Option<&'a [String]>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PoseWriteError {
+    #[error("no such pose")]
+    NotFound,
+    #[error("pose was changed by someone else")]
+    VersionMismatch,
+    #[error("a pose with this name exists")]
+    NameTaken,
+    #[error("database error")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl PoseWriteError {
+    fn from_query(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("23505") => {
+                PoseWriteError::NameTaken
+            }
+            _ => {
+                tracing::error!("Failed to execute query: {:?}", error);
+                PoseWriteError::DatabaseError(error)
+            }
+        }
+    }
+}
+
+impl YogaDatabase {
+    // One page of the poses matching `filter`, in the order of its sort, after the pose with
+    // the sort key and id `after` if given.
+    pub async fn search_poses(
+        &self,
+        filter: &PoseFilter,
+        after: Option<(&str, i32)>,
+        limit: i64,
+    ) -> Result<Vec<PoseMatch>, sqlx::Error> {
+        let after_key = after.map(|(key, _)| key);
+        let after_id = after.map(|(_, pose_id)| pose_id);
+        let rows = sqlx::query!(
+            r#"WITH matches AS (
+                SELECT p.*,
+                CASE
+                    WHEN $4 = 'relevance' AND $1::text IS NOT NULL
+                        -- normalized into [0, 1), ascending text order is descending rank
+                        THEN to_char(1 - ts_rank(p.search, to_tsquery('simple', immutable_unaccent($1)), 32),
+                            'FM0.000000000')
+                    WHEN $4 = 'sanskrit_name' THEN lower(p.sanskrit_name)
+                    WHEN $4 = 'difficulty' THEN
+                        CASE p.difficulty WHEN 'beginner' THEN '1' WHEN 'intermediate' THEN '2' ELSE '3' END
+                        || lower(p.name)
+                    ELSE lower(p.name)
+                END AS sort_key
+                FROM pose p
+                WHERE p.deleted_at IS NULL
+                AND ($1::text IS NULL OR p.search @@ to_tsquery('simple', immutable_unaccent($1)))
+                AND ($2::text IS NULL OR p.category = $2)
+                AND ($3::text IS NULL OR p.difficulty = $3)
+                AND (NOT $9 OR EXISTS (SELECT 1 FROM pose_favorite f
+                    WHERE f.user_id = $8 AND f.pose_id = p.pose_id))
+                AND ($10::text IS NULL OR EXISTS (SELECT 1 FROM pose_tag t
+                    WHERE t.user_id = $8 AND t.pose_id = p.pose_id AND t.tag = $10))
+            )
+            SELECT pose_id AS "pose_id!", name AS "name!", sanskrit_name AS "sanskrit_name!",
+            category AS "category!", difficulty AS "difficulty!", description AS "description!",
+            cues AS "cues!", contraindications AS "contraindications!", image_id,
+            version AS "version!", updated_at AS "updated_at!", sort_key AS "sort_key!",
+            EXISTS (SELECT 1 FROM pose_favorite f WHERE f.user_id = $8 AND f.pose_id = matches.pose_id)
+                AS "favorite!",
+            ARRAY(SELECT t.tag FROM pose_tag t WHERE t.user_id = $8 AND t.pose_id = matches.pose_id
+                ORDER BY t.tag) AS "tags!"
+            FROM matches
+            WHERE $5::text IS NULL OR (sort_key, pose_id) > ($5, $6)
+            ORDER BY sort_key, pose_id
+            LIMIT $7"#,
+            filter.query,
+            filter.category,
+            filter.difficulty,
+            filter.sort.as_ref(),
+            after_key,
+            after_id,
+            limit,
+            filter.user_id,
+            filter.favorites_only,
+            filter.tag
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(rows
+            .into_iter()
+            .map(|row| PoseMatch {
+                sort_key: row.sort_key,
+                favorite: row.favorite,
+                tags: row.tags,
+                pose: PoseRecord {
+                    pose_id: row.pose_id,
+                    name: row.name,
+                    sanskrit_name: row.sanskrit_name,
+                    category: row.category,
+                    difficulty: row.difficulty,
+                    description: row.description,
+                    cues: row.cues,
+                    contraindications: row.contraindications,
+                    image_id: row.image_id,
+                    version: row.version,
+                    updated_at: row.updated_at,
+                },
+            })
+            .collect())
+    }
+
+    pub async fn count_poses(&self, filter: &PoseFilter) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!" FROM pose
+            WHERE deleted_at IS NULL
+            AND ($1::text IS NULL OR search @@ to_tsquery('simple', immutable_unaccent($1)))
+            AND ($2::text IS NULL OR category = $2)
+            AND ($3::text IS NULL OR difficulty = $3)
+            AND (NOT $5 OR EXISTS (SELECT 1 FROM pose_favorite f
+                WHERE f.user_id = $4 AND f.pose_id = pose.pose_id))
+            AND ($6::text IS NULL OR EXISTS (SELECT 1 FROM pose_tag t
+                WHERE t.user_id = $4 AND t.pose_id = pose.pose_id AND t.tag = $6))"#,
+            filter.query,
+            filter.category,
+            filter.difficulty,
+            filter.user_id,
+            filter.favorites_only,
+            filter.tag
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(count.count)
+    }
+
+    pub async fn get_pose(&self, pose_id: i32) -> Result<Option<PoseRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            PoseRecord,
+            r#"SELECT pose_id, name, sanskrit_name, category, difficulty, description, cues,
+            contraindications, image_id, version, updated_at
+            FROM pose WHERE pose_id = $1 AND deleted_at IS NULL"#,
+            pose_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // The poses of the catalog called one of `names`, in English or Sanskrit, regardless of case
+    // and accents. English wins when a name is both. Names without a pose are left out.
+    pub async fn match_pose_names(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<PoseNameMatch>, sqlx::Error> {
+        sqlx::query_as!(
+            PoseNameMatch,
+            r#"SELECT DISTINCT ON (wanted.name) wanted.name AS "name!", p.pose_id
+            FROM UNNEST($1::text[]) AS wanted (name)
+            JOIN pose p ON p.deleted_at IS NULL
+                AND lower(immutable_unaccent(wanted.name)) IN
+                    (lower(immutable_unaccent(p.name)), lower(immutable_unaccent(p.sanskrit_name)))
+            ORDER BY wanted.name,
+                lower(immutable_unaccent(wanted.name)) = lower(immutable_unaccent(p.name)) DESC,
+                p.pose_id"#,
+            names
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn insert_pose(&self, pose: &NewPose<'_>) -> Result<PoseRecord, PoseWriteError> {
+        sqlx::query_as!(
+            PoseRecord,
+            r#"INSERT INTO pose (name, sanskrit_name, category, difficulty, description, cues,
+            contraindications)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING pose_id, name, sanskrit_name, category, difficulty, description, cues,
+            contraindications, image_id, version, updated_at"#,
+            pose.name,
+            pose.sanskrit_name,
+            pose.category,
+            pose.difficulty,
+            pose.description,
+            pose.cues,
+            pose.contraindications
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(PoseWriteError::from_query)
+    }
+
+    // Only if the pose is still at `version`.
+    pub async fn update_pose(
+        &self,
+        pose_id: i32,
+        version: i32,
+        changes: &PoseChanges<'_>,
+    ) -> Result<PoseRecord, PoseWriteError> {
+        let updated = sqlx::query_as!(
+            PoseRecord,
+            r#"UPDATE pose SET
+            name = COALESCE($3, name),
+            sanskrit_name = COALESCE($4, sanskrit_name),
+            category = COALESCE($5, category),
+            difficulty = COALESCE($6, difficulty),
+            description = COALESCE($7, description),
+            cues = COALESCE($8, cues),
+            contraindications = COALESCE($9, contraindications),
+            version = version + 1,
+            updated_at = now()
+            WHERE pose_id = $1 AND version = $2 AND deleted_at IS NULL
+            RETURNING pose_id, name, sanskrit_name, category, difficulty, description, cues,
+            contraindications, image_id, version, updated_at"#,
+            pose_id,
+            version,
+            changes.name,
+            changes.sanskrit_name,
+            changes.category,
+            changes.difficulty,
+            changes.description,
+            changes.cues,
+            changes.contraindications
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(PoseWriteError::from_query)?;
+        match updated {
+            Some(pose) => Ok(pose),
+            None => Err(self.missed_write(pose_id).await),
+        }
+    }
+
+    // Soft, sequences and practice logs may still point at the pose.
+    pub async fn delete_pose(&self, pose_id: i32, version: i32) -> Result<(), PoseWriteError> {
+        let deleted = sqlx::query!(
+            r#"UPDATE pose SET deleted_at = now(), version = version + 1, updated_at = now()
+            WHERE pose_id = $1 AND version = $2 AND deleted_at IS NULL"#,
+            pose_id,
+            version
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(PoseWriteError::from_query)?;
+        if deleted.rows_affected() == 0 {
+            return Err(self.missed_write(pose_id).await);
+        }
+        Ok(())
+    }
+
+    // Swaps the pose's image for `image`, or removes it with None. The previous image's media
+    // row goes too and comes back, so its files can follow.
+    pub async fn set_pose_image(
+        &self,
+        pose_id: i32,
+        version: i32,
+        image: Option<&NewMedia<'_>>,
+    ) -> Result<(PoseRecord, Option<MediaRecord>), PoseWriteError> {
+        let mut transaction = self.pool.begin().await.map_err(PoseWriteError::from_query)?;
+        let previous = sqlx::query!(
+            "SELECT image_id FROM pose WHERE pose_id = $1 AND version = $2 AND deleted_at IS NULL FOR UPDATE",
+            pose_id,
+            version
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .map_err(PoseWriteError::from_query)?;
+        let previous = match previous {
+            Some(previous) => previous.image_id,
+            None => return Err(self.missed_write(pose_id).await),
+        };
+        if let Some(image) = image {
+            sqlx::query!(
+                r#"INSERT INTO media
+                (media_id, content_type, byte_size, width, height, thumbnail_widths, uploaded_by)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+                image.media_id,
+                image.content_type,
+                image.byte_size,
+                image.width,
+                image.height,
+                image.thumbnail_widths,
+                image.uploaded_by
+            )
+            .execute(&mut transaction)
+            .await
+            .map_err(PoseWriteError::from_query)?;
+        }
+        let pose = sqlx::query_as!(
+            PoseRecord,
+            r#"UPDATE pose SET image_id = $2, version = version + 1, updated_at = now()
+            WHERE pose_id = $1
+            RETURNING pose_id, name, sanskrit_name, category, difficulty, description, cues,
+            contraindications, image_id, version, updated_at"#,
+            pose_id,
+            image.map(|image| image.media_id)
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .map_err(PoseWriteError::from_query)?;
+        let previous = match previous {
+            Some(previous) => sqlx::query_as!(
+                MediaRecord,
+                r#"DELETE FROM media WHERE media_id = $1
+                RETURNING media_id, content_type, byte_size, width, height, thumbnail_widths"#,
+                previous
+            )
+            .fetch_optional(&mut transaction)
+            .await
+            .map_err(PoseWriteError::from_query)?,
+            None => None,
+        };
+        transaction.commit().await.map_err(PoseWriteError::from_query)?;
+        Ok((pose, previous))
+    }
+
+    // why a write for a version changed nothing
+    async fn missed_write(&self, pose_id: i32) -> PoseWriteError {
+        match self.get_pose(pose_id).await {
+            Ok(Some(_)) => PoseWriteError::VersionMismatch,
+            Ok(None) => PoseWriteError::NotFound,
+            Err(error) => PoseWriteError::DatabaseError(error),
+        }
+    }
+}
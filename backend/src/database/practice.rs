@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+#[derive(serde::Serialize)]
+pub struct PracticeRecord {
+    pub practice_id: Uuid,
+    pub sequence_id: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub effort: Option<i32>,
+    pub notes: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct PracticeStepRecord {
+    #[serde(skip)]
+    pub practice_id: Uuid,
+    pub position: i32,
+    pub pose_id: i32,
+    pub pose_name: String,
+    pub actual_seconds: i32,
+    pub side: Option<String>,
+}
+
+pub struct NewPractice<'a> {
+    pub sequence_id: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub effort: Option<i32>,
+    pub notes: &'a str,
+}
+
+pub struct NewPracticeStep<'a> {
+    pub pose_id: i32,
+    pub actual_seconds: i32,
+    pub side: Option<&'a str>,
+}
+
+impl YogaDatabase {
+    pub async fn insert_practice(
+        &self,
+        user_id: Uuid,
+        practice: &NewPractice<'_>,
+        steps: &[NewPracticeStep<'_>],
+    ) -> Result<Uuid, sqlx::Error> {
+        let practice_id = Uuid::new_v4();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO practice_session
+            (practice_id, user_id, sequence_id, started_at, ended_at, effort, notes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+            practice_id,
+            user_id,
+            practice.sequence_id,
+            practice.started_at,
+            practice.ended_at,
+            practice.effort,
+            practice.notes
+        )
+        .execute(&mut transaction)
+        .await?;
+        for (position, step) in steps.iter().enumerate() {
+            sqlx::query!(
+                r#"INSERT INTO practice_step (practice_id, position, pose_id, actual_seconds, side)
+                VALUES ($1, $2, $3, $4, $5)"#,
+                practice_id,
+                position as i32,
+                step.pose_id,
+                step.actual_seconds,
+                step.side
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        sqlx::query!("DELETE FROM practice_stats_cache WHERE user_id = $1", user_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(practice_id)
+    }
+
+    // Started within [from, to), latest first.
+    pub async fn list_practice(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PracticeRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            PracticeRecord,
+            r#"SELECT practice_id, sequence_id, started_at, ended_at, effort, notes
+            FROM practice_session
+            WHERE user_id = $1 AND started_at >= $2 AND started_at < $3
+            ORDER BY started_at DESC"#,
+            user_id,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_practice(
+        &self,
+        user_id: Uuid,
+        practice_id: Uuid,
+    ) -> Result<Option<PracticeRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            PracticeRecord,
+            r#"SELECT practice_id, sequence_id, started_at, ended_at, effort, notes
+            FROM practice_session WHERE practice_id = $1 AND user_id = $2"#,
+            practice_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_practice_steps(
+        &self,
+        practice_ids: &[Uuid],
+    ) -> Result<Vec<PracticeStepRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            PracticeStepRecord,
+            r#"SELECT st.practice_id, st.position, st.pose_id, p.name AS pose_name,
+            st.actual_seconds, st.side
+            FROM practice_step st JOIN pose p ON p.pose_id = st.pose_id
+            WHERE st.practice_id = ANY($1)
+            ORDER BY st.practice_id, st.position"#,
+            practice_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{YogaDatabase, YogaDatabaseError};
+
+pub enum RefreshOutcome {
+    // the presented token was current, it is now used and `new_token_hash` replaces it
+    Rotated {
+        user_id: Uuid,
+        family_id: Uuid,
+        client_id: Option<String>,
+        scope: Option<String>,
+    },
+    // the presented token was already used or revoked, the whole family has been revoked
+    Reused { user_id: Uuid, family_id: Uuid },
+    Expired,
+    NotFound,
+}
+
+impl YogaDatabase {
+    pub async fn insert_refresh_token(
+        &self,
+        family_id: Uuid,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        client_id: Option<&str>,
+        scope: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO refresh_token (token_hash, family_id, user_id, expires_at, client_id, scope)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            token_hash,
+            family_id,
+            user_id,
+            expires_at,
+            client_id,
+            scope
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // `client_id` is the client presenting the token, None for our own frontend.
+    // Tokens only work for the client they were issued to.
+    pub async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        new_expires_at: DateTime<Utc>,
+        client_id: Option<&str>,
+    ) -> Result<RefreshOutcome, YogaDatabaseError> {
+        let mut transaction = self.pool.begin().await?;
+        let current = sqlx::query!(
+            r#"SELECT family_id, user_id, expires_at, used_at, revoked_at, client_id, scope
+            FROM refresh_token WHERE token_hash = $1 AND client_id IS NOT DISTINCT FROM $2
+            FOR UPDATE"#,
+            token_hash,
+            client_id
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+
+        let current = match current {
+            Some(current) => current,
+            None => return Ok(RefreshOutcome::NotFound),
+        };
+
+        if current.used_at.is_some() || current.revoked_at.is_some() {
+            // someone is replaying an old token, we can't tell whether it is the thief or the
+            // legitimate client so nobody in this family gets to keep going
+            sqlx::query!(
+                "UPDATE refresh_token SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+                current.family_id
+            )
+            .execute(&mut transaction)
+            .await?;
+            transaction.commit().await?;
+            return Ok(RefreshOutcome::Reused {
+                user_id: current.user_id,
+                family_id: current.family_id,
+            });
+        }
+
+        if current.expires_at < Utc::now() {
+            return Ok(RefreshOutcome::Expired);
+        }
+
+        sqlx::query!(
+            "UPDATE refresh_token SET used_at = now() WHERE token_hash = $1",
+            token_hash
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO refresh_token (token_hash, family_id, user_id, expires_at, client_id, scope)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            new_token_hash,
+            current.family_id,
+            current.user_id,
+            new_expires_at,
+            current.client_id,
+            current.scope
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(RefreshOutcome::Rotated {
+            user_id: current.user_id,
+            family_id: current.family_id,
+            client_id: current.client_id,
+            scope: current.scope,
+        })
+    }
+
+    pub async fn revoke_refresh_family(&self, family_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE refresh_token SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+            family_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    pub async fn revoke_user_refresh_tokens(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE refresh_token SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+#[derive(serde::Serialize)]
+pub struct SequenceSummary {
+    pub sequence_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub step_count: i64,
+    // of the steps held for a duration, breaths aren't counted
+    pub total_seconds: i64,
+    pub visibility: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SequenceRecord {
+    pub sequence_id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub visibility: String,
+    pub share_token: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// what anyone with the link sees of a sequence, nothing about its owner
+#[derive(serde::Serialize)]
+pub struct SharedSequenceRecord {
+    #[serde(skip)]
+    pub sequence_id: Uuid,
+    pub share_token: String,
+    pub name: String,
+    pub description: String,
+    pub visibility: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublicSequenceSummary {
+    pub share_token: String,
+    pub name: String,
+    pub description: String,
+    pub step_count: i64,
+    pub total_seconds: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SequenceStepRecord {
+    pub step_id: Uuid,
+    pub position: i32,
+    pub pose_id: i32,
+    pub pose_name: String,
+    pub duration_seconds: Option<i32>,
+    pub breaths: Option<i32>,
+    pub side: Option<String>,
+    pub notes: String,
+}
+
+pub struct NewSequenceStep<'a> {
+    pub pose_id: i32,
+    pub duration_seconds: Option<i32>,
+    pub breaths: Option<i32>,
+    pub side: Option<&'a str>,
+    pub notes: &'a str,
+}
+
+// steps are numbered from 0 in the order given
+async fn insert_steps(
+    transaction: &mut Transaction<'_, Postgres>,
+    sequence_id: Uuid,
+    steps: &[NewSequenceStep<'_>],
+) -> Result<(), sqlx::Error> {
+    for (position, step) in steps.iter().enumerate() {
+        sqlx::query!(
+            r#"INSERT INTO sequence_step
+            (step_id, sequence_id, position, pose_id, duration_seconds, breaths, side, notes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            Uuid::new_v4(),
+            sequence_id,
+            position as i32,
+            step.pose_id,
+            step.duration_seconds,
+            step.breaths,
+            step.side,
+            step.notes
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+impl YogaDatabase {
+    pub async fn list_sequences(&self, user_id: Uuid) -> Result<Vec<SequenceSummary>, sqlx::Error> {
+        sqlx::query_as!(
+            SequenceSummary,
+            r#"SELECT s.sequence_id, s.name, s.description,
+            (SELECT COUNT(*) FROM sequence_step st WHERE st.sequence_id = s.sequence_id)
+                AS "step_count!",
+            (SELECT COALESCE(SUM(st.duration_seconds), 0) FROM sequence_step st
+                WHERE st.sequence_id = s.sequence_id) AS "total_seconds!",
+            s.visibility, s.created_at, s.updated_at
+            FROM sequence s WHERE s.user_id = $1
+            ORDER BY s.updated_at DESC"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // None unless the sequence exists and belongs to the user.
+    pub async fn get_sequence(
+        &self,
+        user_id: Uuid,
+        sequence_id: Uuid,
+    ) -> Result<Option<SequenceRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            SequenceRecord,
+            r#"SELECT sequence_id, user_id, name, description, visibility, share_token, created_at,
+            updated_at
+            FROM sequence WHERE sequence_id = $1 AND user_id = $2"#,
+            sequence_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_sequence_steps(
+        &self,
+        sequence_id: Uuid,
+    ) -> Result<Vec<SequenceStepRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            SequenceStepRecord,
+            r#"SELECT st.step_id, st.position, st.pose_id, p.name AS pose_name, st.duration_seconds,
+            st.breaths, st.side, st.notes
+            FROM sequence_step st JOIN pose p ON p.pose_id = st.pose_id
+            WHERE st.sequence_id = $1
+            ORDER BY st.position"#,
+            sequence_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Of `pose_ids` those that aren't in the catalog, or were deleted from it unless
+    // `include_deleted`.
+    pub async fn missing_poses(
+        &self,
+        pose_ids: &[i32],
+        include_deleted: bool,
+    ) -> Result<Vec<i32>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT wanted.pose_id AS "pose_id!"
+            FROM UNNEST($1::int[]) AS wanted (pose_id)
+            WHERE NOT EXISTS (SELECT 1 FROM pose p
+                WHERE p.pose_id = wanted.pose_id AND ($2 OR p.deleted_at IS NULL))
+            ORDER BY 1"#,
+            pose_ids,
+            include_deleted
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(rows.into_iter().map(|row| row.pose_id).collect())
+    }
+
+    pub async fn create_sequence(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        description: &str,
+        steps: &[NewSequenceStep<'_>],
+    ) -> Result<Uuid, sqlx::Error> {
+        let sequence_id = Uuid::new_v4();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO sequence (sequence_id, user_id, name, description) VALUES ($1, $2, $3, $4)",
+            sequence_id,
+            user_id,
+            name,
+            description
+        )
+        .execute(&mut transaction)
+        .await?;
+        insert_steps(&mut transaction, sequence_id, steps).await?;
+        transaction.commit().await?;
+        Ok(sequence_id)
+    }
+
+    // Replaces name, description and all steps. False if the user has no such sequence.
+    pub async fn replace_sequence(
+        &self,
+        user_id: Uuid,
+        sequence_id: Uuid,
+        name: &str,
+        description: &str,
+        steps: &[NewSequenceStep<'_>],
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let updated = sqlx::query!(
+            r#"UPDATE sequence SET name = $3, description = $4, updated_at = now()
+            WHERE sequence_id = $1 AND user_id = $2"#,
+            sequence_id,
+            user_id,
+            name,
+            description
+        )
+        .execute(&mut transaction)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!("DELETE FROM sequence_step WHERE sequence_id = $1", sequence_id)
+            .execute(&mut transaction)
+            .await?;
+        insert_steps(&mut transaction, sequence_id, steps).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // `step_ids` has to hold every step of the sequence exactly once, false otherwise.
+    pub async fn reorder_sequence_steps(
+        &self,
+        sequence_id: Uuid,
+        step_ids: &[Uuid],
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let moved = sqlx::query!(
+            r#"UPDATE sequence_step st SET position = new_order.position - 1
+            FROM UNNEST($2::uuid[]) WITH ORDINALITY AS new_order (step_id, position)
+            WHERE st.step_id = new_order.step_id AND st.sequence_id = $1"#,
+            sequence_id,
+            step_ids
+        )
+        .execute(&mut transaction)
+        .await?;
+        let step_count = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!" FROM sequence_step WHERE sequence_id = $1"#,
+            sequence_id
+        )
+        .fetch_one(&mut transaction)
+        .await?
+        .count;
+        if moved.rows_affected() as i64 != step_count || step_ids.len() as i64 != step_count {
+            transaction.rollback().await?;
+            return Ok(false);
+        }
+        sqlx::query!("UPDATE sequence SET updated_at = now() WHERE sequence_id = $1", sequence_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // A copy with all steps for the same user. None if the user has no such sequence.
+    pub async fn duplicate_sequence(
+        &self,
+        user_id: Uuid,
+        sequence_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let copy_id = Uuid::new_v4();
+        let mut transaction = self.pool.begin().await?;
+        let copied = sqlx::query!(
+            r#"INSERT INTO sequence (sequence_id, user_id, name, description)
+            SELECT $3, user_id, $4, description FROM sequence
+            WHERE sequence_id = $1 AND user_id = $2"#,
+            sequence_id,
+            user_id,
+            copy_id,
+            name
+        )
+        .execute(&mut transaction)
+        .await?;
+        if copied.rows_affected() == 0 {
+            return Ok(None);
+        }
+        sqlx::query!(
+            r#"INSERT INTO sequence_step
+            (step_id, sequence_id, position, pose_id, duration_seconds, breaths, side, notes)
+            SELECT gen_random_uuid(), $2, position, pose_id, duration_seconds, breaths, side, notes
+            FROM sequence_step WHERE sequence_id = $1"#,
+            sequence_id,
+            copy_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(copy_id))
+    }
+
+    pub async fn delete_sequence(&self, user_id: Uuid, sequence_id: Uuid) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM sequence WHERE sequence_id = $1 AND user_id = $2",
+            sequence_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    // Private drops the share token, unlisted and public keep the one there is or take
+    // `new_token`. None if the user has no such sequence.
+    pub async fn set_sequence_visibility(
+        &self,
+        user_id: Uuid,
+        sequence_id: Uuid,
+        visibility: &str,
+        new_token: &str,
+    ) -> Result<Option<SequenceRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            SequenceRecord,
+            r#"UPDATE sequence SET visibility = $3,
+            share_token = CASE WHEN $3 = 'private' THEN NULL ELSE COALESCE(share_token, $4) END
+            WHERE sequence_id = $1 AND user_id = $2
+            RETURNING sequence_id, user_id, name, description, visibility, share_token, created_at,
+            updated_at"#,
+            sequence_id,
+            user_id,
+            visibility,
+            new_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Replaces the share token, the old links stop working. None unless the user has the
+    // sequence and shares it.
+    pub async fn replace_share_token(
+        &self,
+        user_id: Uuid,
+        sequence_id: Uuid,
+        new_token: &str,
+    ) -> Result<Option<SequenceRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            SequenceRecord,
+            r#"UPDATE sequence SET share_token = $3
+            WHERE sequence_id = $1 AND user_id = $2 AND visibility <> 'private'
+            RETURNING sequence_id, user_id, name, description, visibility, share_token, created_at,
+            updated_at"#,
+            sequence_id,
+            user_id,
+            new_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Nothing of a deleted or disabled account is shared, until the account is back.
+    pub async fn get_shared_sequence(
+        &self,
+        share_token: &str,
+    ) -> Result<Option<SharedSequenceRecord>, sqlx::Error> {
+        sqlx::query_as!(
+            SharedSequenceRecord,
+            r#"SELECT s.sequence_id, s.share_token AS "share_token!", s.name, s.description,
+            s.visibility, s.updated_at
+            FROM sequence s
+            JOIN user_profile u ON u.user_id = s.user_id
+                AND u.deleted_at IS NULL AND u.disabled_at IS NULL
+            WHERE s.share_token = $1 AND s.visibility <> 'private'"#,
+            share_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // The public sequences, last changed first.
+    pub async fn list_public_sequences(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PublicSequenceSummary>, sqlx::Error> {
+        sqlx::query_as!(
+            PublicSequenceSummary,
+            r#"SELECT s.share_token AS "share_token!", s.name, s.description,
+            (SELECT COUNT(*) FROM sequence_step st WHERE st.sequence_id = s.sequence_id)
+                AS "step_count!",
+            (SELECT COALESCE(SUM(st.duration_seconds), 0) FROM sequence_step st
+                WHERE st.sequence_id = s.sequence_id) AS "total_seconds!",
+            s.updated_at
+            FROM sequence s
+            JOIN user_profile u ON u.user_id = s.user_id
+                AND u.deleted_at IS NULL AND u.disabled_at IS NULL
+            WHERE s.visibility = 'public' AND s.share_token IS NOT NULL
+            ORDER BY s.updated_at DESC, s.sequence_id
+            LIMIT $1 OFFSET $2"#,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // A private copy of a shared sequence for the user, under `name` or the original's.
+    // None if nothing is shared under the token.
+    pub async fn copy_shared_sequence(
+        &self,
+        user_id: Uuid,
+        share_token: &str,
+        name: Option<&str>,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let copy_id = Uuid::new_v4();
+        let mut transaction = self.pool.begin().await?;
+        let original = sqlx::query!(
+            r#"SELECT s.sequence_id, s.name, s.description FROM sequence s
+            JOIN user_profile u ON u.user_id = s.user_id
+                AND u.deleted_at IS NULL AND u.disabled_at IS NULL
+            WHERE s.share_token = $1 AND s.visibility <> 'private'"#,
+            share_token
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        let original = match original {
+            Some(original) => original,
+            None => return Ok(None),
+        };
+        sqlx::query!(
+            "INSERT INTO sequence (sequence_id, user_id, name, description) VALUES ($1, $2, $3, $4)",
+            copy_id,
+            user_id,
+            name.unwrap_or(&original.name),
+            original.description
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO sequence_step
+            (step_id, sequence_id, position, pose_id, duration_seconds, breaths, side, notes)
+            SELECT gen_random_uuid(), $2, position, pose_id, duration_seconds, breaths, side, notes
+            FROM sequence_step WHERE sequence_id = $1"#,
+            original.sequence_id,
+            copy_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(copy_id))
+    }
+}
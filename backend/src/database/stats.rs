@@ -0,0 +1,226 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PracticeStats {
+    pub timezone: String,
+    pub session_count: i64,
+    pub total_seconds: i64,
+    // oldest first, the current week last
+    pub weeks: Vec<WeekStats>,
+    // days in a row with practice, up to today or yesterday
+    pub current_streak_days: i64,
+    pub longest_streak_days: i64,
+    pub top_poses: Vec<PoseStats>,
+    pub categories: Vec<CategoryStats>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WeekStats {
+    // the monday
+    pub week_start: NaiveDate,
+    pub sessions: i64,
+    pub seconds: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PoseStats {
+    pub pose_id: i32,
+    pub pose_name: String,
+    pub times: i64,
+    pub seconds: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CategoryStats {
+    pub category: String,
+    pub times: i64,
+    pub seconds: i64,
+}
+
+impl YogaDatabase {
+    pub async fn get_user_timezone(&self, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        let result = sqlx::query!("SELECT timezone FROM user_profile WHERE user_id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to execute query: {:?}", e);
+                e
+            })?;
+        Ok(result.map(|r| r.timezone))
+    }
+
+    // False unless `timezone` is one Postgres knows, like Europe/Vienna.
+    pub async fn set_user_timezone(&self, user_id: Uuid, timezone: &str) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let updated = sqlx::query!(
+            r#"UPDATE user_profile SET timezone = $2
+            WHERE user_id = $1 AND EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $2)"#,
+            user_id,
+            timezone
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!("DELETE FROM practice_stats_cache WHERE user_id = $1", user_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(updated.rows_affected() > 0)
+    }
+
+    // The cached stats as stored, if computed for `timezone` less than `max_age` ago and still
+    // on the same day there.
+    pub async fn get_cached_stats(
+        &self,
+        user_id: Uuid,
+        timezone: &str,
+        max_age: chrono::Duration,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"SELECT stats FROM practice_stats_cache
+            WHERE user_id = $1 AND timezone = $2 AND computed_at > $3
+            AND (computed_at AT TIME ZONE $2)::date = (now() AT TIME ZONE $2)::date"#,
+            user_id,
+            timezone,
+            Utc::now() - max_age
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(result.map(|r| r.stats))
+    }
+
+    pub async fn store_cached_stats(
+        &self,
+        user_id: Uuid,
+        timezone: &str,
+        stats: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO practice_stats_cache (user_id, timezone, stats) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE SET timezone = $2, stats = $3, computed_at = now()"#,
+            user_id,
+            timezone,
+            stats
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // Everything aggregated by Postgres: the last `week_count` weeks and the `top_count` poses
+    // practiced longest.
+    pub async fn compute_practice_stats(
+        &self,
+        user_id: Uuid,
+        timezone: &str,
+        week_count: i32,
+        top_count: i64,
+    ) -> Result<PracticeStats, sqlx::Error> {
+        let totals = sqlx::query!(
+            r#"SELECT COUNT(*) AS "session_count!",
+            COALESCE(SUM(EXTRACT(EPOCH FROM ended_at - started_at)), 0)::bigint AS "total_seconds!"
+            FROM practice_session WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let weeks = sqlx::query_as!(
+            WeekStats,
+            r#"WITH weeks AS (
+                SELECT generate_series(
+                    date_trunc('week', now() AT TIME ZONE $2) - ($3::int - 1) * interval '1 week',
+                    date_trunc('week', now() AT TIME ZONE $2),
+                    interval '1 week'
+                ) AS week_start
+            )
+            SELECT w.week_start::date AS "week_start!", COUNT(p.practice_id) AS "sessions!",
+            COALESCE(SUM(EXTRACT(EPOCH FROM p.ended_at - p.started_at)), 0)::bigint AS "seconds!"
+            FROM weeks w
+            LEFT JOIN practice_session p ON p.user_id = $1
+                AND date_trunc('week', p.started_at AT TIME ZONE $2) = w.week_start
+            GROUP BY w.week_start
+            ORDER BY w.week_start"#,
+            user_id,
+            timezone,
+            week_count
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // days in a row share the same day minus row number
+        let streaks = sqlx::query!(
+            r#"WITH days AS (
+                SELECT DISTINCT (started_at AT TIME ZONE $2)::date AS day
+                FROM practice_session WHERE user_id = $1
+            ), runs AS (
+                SELECT day, day - (ROW_NUMBER() OVER (ORDER BY day))::int AS run FROM days
+            ), streaks AS (
+                SELECT MAX(day) AS last_day, COUNT(*) AS length FROM runs GROUP BY run
+            )
+            SELECT COALESCE(MAX(length), 0) AS "longest!",
+            COALESCE(MAX(length) FILTER (WHERE last_day >= (now() AT TIME ZONE $2)::date - 1), 0)
+                AS "current!"
+            FROM streaks"#,
+            user_id,
+            timezone
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let top_poses = sqlx::query_as!(
+            PoseStats,
+            r#"SELECT st.pose_id, p.name AS pose_name, COUNT(*) AS "times!",
+            COALESCE(SUM(st.actual_seconds), 0)::bigint AS "seconds!"
+            FROM practice_step st
+            JOIN practice_session ps ON ps.practice_id = st.practice_id
+            JOIN pose p ON p.pose_id = st.pose_id
+            WHERE ps.user_id = $1
+            GROUP BY st.pose_id, p.name
+            ORDER BY 4 DESC, 3 DESC, p.name
+            LIMIT $2"#,
+            user_id,
+            top_count
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let categories = sqlx::query_as!(
+            CategoryStats,
+            r#"SELECT p.category, COUNT(*) AS "times!",
+            COALESCE(SUM(st.actual_seconds), 0)::bigint AS "seconds!"
+            FROM practice_step st
+            JOIN practice_session ps ON ps.practice_id = st.practice_id
+            JOIN pose p ON p.pose_id = st.pose_id
+            WHERE ps.user_id = $1
+            GROUP BY p.category
+            ORDER BY 3 DESC, p.category"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(PracticeStats {
+            timezone: timezone.to_string(),
+            session_count: totals.session_count,
+            total_seconds: totals.total_seconds,
+            weeks,
+            current_streak_days: streaks.current,
+            longest_streak_days: streaks.longest,
+            top_poses,
+            categories,
+            computed_at: Utc::now(),
+        })
+    }
+}
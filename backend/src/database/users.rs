@@ -0,0 +1,220 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::YogaDatabase;
+use crate::auth::Role;
+
+// Whether a user, and one of their sessions (refresh token family or impersonation), may
+// still act.
+pub struct UserStanding {
+    pub disabled: bool,
+    pub deleted: bool,
+    pub session_revoked: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserSummary {
+    pub user_id: Uuid,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub disabled_reason: Option<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+// a refresh token family that can still be refreshed
+#[derive(serde::Serialize)]
+pub struct ActiveSession {
+    pub family_id: Uuid,
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+// ILIKE pattern matching `search` anywhere, its own % and _ taken literally
+fn contains_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl YogaDatabase {
+    // None if there is no such user.
+    pub async fn get_user_standing(
+        &self,
+        user_id: Uuid,
+        session_id: Option<Uuid>,
+    ) -> Result<Option<UserStanding>, sqlx::Error> {
+        sqlx::query_as!(
+            UserStanding,
+            r#"SELECT disabled_at IS NOT NULL AS "disabled!", deleted_at IS NOT NULL AS "deleted!",
+            ($2::uuid IS NOT NULL AND (
+                EXISTS (SELECT 1 FROM refresh_token WHERE family_id = $2 AND revoked_at IS NOT NULL)
+                OR EXISTS (SELECT 1 FROM impersonation WHERE impersonation_id = $2
+                    AND (ended_at IS NOT NULL OR expires_at <= now()))
+            )) AS "session_revoked!"
+            FROM user_profile WHERE user_id = $1"#,
+            user_id,
+            session_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // By email, matching the account's or any of its identities' email, or a provider subject.
+    pub async fn list_users(
+        &self,
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserSummary>, sqlx::Error> {
+        let pattern = search.map(contains_pattern);
+        sqlx::query_as!(
+            UserSummary,
+            r#"SELECT p.user_id, p.email,
+            COALESCE((SELECT array_agg(r.role ORDER BY r.role) FROM user_role r
+                WHERE r.user_id = p.user_id), '{}') AS "roles!",
+            (SELECT MAX(i.last_login_at) FROM user_identity i WHERE i.user_id = p.user_id)
+                AS last_login_at,
+            p.disabled_at, p.disabled_reason, p.deleted_at
+            FROM user_profile p
+            WHERE $1::text IS NULL
+            OR p.email ILIKE $1
+            OR EXISTS (SELECT 1 FROM user_identity i WHERE i.user_id = p.user_id
+                AND (i.email ILIKE $1 OR i.subject = $2))
+            ORDER BY p.email
+            LIMIT $3 OFFSET $4"#,
+            pattern,
+            search,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn get_user_summary(&self, user_id: Uuid) -> Result<Option<UserSummary>, sqlx::Error> {
+        sqlx::query_as!(
+            UserSummary,
+            r#"SELECT p.user_id, p.email,
+            COALESCE((SELECT array_agg(r.role ORDER BY r.role) FROM user_role r
+                WHERE r.user_id = p.user_id), '{}') AS "roles!",
+            (SELECT MAX(i.last_login_at) FROM user_identity i WHERE i.user_id = p.user_id)
+                AS last_login_at,
+            p.disabled_at, p.disabled_reason, p.deleted_at
+            FROM user_profile p WHERE p.user_id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    pub async fn list_active_sessions(&self, user_id: Uuid) -> Result<Vec<ActiveSession>, sqlx::Error> {
+        sqlx::query_as!(
+            ActiveSession,
+            r#"SELECT current.family_id, current.client_id, current.scope,
+            (SELECT MIN(first.issued_at) FROM refresh_token first
+                WHERE first.family_id = current.family_id) AS "started_at!",
+            current.issued_at AS last_refreshed_at, current.expires_at
+            FROM refresh_token current
+            WHERE current.user_id = $1 AND current.used_at IS NULL AND current.revoked_at IS NULL
+            AND current.expires_at > now()
+            ORDER BY current.issued_at DESC"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+    }
+
+    // Disabling also ends every session of the user. False if there is no such user.
+    pub async fn disable_user(&self, user_id: Uuid, reason: Option<&str>) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let disabled = sqlx::query!(
+            r#"UPDATE user_profile SET disabled_at = COALESCE(disabled_at, now()), disabled_reason = $2
+            WHERE user_id = $1"#,
+            user_id,
+            reason
+        )
+        .execute(&mut transaction)
+        .await?;
+        if disabled.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE refresh_token SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn enable_user(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let enabled = sqlx::query!(
+            "UPDATE user_profile SET disabled_at = NULL, disabled_reason = NULL WHERE user_id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(enabled.rows_affected() > 0)
+    }
+
+    // Granted here the role is 'local', a provider's next login doesn't take it away.
+    pub async fn grant_role(&self, user_id: Uuid, role: Role) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO user_role (user_id, role, source) VALUES ($1, $2, 'local')
+            ON CONFLICT (user_id, role) DO UPDATE SET source = 'local'"#,
+            user_id,
+            role.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    // A role a provider grants comes back with the next login through that provider.
+    pub async fn revoke_role(&self, user_id: Uuid, role: Role) -> Result<bool, sqlx::Error> {
+        let revoked = sqlx::query!(
+            "DELETE FROM user_role WHERE user_id = $1 AND role = $2",
+            user_id,
+            role.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+        Ok(revoked.rows_affected() > 0)
+    }
+}
@@ -3,13 +3,28 @@ pub mod routes;
 pub mod configuration;
 pub mod database;
 pub mod auth;
+pub mod rate_limit;
+pub mod csrf;
+pub mod account_purge;
+pub mod media;
 
 use std::collections::HashMap;
-use auth::{AuthClientType, AuthName};
+use auth::AuthClientType;
+use auth::providers::LoginProvider;
+use auth::jwt::TokenIssuer;
 
 pub struct YogaAppData {
-    pub oauth_clients: HashMap<AuthName, AuthClientType>,
+    // by provider name from configuration
+    pub oauth_clients: HashMap<String, AuthClientType>,
+    pub login_providers: Vec<LoginProvider>,
     pub host: String,
     pub after_login_url: String,
+    pub device_verification_url: String,
+    pub login_url: String,
+    pub consent_url: String,
     pub port: String,
+    pub token_issuer: TokenIssuer,
+    // how long a deleted account waits for the purge
+    pub deletion_grace_days: i64,
+    pub impersonation_minutes: i64,
 }
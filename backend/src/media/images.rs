@@ -0,0 +1,135 @@
+// What uploads may be and the thumbnails made of them. The type is sniffed from the bytes, the
+// content type a client declares isn't trusted.
+
+use std::io::Cursor;
+
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageOutputFormat};
+
+const THUMBNAIL_QUALITY: u8 = 82;
+
+#[derive(thiserror::Error, Debug)]
+pub enum UploadError {
+    #[error("only jpeg, png and webp images")]
+    UnsupportedType,
+    #[error("image is larger than {0} pixels on a side")]
+    TooLarge(u32),
+    #[error("not a readable image")]
+    Unreadable(#[from] image::ImageError),
+}
+
+pub struct ProcessedImage {
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    // (width, jpeg), narrowest first
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    }
+}
+
+pub fn extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        _ => "jpg",
+    }
+}
+
+fn jpeg(image: &DynamicImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut bytes = Vec::new();
+    // no alpha in jpeg
+    DynamicImage::ImageRgb8(image.to_rgb8()).write_to(
+        &mut Cursor::new(&mut bytes),
+        ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY),
+    )?;
+    Ok(bytes)
+}
+
+// Checks the upload and scales it down to each of `widths` narrower than itself. Busy for a
+// while with big images, run it in web::block.
+pub fn process(
+    bytes: &[u8],
+    max_dimension: u32,
+    widths: &[u32],
+) -> Result<ProcessedImage, UploadError> {
+    let content_type = sniff(bytes).ok_or(UploadError::UnsupportedType)?;
+    let format = match content_type {
+        "image/png" => ImageFormat::Png,
+        "image/webp" => ImageFormat::WebP,
+        _ => ImageFormat::Jpeg,
+    };
+    // from the header, a decompression bomb is never decoded
+    let (width, height) =
+        image::io::Reader::with_format(Cursor::new(bytes), format).into_dimensions()?;
+    if width > max_dimension || height > max_dimension {
+        return Err(UploadError::TooLarge(max_dimension));
+    }
+    let image = image::load_from_memory_with_format(bytes, format)?;
+
+    let mut widths = widths.to_vec();
+    widths.sort_unstable();
+    widths.dedup();
+    let mut thumbnails = Vec::new();
+    for thumbnail_width in widths.into_iter().filter(|w| *w > 0 && *w < width) {
+        // keeps the aspect ratio, the height is no limit
+        let thumbnail = image.resize(thumbnail_width, max_dimension, FilterType::Lanczos3);
+        thumbnails.push((thumbnail_width, jpeg(&thumbnail)?));
+    }
+    Ok(ProcessedImage {
+        content_type,
+        width,
+        height,
+        thumbnails,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, height, Rgba([40, 90, 160, 255])))
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn image_larger_than_the_max_dimension_is_refused() {
+        assert!(matches!(process(&png(120, 40), 100, &[]), Err(UploadError::TooLarge(100))));
+        assert!(matches!(process(&png(40, 120), 100, &[]), Err(UploadError::TooLarge(100))));
+    }
+
+    #[test]
+    fn image_at_the_max_dimension_is_taken() {
+        let processed = process(&png(100, 50), 100, &[]).unwrap();
+        assert_eq!(processed.content_type, "image/png");
+        assert_eq!((processed.width, processed.height), (100, 50));
+    }
+
+    #[test]
+    fn thumbnails_only_narrower_than_the_image() {
+        let processed = process(&png(100, 50), 100, &[480, 40, 0, 40, 100]).unwrap();
+        let widths: Vec<u32> = processed.thumbnails.iter().map(|(width, _)| *width).collect();
+        assert_eq!(widths, vec![40]);
+        assert_eq!(sniff(&processed.thumbnails[0].1), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn other_types_are_refused() {
+        assert!(matches!(
+            process(b"GIF89a\x01\x00\x01\x00\x00\x00\x00;", 100, &[]),
+            Err(UploadError::UnsupportedType)
+        ));
+        assert!(matches!(process(b"not an image", 100, &[]), Err(UploadError::UnsupportedType)));
+    }
+}
@@ -0,0 +1,86 @@
+// Media in a directory of the server, for development and single machine setups.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use actix_web::web;
+use async_trait::async_trait;
+
+use super::{valid_key, MediaError, MediaStore};
+
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: &str) -> Result<Self, MediaError> {
+        std::fs::create_dir_all(root)?;
+        Ok(LocalStore {
+            root: PathBuf::from(root),
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, MediaError> {
+        if !valid_key(key) {
+            return Err(MediaError::InvalidKey);
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+// the filesystem blocks, off the worker threads with it
+async fn blocking<T, F>(f: F) -> Result<T, MediaError>
+where
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(f)
+        .await
+        .map_err(|error| std::io::Error::other(error.to_string()))?
+        .map_err(MediaError::from)
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    // complete files only, a reader never sees half an image
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, bytes)?;
+    std::fs::rename(&partial, path)
+}
+
+#[async_trait]
+impl MediaStore for LocalStore {
+    async fn put(&self, key: &str, _content_type: &str, bytes: Vec<u8>) -> Result<(), MediaError> {
+        let path = self.path(key)?;
+        blocking(move || write_file(&path, &bytes)).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, MediaError> {
+        let path = self.path(key)?;
+        blocking(move || match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), MediaError> {
+        let path = self.path(key)?;
+        blocking(move || {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(error) if error.kind() == ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+            // the media's directory goes with its last file
+            if let Some(directory) = path.parent() {
+                let _ = std::fs::remove_dir(directory);
+            }
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,291 @@
+// Uploaded images and where they are kept. The storage is pluggable: the local filesystem or
+// any S3 compatible service. Browsers only get signed urls that run out after a while, either
+// the storage's own (S3 presigned) or ours, served by GET /api/v1/media/{key}.
+// Keys look like media/<media_id>/original.png and media/<media_id>/480.jpg.
+
+pub mod images;
+pub mod local;
+pub mod s3;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+use crate::configuration::{MediaSettings, MediaStorageKind};
+
+pub const MEDIA_PATH: &str = "/api/v1/media/";
+
+#[derive(thiserror::Error, Debug)]
+pub enum MediaError {
+    #[error("invalid media key")]
+    InvalidKey,
+    #[error("media storage s3 needs the s3 settings")]
+    MissingS3Settings,
+    #[error("media storage io error")]
+    Io(#[from] std::io::Error),
+    #[error("media storage request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("media storage answered {0}")]
+    Status(reqwest::StatusCode),
+}
+
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, bytes: Vec<u8>) -> Result<(), MediaError>;
+    // None if there is nothing under the key
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, MediaError>;
+    // deleting what isn't there is fine
+    async fn delete(&self, key: &str) -> Result<(), MediaError>;
+    // a url the storage serves the object at by itself for `ttl`, None if it has to go through us
+    fn presigned_url(&self, _key: &str, _ttl: Duration) -> Option<String> {
+        None
+    }
+}
+
+pub struct Media {
+    store: Box<dyn MediaStore>,
+    public_url: String,
+    signing_key: Vec<u8>,
+    pub url_ttl: Duration,
+    pub max_upload_bytes: usize,
+    pub max_dimension: u32,
+    pub thumbnail_widths: Vec<u32>,
+}
+
+// keys are ours, made of ids, sizes and extensions, nothing that could climb out of a directory
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('/').all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+}
+
+pub fn original_key(media_id: Uuid, content_type: &str) -> String {
+    format!(
+        "media/{}/original.{}",
+        media_id,
+        images::extension(content_type)
+    )
+}
+
+pub fn thumbnail_key(media_id: Uuid, width: i32) -> String {
+    format!("media/{}/{}.jpg", media_id, width)
+}
+
+// the original and every thumbnail
+pub fn media_keys(media_id: Uuid, content_type: &str, thumbnail_widths: &[i32]) -> Vec<String> {
+    std::iter::once(original_key(media_id, content_type))
+        .chain(
+            thumbnail_widths
+                .iter()
+                .map(|width| thumbnail_key(media_id, *width)),
+        )
+        .collect()
+}
+
+// by extension, for serving what we stored ourselves
+pub fn content_type_of(key: &str) -> &'static str {
+    match key.rsplit('.').next() {
+        Some("jpg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+impl Media {
+    pub fn new(settings: &MediaSettings) -> Result<Self, MediaError> {
+        let store: Box<dyn MediaStore> = match settings.storage {
+            MediaStorageKind::Local => Box::new(local::LocalStore::new(&settings.local_path)?),
+            MediaStorageKind::S3 => match &settings.s3 {
+                Some(s3_settings) => Box::new(s3::S3Store::new(s3_settings)),
+                None => return Err(MediaError::MissingS3Settings),
+            },
+        };
+        let signing_key = match &settings.signing_key {
+            Some(key) => key.expose_secret().as_bytes().to_vec(),
+            None => {
+                tracing::warn!("no media signing key, media urls stop working on restart");
+                let mut key = vec![0u8; 32];
+                openssl::rand::rand_bytes(&mut key).expect("openssl rand_bytes failed");
+                key
+            }
+        };
+        Ok(Media {
+            store,
+            public_url: settings.public_url.trim_end_matches('/').to_string(),
+            signing_key,
+            url_ttl: Duration::from_secs(settings.url_ttl_minutes.max(1) as u64 * 60),
+            max_upload_bytes: settings.max_upload_bytes,
+            max_dimension: settings.max_dimension,
+            thumbnail_widths: settings.thumbnail_widths.clone(),
+        })
+    }
+
+    pub async fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        bytes: Vec<u8>,
+    ) -> Result<(), MediaError> {
+        self.store.put(key, content_type, bytes).await
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, MediaError> {
+        self.store.get(key).await
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), MediaError> {
+        self.store.delete(key).await
+    }
+
+    // What a browser fetches the object with, good for url_ttl.
+    pub fn url(&self, key: &str) -> String {
+        if let Some(url) = self.store.presigned_url(key, self.url_ttl) {
+            return url;
+        }
+        let expires = unix_seconds(SystemTime::now() + self.url_ttl);
+        format!(
+            "{}{}{}?expires={}&signature={}",
+            self.public_url,
+            MEDIA_PATH,
+            key,
+            expires,
+            self.signature(key, expires)
+        )
+    }
+
+    // whether one of our urls is genuine and still good
+    pub fn verify(&self, key: &str, expires: u64, signature: &str) -> bool {
+        if expires < unix_seconds(SystemTime::now()) {
+            return false;
+        }
+        let expected = self.signature(key, expires);
+        expected.len() == signature.len()
+            && openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+    }
+
+    fn signature(&self, key: &str, expires: u64) -> String {
+        URL_SAFE_NO_PAD.encode(hmac_sha256(
+            &self.signing_key,
+            format!("{}\n{}", key, expires).as_bytes(),
+        ))
+    }
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).expect("openssl hmac key failed");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("openssl signer failed");
+    signer.update(data).expect("openssl hmac failed");
+    signer.sign_to_vec().expect("openssl hmac failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // urls are signed without ever touching the storage
+    struct NoStore;
+
+    #[async_trait]
+    impl MediaStore for NoStore {
+        async fn put(&self, _key: &str, _content_type: &str, _bytes: Vec<u8>) -> Result<(), MediaError> {
+            Ok(())
+        }
+        async fn get(&self, _key: &str) -> Result<Option<Vec<u8>>, MediaError> {
+            Ok(None)
+        }
+        async fn delete(&self, _key: &str) -> Result<(), MediaError> {
+            Ok(())
+        }
+    }
+
+    fn media(signing_key: &str) -> Media {
+        Media {
+            store: Box::new(NoStore),
+            public_url: "http://127.0.0.1:3000".to_string(),
+            signing_key: signing_key.as_bytes().to_vec(),
+            url_ttl: Duration::from_secs(3600),
+            max_upload_bytes: 1024,
+            max_dimension: 100,
+            thumbnail_widths: vec![],
+        }
+    }
+
+    // (expires, signature) of one of our urls
+    fn signed(url: &str) -> (u64, String) {
+        let query = url::Url::parse(url).unwrap();
+        let pairs: std::collections::HashMap<_, _> = query.query_pairs().into_owned().collect();
+        (pairs["expires"].parse().unwrap(), pairs["signature"].clone())
+    }
+
+    #[test]
+    fn keys_we_make_are_valid() {
+        let media_id = Uuid::new_v4();
+        assert!(valid_key(&original_key(media_id, "image/png")));
+        assert!(valid_key(&thumbnail_key(media_id, 480)));
+    }
+
+    #[test]
+    fn keys_that_leave_the_directory_are_invalid() {
+        for key in [
+            "",
+            "/media/x/480.jpg",
+            "media/x/",
+            "media//480.jpg",
+            "media/../480.jpg",
+            "../etc/passwd",
+            "media/.hidden",
+            "media\\x\\480.jpg",
+            "media/x/4 80.jpg",
+            "media/x%2F..%2F/480.jpg",
+        ] {
+            assert!(!valid_key(key), "{:?} is valid", key);
+        }
+    }
+
+    #[test]
+    fn our_url_verifies() {
+        let media = media("secret");
+        let (expires, signature) = signed(&media.url("media/x/480.jpg"));
+        assert!(media.verify("media/x/480.jpg", expires, &signature));
+    }
+
+    #[test]
+    fn url_for_another_key_does_not_verify() {
+        let media = media("secret");
+        let (expires, signature) = signed(&media.url("media/x/480.jpg"));
+        assert!(!media.verify("media/x/original.png", expires, &signature));
+        assert!(!media.verify("media/x/480.jpg", expires + 1, &signature));
+        assert!(!media.verify("media/x/480.jpg", expires, &signature[1..]));
+        assert!(!media.verify("media/x/480.jpg", expires, ""));
+    }
+
+    #[test]
+    fn url_signed_with_another_key_does_not_verify() {
+        let (expires, signature) = signed(&media("old secret").url("media/x/480.jpg"));
+        assert!(!media("secret").verify("media/x/480.jpg", expires, &signature));
+    }
+
+    #[test]
+    fn expired_url_does_not_verify() {
+        let media = media("secret");
+        let expired = unix_seconds(SystemTime::now()) - 1;
+        let signature = media.signature("media/x/480.jpg", expired);
+        assert!(!media.verify("media/x/480.jpg", expired, &signature));
+    }
+}
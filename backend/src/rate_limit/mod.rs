@@ -0,0 +1,452 @@
+// Throttling of the login and token endpoints
+// Token buckets: a bucket holds up to `capacity` requests and refills at `refill_per_minute`,
+// a request takes one and is turned away with 429 and Retry-After when the bucket is empty.
+// - per ip address for the paths in configuration (the middleware)
+// - per account (client_id) at the token endpoint, for confidential clients
+// A confidential client whose secret is wrong lockout_after_failures times in a row from one ip
+// address is locked out there, every further failure doubles the lockout.
+// Buckets are kept in memory, or in Redis when several instances have to share them.
+
+pub mod middleware;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use redis::aio::ConnectionManager;
+
+use crate::configuration::{BucketSettings, RateLimitSettings};
+
+// the in memory store forgets idle buckets when it grows past this
+const MAX_MEMORY_KEYS: usize = 10_000;
+
+// KEYS[1] bucket, ARGV capacity, refill per millisecond, now in milliseconds
+// returns 0 when a token was taken, otherwise the milliseconds until there is one
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local per_ms = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * per_ms)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) / per_ms)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / per_ms))
+return wait
+"#;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn tokens_at(&self, now: Instant, capacity: f64, per_second: f64) -> f64 {
+        (self.tokens + now.duration_since(self.updated).as_secs_f64() * per_second).min(capacity)
+    }
+
+    // Takes a token, or says how long until there is one.
+    fn take(&mut self, now: Instant, capacity: f64, per_second: f64) -> Result<(), Duration> {
+        self.tokens = self.tokens_at(now, capacity, per_second);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    // Counts a failure and returns the count. Like the failures in Redis, they are forgotten
+    // once `forget_after` has passed since the last one.
+    fn record(&mut self, now: Instant, forget_after: Duration) -> u32 {
+        if now.duration_since(self.last_failure) > forget_after {
+            self.count = 0;
+            self.locked_until = None;
+        }
+        self.count += 1;
+        self.last_failure = now;
+        self.count
+    }
+}
+
+enum Store {
+    Memory {
+        buckets: Mutex<HashMap<String, Bucket>>,
+        failures: Mutex<HashMap<String, Failures>>,
+    },
+    Redis(ConnectionManager),
+}
+
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    store: Store,
+}
+
+fn refill_per_second(bucket: &BucketSettings) -> f64 {
+    (bucket.refill_per_minute.max(1) as f64) / 60.0
+}
+
+pub fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    // whole seconds, never 0
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, seconds.max(1).to_string()))
+        .body("too many requests")
+}
+
+impl RateLimiter {
+    pub async fn new(settings: RateLimitSettings) -> Self {
+        let store = match &settings.redis_url {
+            Some(redis_url) => match Self::connect(redis_url).await {
+                Ok(connection) => Store::Redis(connection),
+                Err(error) => {
+                    tracing::error!("rate limit redis unavailable, limiting in memory: {}", error);
+                    Self::memory()
+                }
+            },
+            None => Self::memory(),
+        };
+        RateLimiter { settings, store }
+    }
+
+    async fn connect(redis_url: &str) -> Result<ConnectionManager, redis::RedisError> {
+        let client = redis::Client::open(redis_url)?;
+        ConnectionManager::new(client).await
+    }
+
+    fn memory() -> Store {
+        Store::Memory {
+            buckets: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn applies_to(&self, path: &str) -> bool {
+        self.settings.enabled && self.settings.paths.iter().any(|prefix| path.starts_with(prefix))
+    }
+
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<String> {
+        if self.settings.trust_forwarded_for {
+            request.connection_info().realip_remote_addr().map(|ip| ip.to_string())
+        } else {
+            request.peer_addr().map(|address| address.ip().to_string())
+        }
+    }
+
+    pub async fn check_ip(&self, ip: &str) -> Result<(), Duration> {
+        self.take(&format!("rate:ip:{}", ip), &self.settings.per_ip).await
+    }
+
+    pub async fn check_account(&self, account: &str) -> Result<(), Duration> {
+        self.take(&format!("rate:account:{}", account), &self.settings.per_account)
+            .await
+    }
+
+    // Turns away a client that is locked out, see record_failure.
+    pub async fn check_lockout(&self, key: &str) -> Result<(), Duration> {
+        if !self.settings.enabled {
+            return Ok(());
+        }
+        match self.locked_for(key).await {
+            Some(remaining) => Err(remaining),
+            None => Ok(()),
+        }
+    }
+
+    async fn take(&self, key: &str, bucket: &BucketSettings) -> Result<(), Duration> {
+        if !self.settings.enabled {
+            return Ok(());
+        }
+        let per_second = refill_per_second(bucket);
+        let capacity = bucket.capacity.max(1) as f64;
+        match &self.store {
+            Store::Memory { buckets, .. } => {
+                let now = Instant::now();
+                let mut buckets = buckets.lock().unwrap();
+                if buckets.len() > MAX_MEMORY_KEYS {
+                    // a bucket that has refilled is the same as no bucket
+                    buckets.retain(|_, b| b.tokens_at(now, capacity, per_second) < capacity);
+                }
+                buckets
+                    .entry(key.to_string())
+                    .or_insert(Bucket {
+                        tokens: capacity,
+                        updated: now,
+                    })
+                    .take(now, capacity, per_second)
+            }
+            Store::Redis(connection) => {
+                let mut connection = connection.clone();
+                let wait: Result<u64, redis::RedisError> = redis::Script::new(TAKE_TOKEN_SCRIPT)
+                    .key(key)
+                    .arg(capacity)
+                    .arg(per_second / 1000.0)
+                    .arg(now_millis())
+                    .invoke_async(&mut connection)
+                    .await;
+                match wait {
+                    Ok(0) => Ok(()),
+                    Ok(wait) => Err(Duration::from_millis(wait)),
+                    Err(error) => {
+                        // fail open, redis being down shouldn't lock everybody out
+                        tracing::error!("rate limit redis error {}", error);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    fn lockout(&self, failures: u32) -> Option<Duration> {
+        if failures < self.settings.lockout_after_failures.max(1) {
+            return None;
+        }
+        let doublings = (failures - self.settings.lockout_after_failures.max(1)).min(20);
+        let seconds = self
+            .settings
+            .lockout_base_seconds
+            .saturating_mul(1 << doublings)
+            .min(self.settings.lockout_max_seconds);
+        Some(Duration::from_secs(seconds))
+    }
+
+    async fn locked_for(&self, account: &str) -> Option<Duration> {
+        match &self.store {
+            Store::Memory { failures, .. } => {
+                let failures = failures.lock().unwrap();
+                let locked_until = failures.get(account)?.locked_until?;
+                locked_until.checked_duration_since(Instant::now())
+            }
+            Store::Redis(connection) => {
+                let mut connection = connection.clone();
+                let remaining: Result<i64, redis::RedisError> = redis::cmd("PTTL")
+                    .arg(format!("lockout:until:{}", account))
+                    .query_async(&mut connection)
+                    .await;
+                match remaining {
+                    Ok(milliseconds) if milliseconds > 0 => {
+                        Some(Duration::from_millis(milliseconds as u64))
+                    }
+                    Ok(_) => None,
+                    Err(error) => {
+                        tracing::error!("rate limit redis error {}", error);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn record_failure(&self, account: &str) {
+        if !self.settings.enabled {
+            return;
+        }
+        match &self.store {
+            Store::Memory { failures, .. } => {
+                let now = Instant::now();
+                let forget_after = Duration::from_secs(self.settings.lockout_max_seconds.max(1));
+                let mut failures = failures.lock().unwrap();
+                if failures.len() > MAX_MEMORY_KEYS {
+                    failures.retain(|_, f| {
+                        f.locked_until.map(|until| until > now).unwrap_or(false)
+                            || now.duration_since(f.last_failure) <= forget_after
+                    });
+                }
+                let entry = failures.entry(account.to_string()).or_insert(Failures {
+                    count: 0,
+                    last_failure: now,
+                    locked_until: None,
+                });
+                let count = entry.record(now, forget_after);
+                if let Some(lockout) = self.lockout(count) {
+                    entry.locked_until = Some(now + lockout);
+                    tracing::warn!("{} locked out for {:?} after {} failures", account, lockout, count);
+                }
+            }
+            Store::Redis(connection) => {
+                let mut connection = connection.clone();
+                let count_key = format!("lockout:failures:{}", account);
+                let count: Result<u32, redis::RedisError> = redis::pipe()
+                    .atomic()
+                    .incr(&count_key, 1)
+                    // failures are forgotten once the longest lockout has passed
+                    .expire(&count_key, self.settings.lockout_max_seconds.max(1) as usize)
+                    .ignore()
+                    .query_async::<_, (u32,)>(&mut connection)
+                    .await
+                    .map(|(count,)| count);
+                let count = match count {
+                    Ok(count) => count,
+                    Err(error) => {
+                        tracing::error!("rate limit redis error {}", error);
+                        return;
+                    }
+                };
+                if let Some(lockout) = self.lockout(count) {
+                    tracing::warn!("{} locked out for {:?} after {} failures", account, lockout, count);
+                    let locked: Result<(), redis::RedisError> = redis::cmd("SET")
+                        .arg(format!("lockout:until:{}", account))
+                        .arg(1)
+                        .arg("PX")
+                        .arg(lockout.as_millis() as u64)
+                        .query_async(&mut connection)
+                        .await;
+                    if let Err(error) = locked {
+                        tracing::error!("rate limit redis error {}", error);
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn record_success(&self, account: &str) {
+        if !self.settings.enabled {
+            return;
+        }
+        match &self.store {
+            Store::Memory { failures, .. } => {
+                failures.lock().unwrap().remove(account);
+            }
+            Store::Redis(connection) => {
+                let mut connection = connection.clone();
+                let cleared: Result<(), redis::RedisError> = redis::cmd("DEL")
+                    .arg(format!("lockout:failures:{}", account))
+                    .query_async(&mut connection)
+                    .await;
+                if let Err(error) = cleared {
+                    tracing::error!("rate limit redis error {}", error);
+                }
+            }
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        let bucket = BucketSettings {
+            capacity: 10,
+            refill_per_minute: 5,
+        };
+        RateLimiter {
+            settings: RateLimitSettings {
+                enabled: true,
+                paths: vec!["/oauth/".to_string()],
+                per_ip: bucket,
+                per_account: bucket,
+                lockout_after_failures: 5,
+                lockout_base_seconds: 30,
+                lockout_max_seconds: 3600,
+                trust_forwarded_for: false,
+                redis_url: None,
+            },
+            store: RateLimiter::memory(),
+        }
+    }
+
+    #[test]
+    fn no_lockout_before_the_threshold() {
+        let limiter = limiter();
+        for failures in 0..5 {
+            assert_eq!(limiter.lockout(failures), None);
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_per_failure_up_to_the_max() {
+        let limiter = limiter();
+        assert_eq!(limiter.lockout(5), Some(Duration::from_secs(30)));
+        assert_eq!(limiter.lockout(6), Some(Duration::from_secs(60)));
+        assert_eq!(limiter.lockout(7), Some(Duration::from_secs(120)));
+        assert_eq!(limiter.lockout(11), Some(Duration::from_secs(1920)));
+        assert_eq!(limiter.lockout(12), Some(Duration::from_secs(3600)));
+        // the shift is capped, a huge count doesn't overflow
+        assert_eq!(limiter.lockout(u32::MAX), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn bucket_empties_and_says_when_the_next_token_comes() {
+        let now = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            updated: now,
+        };
+        assert_eq!(bucket.take(now, 10.0, 0.5), Ok(()));
+        assert_eq!(bucket.take(now, 10.0, 0.5), Ok(()));
+        // one token every two seconds
+        assert_eq!(bucket.take(now, 10.0, 0.5), Err(Duration::from_secs(2)));
+        assert_eq!(
+            bucket.take(now + Duration::from_secs(1), 10.0, 0.5),
+            Err(Duration::from_secs(1))
+        );
+        assert_eq!(bucket.take(now + Duration::from_secs(2), 10.0, 0.5), Ok(()));
+    }
+
+    #[test]
+    fn bucket_refills_up_to_capacity() {
+        let now = Instant::now();
+        let bucket = Bucket {
+            tokens: 0.0,
+            updated: now,
+        };
+        assert_eq!(bucket.tokens_at(now + Duration::from_secs(4), 10.0, 0.5), 2.0);
+        assert_eq!(bucket.tokens_at(now + Duration::from_secs(3600), 10.0, 0.5), 10.0);
+    }
+
+    #[test]
+    fn failures_are_forgotten_after_the_longest_lockout() {
+        let now = Instant::now();
+        let forget_after = Duration::from_secs(3600);
+        let mut failures = Failures {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        };
+        assert_eq!(failures.record(now, forget_after), 1);
+        assert_eq!(failures.record(now + Duration::from_secs(3000), forget_after), 2);
+        // counted from the last failure, not the first
+        assert_eq!(failures.record(now + Duration::from_secs(6000), forget_after), 3);
+        failures.locked_until = Some(now + Duration::from_secs(6030));
+        let later = now + Duration::from_secs(6000) + forget_after + Duration::from_secs(1);
+        assert_eq!(failures.record(later, forget_after), 1);
+        assert_eq!(failures.locked_until, None);
+    }
+
+    #[actix_web::test]
+    async fn lockout_stays_with_the_ip_it_came_from() {
+        let limiter = limiter();
+        for _ in 0..5 {
+            limiter.record_failure("203.0.113.7:backoffice").await;
+        }
+        assert!(limiter.check_lockout("203.0.113.7:backoffice").await.is_err());
+        assert_eq!(limiter.check_lockout("198.51.100.2:backoffice").await, Ok(()));
+
+        limiter.record_success("203.0.113.7:backoffice").await;
+        assert_eq!(limiter.check_lockout("203.0.113.7:backoffice").await, Ok(()));
+    }
+}
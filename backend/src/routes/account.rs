@@ -0,0 +1,174 @@
+// A user's own account: leaving (DELETE /me) and taking their data along (GET /me/export).
+// Deleting only marks the account, it is purged after the grace period (account_purge.rs) and
+// logging in before that brings it back.
+
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::extractor::{AuthError, AuthenticatedUser};
+use crate::database::{
+    AuthEventFilter, AuthEventRecord, ConsentRecord, PoseAnnotationRecord, RefreshTokenRecord,
+    RoleGrant, UserIdentity, UserProfileRecord, YogaDatabase,
+};
+use crate::routes::oauth::revoke_provider_tokens;
+use crate::routes::practice::{practice_history, PracticeDetail};
+use crate::routes::sequences::{sequence_detail, SequenceDetail};
+use crate::routes::tokens::{REFRESH_COOKIE, REFRESH_COOKIE_PATH};
+use crate::session_state::TypedSession;
+use crate::YogaAppData;
+use actix_web::{
+    cookie::Cookie,
+    http::header::{ContentDisposition, CACHE_CONTROL},
+    web, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use std::time::UNIX_EPOCH;
+
+#[derive(serde::Serialize)]
+pub struct DeletionResponse {
+    purge_after: DateTime<Utc>,
+}
+
+fn removal_cookie(name: &'static str, path: &'static str) -> Cookie<'static> {
+    let mut cookie = Cookie::named(name);
+    cookie.set_path(path);
+    cookie.make_removal();
+    cookie
+}
+
+// Only the user, not a registered client or an admin acting for them.
+fn account_owner(user: &AuthenticatedUser) -> Result<(), AuthError> {
+    user.not_impersonated()?;
+    match user.client_id {
+        Some(_) => Err(AuthError::Forbidden),
+        None => Ok(()),
+    }
+}
+
+#[actix_web::delete("/me")]
+pub async fn delete_me(
+    user: AuthenticatedUser,
+    session: TypedSession,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    account_owner(&user)?;
+    let purge_after = Utc::now() + chrono::Duration::days(app_data.deletion_grace_days);
+    let deleted = db
+        .soft_delete_user(user.user_id, purge_after)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !deleted {
+        return Ok(HttpResponse::Conflict().body("account already deleted"));
+    }
+
+    // provider tokens are only kept in the browser session of the login
+    let provider = session.get_oauth_provider()?;
+    if session.get_user_id()? == Some(user.user_id)
+        && revoke_provider_tokens(&session, &app_data).await?
+    {
+        AuthEvent::new(AuthEventKind::TokenRevoked)
+            .user(Some(user.user_id))
+            .provider(provider.as_deref())
+            .detail("provider grant")
+            .record(&db, &client_info)
+            .await;
+    }
+    let detail = format!("purge after {}", purge_after.to_rfc3339());
+    AuthEvent::new(AuthEventKind::AccountDeleted)
+        .user(Some(user.user_id))
+        .provider(provider.as_deref())
+        .detail(&detail)
+        .record(&db, &client_info)
+        .await;
+    tracing::info!("account {} deleted, purge after {}", user.user_id, purge_after);
+
+    session.purge();
+    Ok(HttpResponse::Accepted()
+        .cookie(removal_cookie("access_token", "/"))
+        .cookie(removal_cookie(REFRESH_COOKIE, REFRESH_COOKIE_PATH))
+        .json(DeletionResponse { purge_after }))
+}
+
+// Everything stored about the user. Tokens and secrets are never stored, only their metadata.
+#[derive(serde::Serialize)]
+pub struct AccountExport {
+    exported_at: DateTime<Utc>,
+    profile: UserProfileRecord,
+    roles: Vec<RoleGrant>,
+    identities: Vec<UserIdentity>,
+    consents: Vec<ConsentRecord>,
+    refresh_tokens: Vec<RefreshTokenRecord>,
+    security_events: Vec<AuthEventRecord>,
+    sequences: Vec<SequenceDetail>,
+    practice: Vec<PracticeDetail>,
+    pose_annotations: Vec<PoseAnnotationRecord>,
+}
+
+#[actix_web::get("/me/export")]
+pub async fn export_me(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    account_owner(&user)?;
+    let profile = match db
+        .get_user_profile(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(profile) => profile,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut sequences = Vec::new();
+    for summary in db
+        .list_sequences(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        if let Some(detail) = sequence_detail(&db, user.user_id, summary.sequence_id).await? {
+            sequences.push(detail);
+        }
+    }
+    // all of it, practice logged with a clock that runs ahead included
+    let practice = practice_history(
+        &db,
+        user.user_id,
+        DateTime::<Utc>::from(UNIX_EPOCH),
+        Utc::now() + chrono::Duration::days(1),
+    )
+    .await?;
+    let events_filter = AuthEventFilter { user_id: Some(user.user_id), ..Default::default() };
+    let export = AccountExport {
+        exported_at: Utc::now(),
+        profile,
+        roles: db
+            .get_role_grants(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        identities: db
+            .get_user_identities(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        consents: db
+            .get_consents(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        refresh_tokens: db
+            .get_refresh_token_records(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        security_events: db
+            .list_auth_events(&events_filter, i64::MAX, 0)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        sequences,
+        practice,
+        pose_annotations: db
+            .list_pose_annotations(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition::attachment("yogamat-export.json"))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(export))
+}
@@ -0,0 +1,233 @@
+// User management for admins. Every change is written to the audit log with the admin as actor.
+// Role changes show up in the user's access tokens from their next refresh on.
+
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::Role;
+use crate::database::{ActiveSession, RoleGrant, UserIdentity, UserSummary, YogaDatabase};
+use crate::routes::Paging;
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UserSearch {
+    // part of an email, or a provider's subject
+    q: Option<String>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserPage {
+    users: Vec<UserSummary>,
+    page: i64,
+    per_page: i64,
+    has_more: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserDetail {
+    #[serde(flatten)]
+    summary: UserSummary,
+    role_grants: Vec<RoleGrant>,
+    identities: Vec<UserIdentity>,
+    sessions: Vec<ActiveSession>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableRequest {
+    reason: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleRequest {
+    role: String,
+}
+
+// 404 for a user that doesn't exist
+async fn existing_user(db: &YogaDatabase, user_id: Uuid) -> Result<UserSummary, actix_web::Error> {
+    db.get_user_summary(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("no such user"))
+}
+
+fn parse_role(role: &str) -> Result<Role, actix_web::Error> {
+    Role::try_from(role).map_err(|_| actix_web::error::ErrorBadRequest("unknown role"))
+}
+
+#[actix_web::get("/admin/users")]
+pub async fn list_users(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    query: web::Query<UserSearch>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let (page, per_page) = Paging { page: query.page, per_page: query.per_page }.resolve();
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    // one more than asked for tells whether there is another page
+    let mut users = db
+        .list_users(search, per_page + 1, (page - 1) * per_page)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let has_more = users.len() as i64 > per_page;
+    users.truncate(per_page as usize);
+    Ok(HttpResponse::Ok().json(UserPage { users, page, per_page, has_more }))
+}
+
+#[actix_web::get("/admin/users/{user_id}")]
+pub async fn get_user(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let user_id = path.into_inner();
+    let summary = existing_user(&db, user_id).await?;
+    let detail = UserDetail {
+        summary,
+        role_grants: db
+            .get_role_grants(user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        identities: db
+            .get_user_identities(user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        sessions: db
+            .list_active_sessions(user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+    };
+    Ok(HttpResponse::Ok().json(detail))
+}
+
+#[actix_web::post("/admin/users/{user_id}/disable")]
+pub async fn disable_user(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<Uuid>,
+    body: Option<web::Json<DisableRequest>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let user_id = path.into_inner();
+    if user_id == admin.user_id {
+        return Ok(HttpResponse::Conflict().body("admins can't disable themselves"));
+    }
+    let reason = body.and_then(|body| body.into_inner().reason);
+    let disabled = db
+        .disable_user(user_id, reason.as_deref())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !disabled {
+        return Ok(HttpResponse::NotFound().body("no such user"));
+    }
+    let mut event = AuthEvent::new(AuthEventKind::UserDisabled)
+        .user(Some(user_id))
+        .actor(admin.user_id);
+    if let Some(reason) = &reason {
+        event = event.detail(reason);
+    }
+    event.record(&db, &client_info).await;
+    Ok(HttpResponse::Ok().json(existing_user(&db, user_id).await?))
+}
+
+#[actix_web::post("/admin/users/{user_id}/enable")]
+pub async fn enable_user(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let user_id = path.into_inner();
+    let enabled = db
+        .enable_user(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !enabled {
+        return Ok(HttpResponse::NotFound().body("no such user"));
+    }
+    AuthEvent::new(AuthEventKind::UserEnabled)
+        .user(Some(user_id))
+        .actor(admin.user_id)
+        .record(&db, &client_info)
+        .await;
+    Ok(HttpResponse::Ok().json(existing_user(&db, user_id).await?))
+}
+
+// Ends every session of the user, their access tokens stop working with the next request.
+#[actix_web::post("/admin/users/{user_id}/logout")]
+pub async fn force_logout(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let user_id = path.into_inner();
+    existing_user(&db, user_id).await?;
+    db.revoke_user_refresh_tokens(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    AuthEvent::new(AuthEventKind::ForcedLogout)
+        .user(Some(user_id))
+        .actor(admin.user_id)
+        .record(&db, &client_info)
+        .await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[actix_web::post("/admin/users/{user_id}/roles")]
+pub async fn grant_role(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<Uuid>,
+    body: web::Json<RoleRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let user_id = path.into_inner();
+    let role = parse_role(&body.role)?;
+    existing_user(&db, user_id).await?;
+    db.grant_role(user_id, role)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    AuthEvent::new(AuthEventKind::RoleGranted)
+        .user(Some(user_id))
+        .actor(admin.user_id)
+        .detail(role.as_ref())
+        .record(&db, &client_info)
+        .await;
+    Ok(HttpResponse::Ok().json(existing_user(&db, user_id).await?))
+}
+
+#[actix_web::delete("/admin/users/{user_id}/roles/{role}")]
+pub async fn revoke_role(
+    admin: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<(Uuid, String)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    let (user_id, role) = path.into_inner();
+    let role = parse_role(&role)?;
+    if user_id == admin.user_id && role == Role::Admin {
+        return Ok(HttpResponse::Conflict().body("admins can't take away their own admin role"));
+    }
+    existing_user(&db, user_id).await?;
+    let revoked = db
+        .revoke_role(user_id, role)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if revoked {
+        AuthEvent::new(AuthEventKind::RoleRevoked)
+            .user(Some(user_id))
+            .actor(admin.user_id)
+            .detail(role.as_ref())
+            .record(&db, &client_info)
+            .await;
+    }
+    Ok(HttpResponse::Ok().json(existing_user(&db, user_id).await?))
+}
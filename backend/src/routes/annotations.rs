@@ -0,0 +1,172 @@
+// A user's own favorites, tags and notes on catalog poses. Nobody else sees them, they come
+// along in the user's PoseInfo responses.
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{PoseRecord, TagCount, YogaDatabase};
+use crate::media::Media;
+use crate::routes::poses::annotated;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 50;
+const MAX_NOTE_LENGTH: usize = 2000;
+
+#[derive(Deserialize)]
+pub struct TagsRequest {
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NoteRequest {
+    note: String,
+}
+
+#[derive(Serialize)]
+pub struct TagListResponse {
+    tags: Vec<TagCount>,
+}
+
+fn may_annotate_poses(user: &AuthenticatedUser) -> Result<(), actix_web::Error> {
+    if !user.has_scope("poses") {
+        return Err(actix_web::error::ErrorForbidden("poses scope required"));
+    }
+    Ok(())
+}
+
+// only poses still in the catalog get new annotations
+async fn existing_pose(db: &YogaDatabase, pose_id: i32) -> Result<PoseRecord, actix_web::Error> {
+    db.get_pose(pose_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("no such pose"))
+}
+
+// Tags are trimmed and lowercased, so "Hips" and "hips " are the same tag. Duplicates are
+// dropped.
+fn clean_tags(tags: &[String]) -> Result<Vec<String>, actix_web::Error> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(actix_web::error::ErrorBadRequest("a tag is at most 50 characters"));
+        }
+        if !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    if cleaned.len() > MAX_TAGS {
+        return Err(actix_web::error::ErrorBadRequest("at most 20 tags per pose"));
+    }
+    Ok(cleaned)
+}
+
+async fn favorite(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    pose_id: i32,
+    favorite: bool,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_annotate_poses(&user)?;
+    let pose = existing_pose(&db, pose_id).await?;
+    db.set_favorite(user.user_id, pose_id, favorite)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(annotated(&db, &media, user.user_id, pose).await?))
+}
+
+#[actix_web::put("/poses/{pose_id}/favorite")]
+pub async fn add_favorite(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    favorite(user, db, media, path.into_inner(), true).await
+}
+
+#[actix_web::delete("/poses/{pose_id}/favorite")]
+pub async fn remove_favorite(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    favorite(user, db, media, path.into_inner(), false).await
+}
+
+// Replaces the user's tags on the pose, an empty list removes them all.
+#[actix_web::put("/poses/{pose_id}/tags")]
+pub async fn set_tags(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+    body: web::Json<TagsRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_annotate_poses(&user)?;
+    let tags = clean_tags(&body.tags)?;
+    let pose = existing_pose(&db, path.into_inner()).await?;
+    db.set_pose_tags(user.user_id, pose.pose_id, &tags)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(annotated(&db, &media, user.user_id, pose).await?))
+}
+
+#[actix_web::put("/poses/{pose_id}/note")]
+pub async fn set_note(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+    body: web::Json<NoteRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_annotate_poses(&user)?;
+    let note = body.note.trim();
+    if note.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("note is empty, DELETE it instead"));
+    }
+    if note.chars().count() > MAX_NOTE_LENGTH {
+        return Ok(HttpResponse::BadRequest().body("a note is at most 2000 characters"));
+    }
+    let pose = existing_pose(&db, path.into_inner()).await?;
+    db.set_pose_note(user.user_id, pose.pose_id, note)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(annotated(&db, &media, user.user_id, pose).await?))
+}
+
+#[actix_web::delete("/poses/{pose_id}/note")]
+pub async fn delete_note(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_annotate_poses(&user)?;
+    let deleted = db
+        .delete_pose_note(user.user_id, path.into_inner())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !deleted {
+        return Ok(HttpResponse::NotFound().body("no note on this pose"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// every tag the user has, with how many catalog poses carry it
+#[actix_web::get("/me/tags")]
+pub async fn my_tags(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_annotate_poses(&user)?;
+    let tags = db
+        .list_user_tags(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(TagListResponse { tags }))
+}
@@ -0,0 +1,291 @@
+// We are the authorization server for our other apps ("log in with yogamat").
+// Authorization code flow with PKCE, RFC 6749 section 4.1 and RFC 7636
+// 1. The app sends the browser to /oauth/authorize
+// 2. If nobody is logged in the user logs in with one of the upstream providers (Google, ...)
+//    and comes back to /oauth/authorize
+// 3. The user consents on the frontend's consent page (skipped if they already did)
+// 4. The browser goes back to the app with a code, the app exchanges it at /oauth/token
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::opaque_token::{hash_opaque_token, new_opaque_token};
+use crate::database::{NewAuthorizationRequest, YogaDatabase};
+use crate::session_state::TypedSession;
+use crate::YogaAppData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use url::Url;
+use uuid::Uuid;
+
+const REQUEST_LIFETIME_SECONDS: i64 = 600;
+const CODE_LIFETIME_SECONDS: i64 = 60;
+
+#[derive(serde::Deserialize)]
+pub struct AuthorizeRequest {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+fn found(location: String) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, location))
+        .finish()
+}
+
+fn redirect_url(redirect_uri: &str, params: &[(&str, &str)], state: Option<&str>) -> String {
+    match Url::parse(redirect_uri) {
+        Ok(mut url) => {
+            {
+                let mut query = url.query_pairs_mut();
+                for (key, value) in params {
+                    query.append_pair(key, value);
+                }
+                if let Some(state) = state {
+                    query.append_pair("state", state);
+                }
+            }
+            url.into()
+        }
+        // redirect_uris are checked when clients are registered
+        Err(_) => redirect_uri.to_string(),
+    }
+}
+
+fn error_redirect(redirect_uri: &str, error: &str, state: Option<&str>) -> HttpResponse {
+    found(redirect_url(redirect_uri, &[("error", error)], state))
+}
+
+// Creates the authorization code for a consented request, returns where to send the browser.
+async fn code_redirect_url(
+    db: &YogaDatabase,
+    request_id: Uuid,
+    redirect_uri: &str,
+    state: Option<&str>,
+) -> Result<String, actix_web::Error> {
+    let code = new_opaque_token();
+    db.set_authorization_code(
+        request_id,
+        &hash_opaque_token(&code),
+        Utc::now() + chrono::Duration::seconds(CODE_LIFETIME_SECONDS),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(redirect_url(redirect_uri, &[("code", &code)], state))
+}
+
+#[actix_web::get("/authorize")]
+pub async fn authorize(
+    request: HttpRequest,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    session: TypedSession,
+    query: web::Query<AuthorizeRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Until the client and redirect_uri are known to be good, errors are shown to the user
+    // and never redirected, otherwise we would be an open redirector.
+    let client = match db
+        .get_oauth_client(&query.client_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(client) => client,
+        None => return Ok(HttpResponse::BadRequest().body("unknown client_id")),
+    };
+    if !client.redirect_uris.iter().any(|uri| uri == &query.redirect_uri) {
+        return Ok(HttpResponse::BadRequest().body("redirect_uri is not registered for this client"));
+    }
+
+    let state = query.state.as_deref();
+    if query.response_type != "code" {
+        return Ok(error_redirect(&query.redirect_uri, "unsupported_response_type", state));
+    }
+    if !client.allows_grant("authorization_code") {
+        return Ok(error_redirect(&query.redirect_uri, "unauthorized_client", state));
+    }
+    let code_challenge = match (&query.code_challenge, query.code_challenge_method.as_deref()) {
+        (Some(challenge), Some("S256")) => challenge,
+        // PKCE is required for every client, plain is not accepted
+        _ => return Ok(error_redirect(&query.redirect_uri, "invalid_request", state)),
+    };
+    let scope = query.scope.clone().unwrap_or_default();
+    if !client.allows_scopes(&scope) {
+        return Ok(error_redirect(&query.redirect_uri, "invalid_scope", state));
+    }
+
+    // the login in the session only counts while its refresh token family is alive and the
+    // user may log in
+    let logged_in = match session.get_user_id()? {
+        Some(user_id) => {
+            let standing = db
+                .get_user_standing(user_id, session.get_session_id()?)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            match standing {
+                Some(standing) if !standing.disabled && !standing.deleted && !standing.session_revoked => {
+                    Some(user_id)
+                }
+                // the new login replaces it
+                _ => None,
+            }
+        }
+        None => None,
+    };
+    let user_id = match logged_in {
+        Some(user_id) => user_id,
+        None => {
+            // log in upstream first, LoginAttempt::complete sends the browser back here
+            session.set_return_to(request.uri().to_string())?;
+            return Ok(found(app_data.login_url.clone()));
+        }
+    };
+
+    let request_id = db
+        .insert_authorization_request(NewAuthorizationRequest {
+            client_id: &client.client_id,
+            user_id,
+            redirect_uri: &query.redirect_uri,
+            scope: &scope,
+            state,
+            nonce: query.nonce.as_deref(),
+            code_challenge,
+            expires_at: Utc::now() + chrono::Duration::seconds(REQUEST_LIFETIME_SECONDS),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let consented = db
+        .has_consent(user_id, &client.client_id, &scope)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if consented {
+        let location = code_redirect_url(&db, request_id, &query.redirect_uri, state).await?;
+        return Ok(found(location));
+    }
+
+    Ok(found(format!("{}?request_id={}", app_data.consent_url, request_id)))
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentInfo {
+    request_id: Uuid,
+    client_id: String,
+    client_name: String,
+    scope: String,
+}
+
+// What the consent page shows before the user decides.
+#[actix_web::get("/oauth/consent/{request_id}")]
+pub async fn consent_request(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match db
+        .get_authorization_request(path.into_inner(), user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(request) => Ok(HttpResponse::Ok().json(ConsentInfo {
+            request_id: request.request_id,
+            client_id: request.client_id,
+            client_name: request.client_name,
+            scope: request.scope,
+        })),
+        None => Ok(HttpResponse::NotFound().body("no pending authorization request")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConsentDecision {
+    request_id: Uuid,
+    approve: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentRedirect {
+    redirect_to: String,
+}
+
+// The frontend sends the browser to `redirect_to`, back to the app that asked.
+#[actix_web::post("/oauth/consent")]
+pub async fn decide_consent(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    decision: web::Json<ConsentDecision>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // a registered client can't consent to itself
+    if user.client_id.is_some() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    user.not_impersonated()?;
+    let request = match db
+        .get_authorization_request(decision.request_id, user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(request) => request,
+        None => return Ok(HttpResponse::NotFound().body("no pending authorization request")),
+    };
+
+    let redirect_to = if decision.approve {
+        db.save_consent(user.user_id, &request.client_id, &request.scope)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        code_redirect_url(
+            &db,
+            request.request_id,
+            &request.redirect_uri,
+            request.state.as_deref(),
+        )
+        .await?
+    } else {
+        db.delete_authorization_request(request.request_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        redirect_url(
+            &request.redirect_uri,
+            &[("error", "access_denied")],
+            request.state.as_deref(),
+        )
+    };
+    Ok(HttpResponse::Ok().json(ConsentRedirect { redirect_to }))
+}
+
+#[derive(serde::Serialize)]
+pub struct UserInfo {
+    sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email_verified: Option<bool>,
+}
+
+// OpenID Connect Core 5.3
+#[actix_web::route("/userinfo", method = "GET", method = "POST")]
+pub async fn userinfo(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !user.has_scope("openid") {
+        return Ok(HttpResponse::Forbidden()
+            .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Bearer error=\"insufficient_scope\""))
+            .finish());
+    }
+    let email = if user.has_scope("email") {
+        db.get_user_email(user.user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(UserInfo {
+        sub: user.user_id.to_string(),
+        email_verified: email.as_ref().map(|_| true),
+        email,
+    }))
+}
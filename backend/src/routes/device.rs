@@ -0,0 +1,233 @@
+// OAuth 2.0 Device Authorization Grant, RFC 8628
+// https://www.rfc-editor.org/rfc/rfc8628
+// 1. The device (our cli) asks for a device code and a user code at /oauth/device/code
+// 2. The user opens the verification page of the frontend, logs in and enters the user code
+// 3. Meanwhile the device polls /oauth/token with the device code until the user has decided
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::opaque_token::{hash_opaque_token, new_opaque_token};
+use crate::database::{DevicePoll, YogaDatabase};
+use crate::routes::tokens::{issue_token_family, TokenErrorResponse};
+use crate::YogaAppData;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+
+pub const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const DEVICE_CODE_LIFETIME_SECONDS: i64 = 600;
+const POLL_INTERVAL_SECONDS: i32 = 5;
+// no vowels so we don't spell words, no easily confused characters
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize)]
+pub struct DeviceCodeRequest {
+    client_id: String,
+    scope: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: String,
+    expires_in: i64,
+    interval: i32,
+}
+
+// eight characters as XXXX-XXXX
+fn new_user_code() -> String {
+    let mut code = String::with_capacity(9);
+    while code.len() < 9 {
+        let mut byte = [0u8; 1];
+        openssl::rand::rand_bytes(&mut byte).expect("openssl rand_bytes failed");
+        // reject the top of the range so every character is equally likely
+        let limit = 256 - (256 % USER_CODE_ALPHABET.len());
+        if (byte[0] as usize) >= limit {
+            continue;
+        }
+        if code.len() == 4 {
+            code.push('-');
+        }
+        code.push(USER_CODE_ALPHABET[byte[0] as usize % USER_CODE_ALPHABET.len()] as char);
+    }
+    code
+}
+
+// users type the code, be forgiving about case and the dash
+fn normalize_user_code(user_code: &str) -> String {
+    let letters: String = user_code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if letters.len() == 8 {
+        format!("{}-{}", &letters[..4], &letters[4..])
+    } else {
+        letters
+    }
+}
+
+#[actix_web::post("/device/code")]
+pub async fn device_code(
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    form: web::Form<DeviceCodeRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let client = match db
+        .get_oauth_client(&form.client_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(client) if client.allows_grant(DEVICE_CODE_GRANT) => client,
+        _ => {
+            return Ok(HttpResponse::Unauthorized().json(TokenErrorResponse::new("invalid_client")))
+        }
+    };
+    let scope = form.scope.clone().unwrap_or_default();
+    if !client.allows_scopes(&scope) {
+        return Ok(HttpResponse::BadRequest().json(TokenErrorResponse::new("invalid_scope")));
+    }
+
+    let device_code = new_opaque_token();
+    // user codes are short, now and then one is taken already
+    let mut attempts = 0;
+    let user_code = loop {
+        let user_code = new_user_code();
+        let inserted = db
+            .insert_device_authorization(
+                &hash_opaque_token(&device_code),
+                &user_code,
+                &client.client_id,
+                &scope,
+                POLL_INTERVAL_SECONDS,
+                Utc::now() + chrono::Duration::seconds(DEVICE_CODE_LIFETIME_SECONDS),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if inserted {
+            break user_code;
+        }
+        attempts += 1;
+        if attempts == USER_CODE_ATTEMPTS {
+            tracing::error!("no free user_code after {} attempts", attempts);
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(TokenErrorResponse::new("temporarily_unavailable")));
+        }
+    };
+
+    let verification_uri = app_data.device_verification_url.clone();
+    let verification_uri_complete = format!(
+        "{}?user_code={}",
+        verification_uri,
+        urlencoding::encode(&user_code)
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+        .json(DeviceCodeResponse {
+            device_code,
+            user_code,
+            verification_uri,
+            verification_uri_complete,
+            expires_in: DEVICE_CODE_LIFETIME_SECONDS,
+            interval: POLL_INTERVAL_SECONDS,
+        }))
+}
+
+// The device_code grant of the token endpoint, the client has been authenticated.
+pub async fn device_code_grant(
+    app_data: &YogaAppData,
+    db: &YogaDatabase,
+    client_id: &str,
+    code: Option<&str>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // not device_code, the handler above is a unit struct of that name
+    let code = match code {
+        Some(code) => code,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(TokenErrorResponse::describe(
+                "invalid_request",
+                "device_code is required",
+            )))
+        }
+    };
+
+    let poll = db
+        .poll_device_authorization(&hash_opaque_token(code), client_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let error = match poll {
+        DevicePoll::Approved { user_id, scope } => {
+            let (_, tokens) =
+                issue_token_family(app_data, db, user_id, Some(client_id), Some(&scope)).await?;
+            return Ok(HttpResponse::Ok()
+                .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+                .json(tokens));
+        }
+        DevicePoll::Pending => "authorization_pending",
+        DevicePoll::SlowDown => "slow_down",
+        DevicePoll::Denied => "access_denied",
+        DevicePoll::Expired => "expired_token",
+        DevicePoll::Invalid => "invalid_grant",
+    };
+    Ok(HttpResponse::BadRequest().json(TokenErrorResponse::new(error)))
+}
+
+#[derive(serde::Serialize)]
+pub struct DeviceRequestInfo {
+    user_code: String,
+    client_id: String,
+    client_name: String,
+    scope: String,
+    expires_at: chrono::DateTime<Utc>,
+}
+
+// What the verification page shows the user before they approve.
+#[actix_web::get("/device/{user_code}")]
+pub async fn device_request(
+    _user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_code = normalize_user_code(&path.into_inner());
+    match db
+        .get_pending_device_authorization(&user_code)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(request) => Ok(HttpResponse::Ok().json(DeviceRequestInfo {
+            user_code: request.user_code,
+            client_id: request.client_id,
+            client_name: request.client_name,
+            scope: request.scope,
+            expires_at: request.expires_at,
+        })),
+        None => Ok(HttpResponse::NotFound().body("no pending request for this code")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeviceDecision {
+    user_code: String,
+    approve: bool,
+}
+
+#[actix_web::post("/device")]
+pub async fn decide_device_request(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    decision: web::Json<DeviceDecision>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.not_impersonated()?;
+    let user_code = normalize_user_code(&decision.user_code);
+    let decided = db
+        .decide_device_authorization(&user_code, user.user_id, decision.approve)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if decided {
+        tracing::info!("device code {} decided by {}", user_code, user.user_id);
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().body("no pending request for this code"))
+    }
+}
@@ -0,0 +1,197 @@
+// Support: an admin views the app as one of the users. The admin gets an access token for the
+// user that carries the admin as actor, has no refresh token and stops working when the
+// impersonation is stopped or runs out. The admin role is never passed on, and neither account
+// deletion and export nor granting consents to clients are possible while impersonating.
+// Start and stop are written to the audit log of the user with the admin as actor.
+
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::Role;
+use crate::database::YogaDatabase;
+use crate::routes::tokens::access_token_response;
+use crate::session_state::{ImpersonationMarker, TypedSession};
+use crate::YogaAppData;
+use actix_web::{
+    cookie::{time::OffsetDateTime, Cookie, SameSite},
+    web, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct ImpersonationStarted {
+    access_token: String,
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImpersonationStatus {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    impersonator: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImpersonationStopped {
+    // the admin's own token again, if the impersonation was started in this browser session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access_token: Option<String>,
+}
+
+fn access_cookie(access_token: String, expires_at: DateTime<Utc>) -> Cookie<'static> {
+    let mut cookie = Cookie::build("access_token", access_token)
+        .path("/")
+        .same_site(SameSite::Strict)
+        .finish();
+    if let Ok(expires) = OffsetDateTime::from_unix_timestamp(expires_at.timestamp()) {
+        cookie.set_expires(expires);
+    }
+    cookie
+}
+
+#[actix_web::post("/admin/users/{user_id}/impersonate")]
+pub async fn start_impersonation(
+    admin: AuthenticatedUser,
+    session: TypedSession,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    admin.require_admin()?;
+    // no chains, stop the current one first
+    admin.not_impersonated()?;
+    let user_id = path.into_inner();
+    if user_id == admin.user_id {
+        return Ok(HttpResponse::Conflict().body("admins can't impersonate themselves"));
+    }
+    let user = db
+        .get_user_summary(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("no such user"))?;
+    if user.disabled_at.is_some() || user.deleted_at.is_some() {
+        return Ok(HttpResponse::Conflict().body("user is disabled or deleted"));
+    }
+
+    let roles: Vec<Role> = db
+        .get_user_roles(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .filter(|role| *role != Role::Admin)
+        .collect();
+    let expires_at = Utc::now() + chrono::Duration::minutes(app_data.impersonation_minutes);
+    let impersonation_id = db
+        .start_impersonation(admin.user_id, user_id, expires_at)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let access_token = app_data
+        .token_issuer
+        .issue_impersonation_token(
+            user_id,
+            impersonation_id,
+            roles,
+            admin.user_id,
+            expires_at.timestamp() as usize,
+        )
+        .map_err(|error| {
+            tracing::error!("failed to issue impersonation token {}", error);
+            actix_web::error::ErrorInternalServerError(error)
+        })?;
+
+    session.set_impersonation(ImpersonationMarker { impersonation_id, user_id, expires_at })?;
+    let detail = format!("until {}", expires_at.to_rfc3339());
+    AuthEvent::new(AuthEventKind::ImpersonationStarted)
+        .user(Some(user_id))
+        .actor(admin.user_id)
+        .detail(&detail)
+        .record(&db, &client_info)
+        .await;
+    tracing::info!("admin {} impersonating user {} until {}", admin.user_id, user_id, expires_at);
+
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(access_token.clone(), expires_at))
+        .json(ImpersonationStarted { access_token, user_id, expires_at }))
+}
+
+// For the banner of the frontend.
+#[actix_web::get("/impersonation")]
+pub async fn impersonation_status(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let impersonator = match user.impersonator {
+        Some(impersonator) => impersonator,
+        None => {
+            return Ok(HttpResponse::Ok().json(ImpersonationStatus {
+                active: false,
+                user_id: None,
+                email: None,
+                impersonator: None,
+                expires_at: None,
+            }))
+        }
+    };
+    let impersonation = db
+        .get_impersonation(user.session_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let email = db
+        .get_user_summary(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map(|summary| summary.email);
+    Ok(HttpResponse::Ok().json(ImpersonationStatus {
+        active: true,
+        user_id: Some(user.user_id),
+        email,
+        impersonator: Some(impersonator),
+        expires_at: impersonation.map(|impersonation| impersonation.expires_at),
+    }))
+}
+
+#[actix_web::post("/impersonation/stop")]
+pub async fn stop_impersonation(
+    user: AuthenticatedUser,
+    session: TypedSession,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let admin_id = match user.impersonator {
+        Some(admin_id) => admin_id,
+        None => return Ok(HttpResponse::Conflict().body("not impersonating")),
+    };
+    let ended = db
+        .end_impersonation(user.session_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if ended {
+        AuthEvent::new(AuthEventKind::ImpersonationStopped)
+            .user(Some(user.user_id))
+            .actor(admin_id)
+            .record(&db, &client_info)
+            .await;
+    }
+    session.end_impersonation();
+
+    // back to the admin's own login, if this browser session is it
+    let session_id = match (session.get_user_id()?, session.get_session_id()?) {
+        (Some(session_user), Some(session_id)) if session_user == admin_id => session_id,
+        _ => return Ok(HttpResponse::Ok().json(ImpersonationStopped { access_token: None })),
+    };
+    let tokens =
+        access_token_response(&app_data, &db, admin_id, session_id, None, None, None).await?;
+    let expires_at = Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64);
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(tokens.access_token.clone(), expires_at))
+        .json(ImpersonationStopped { access_token: Some(tokens.access_token) }))
+}
@@ -0,0 +1,514 @@
+// Sequences in and out of the app: a versioned JSON format, CSV of the steps for spreadsheets,
+// and Markdown or HTML to print. Imports match poses by id or by English or Sanskrit name. A
+// step whose pose isn't found is left out and reported, the rest is imported anyway.
+
+use std::collections::HashMap;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::YogaDatabase;
+use crate::routes::sequences::{
+    may_use_sequences, new_steps, sequence_detail, sequence_name, SequenceDetail, StepRequest,
+};
+use actix_web::{
+    http::header::{ContentDisposition, CONTENT_SECURITY_POLICY},
+    web, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const FORMAT: &str = "yogamat-sequences";
+// bumped whenever the format changes incompatibly, imports only take this one
+const VERSION: u32 = 1;
+const MAX_IMPORT_SEQUENCES: usize = 100;
+
+// the JSON file, exports hold one sequence, imports as many as they like
+#[derive(Serialize, Deserialize)]
+pub struct SequenceDocument {
+    format: String,
+    version: u32,
+    sequences: Vec<SequenceFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SequenceFile {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    steps: Vec<StepFile>,
+}
+
+// The pose by id or by name. An id that isn't in the catalog falls back to the name, so files
+// from another installation work as long as the names match.
+#[derive(Serialize, Deserialize)]
+pub struct StepFile {
+    pose_id: Option<i32>,
+    pose_name: Option<String>,
+    duration_seconds: Option<i32>,
+    breaths: Option<i32>,
+    side: Option<String>,
+    #[serde(default)]
+    notes: String,
+}
+
+// A row of the CSV. The position is for people reading the sheet, imports go by the order
+// of the rows.
+#[derive(Serialize, Deserialize)]
+struct CsvStep {
+    position: Option<i32>,
+    pose_id: Option<i32>,
+    pose_name: Option<String>,
+    duration_seconds: Option<i32>,
+    breaths: Option<i32>,
+    side: Option<String>,
+    notes: Option<String>,
+}
+
+#[derive(strum_macros::EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum FileFormat {
+    Json,
+    Csv,
+    Markdown,
+    Html,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+// ?format=json|csv, csv needs the sequence's name as ?name=
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    format: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportedSequence {
+    sequence_id: Uuid,
+    name: String,
+    step_count: usize,
+}
+
+#[derive(Serialize)]
+pub struct UnmatchedStep {
+    sequence: String,
+    // of the step in the file, from 1
+    position: usize,
+    pose_id: Option<i32>,
+    pose_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    imported: Vec<ImportedSequence>,
+    unmatched: Vec<UnmatchedStep>,
+}
+
+fn file_format(format: Option<&str>) -> Result<FileFormat, actix_web::Error> {
+    format
+        .unwrap_or("json")
+        .parse::<FileFormat>()
+        .map_err(|_| actix_web::error::ErrorBadRequest("format is json, csv, markdown or html"))
+}
+
+// "Morning Flow #2" -> morning-flow-2
+fn file_stem(name: &str) -> String {
+    let stem = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if stem.is_empty() {
+        "sequence".to_string()
+    } else {
+        stem
+    }
+}
+
+fn document(detail: &SequenceDetail) -> SequenceDocument {
+    SequenceDocument {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        sequences: vec![SequenceFile {
+            name: detail.sequence.name.clone(),
+            description: detail.sequence.description.clone(),
+            steps: detail
+                .steps
+                .iter()
+                .map(|step| StepFile {
+                    pose_id: Some(step.pose_id),
+                    pose_name: Some(step.pose_name.clone()),
+                    duration_seconds: step.duration_seconds,
+                    breaths: step.breaths,
+                    side: step.side.clone(),
+                    notes: step.notes.clone(),
+                })
+                .collect(),
+        }],
+    }
+}
+
+// A spreadsheet runs a cell starting with one of these as a formula, and the text comes from
+// whoever wrote the sequence, maybe somebody else whose shared sequence was copied.
+const FORMULA_START: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+// the quote makes the spreadsheet show the cell as text
+fn spreadsheet_text(text: &str) -> String {
+    if text.starts_with(FORMULA_START) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+// what spreadsheet_text quoted, as it was
+fn unquote_spreadsheet_text(text: String) -> String {
+    match text.strip_prefix('\'') {
+        Some(quoted) if quoted.starts_with(FORMULA_START) => quoted.to_string(),
+        _ => text,
+    }
+}
+
+fn csv_steps(detail: &SequenceDetail) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for step in &detail.steps {
+        writer.serialize(CsvStep {
+            position: Some(step.position + 1),
+            pose_id: Some(step.pose_id),
+            pose_name: Some(spreadsheet_text(&step.pose_name)),
+            duration_seconds: step.duration_seconds,
+            breaths: step.breaths,
+            side: step.side.clone(),
+            notes: Some(spreadsheet_text(&step.notes)),
+        })?;
+    }
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error().into())
+}
+
+// "30 seconds, left side"
+fn hold(duration_seconds: Option<i32>, breaths: Option<i32>, side: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    match (duration_seconds, breaths) {
+        (Some(seconds), _) => parts.push(format!("{} seconds", seconds)),
+        (None, Some(breaths)) => parts.push(format!("{} breaths", breaths)),
+        (None, None) => (),
+    }
+    match side {
+        Some("both") => parts.push("both sides".to_string()),
+        Some(side) => parts.push(format!("{} side", side)),
+        None => (),
+    }
+    parts.join(", ")
+}
+
+fn total_minutes(detail: &SequenceDetail) -> i64 {
+    let seconds: i64 = detail
+        .steps
+        .iter()
+        .filter_map(|step| step.duration_seconds)
+        .map(i64::from)
+        .sum();
+    (seconds + 59) / 60
+}
+
+fn markdown(detail: &SequenceDetail) -> String {
+    let mut text = format!("# {}\n\n", detail.sequence.name);
+    if !detail.sequence.description.is_empty() {
+        text.push_str(&format!("{}\n\n", detail.sequence.description));
+    }
+    text.push_str(&format!(
+        "{} poses, about {} minutes held\n\n",
+        detail.steps.len(),
+        total_minutes(detail)
+    ));
+    for (number, step) in detail.steps.iter().enumerate() {
+        text.push_str(&format!("{}. **{}**", number + 1, step.pose_name));
+        let held = hold(step.duration_seconds, step.breaths, step.side.as_deref());
+        if !held.is_empty() {
+            text.push_str(&format!(", {}", held));
+        }
+        if !step.notes.is_empty() {
+            text.push_str(&format!(" - {}", step.notes));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn html(detail: &SequenceDetail) -> String {
+    let name = escape_html(&detail.sequence.name);
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+        <style>body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }} \
+        li {{ margin-bottom: 0.5em; }} .notes {{ color: #555; }}</style>\n</head>\n<body>\n\
+        <h1>{}</h1>\n",
+        name, name
+    );
+    if !detail.sequence.description.is_empty() {
+        page.push_str(&format!(
+            "<p>{}</p>\n",
+            escape_html(&detail.sequence.description)
+        ));
+    }
+    page.push_str(&format!(
+        "<p>{} poses, about {} minutes held</p>\n<ol>\n",
+        detail.steps.len(),
+        total_minutes(detail)
+    ));
+    for step in &detail.steps {
+        page.push_str(&format!(
+            "<li><strong>{}</strong>",
+            escape_html(&step.pose_name)
+        ));
+        let held = hold(step.duration_seconds, step.breaths, step.side.as_deref());
+        if !held.is_empty() {
+            page.push_str(&format!(", {}", escape_html(&held)));
+        }
+        if !step.notes.is_empty() {
+            page.push_str(&format!(
+                "<br><span class=\"notes\">{}</span>",
+                escape_html(&step.notes)
+            ));
+        }
+        page.push_str("</li>\n");
+    }
+    page.push_str("</ol>\n</body>\n</html>\n");
+    page
+}
+
+// ?format=json (the default), csv, markdown or html
+#[actix_web::get("/sequences/{sequence_id}/export")]
+pub async fn export_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let format = file_format(query.format.as_deref())?;
+    let detail = match sequence_detail(&db, user.user_id, path.into_inner()).await? {
+        Some(detail) => detail,
+        None => return Ok(HttpResponse::NotFound().body("no such sequence")),
+    };
+    let stem = file_stem(&detail.sequence.name);
+    let response = match format {
+        FileFormat::Json => HttpResponse::Ok()
+            .insert_header(ContentDisposition::attachment(format!("{}.json", stem)))
+            .json(document(&detail)),
+        FileFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(ContentDisposition::attachment(format!("{}.csv", stem)))
+            .body(csv_steps(&detail).map_err(actix_web::error::ErrorInternalServerError)?),
+        FileFormat::Markdown => HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .insert_header(ContentDisposition::attachment(format!("{}.md", stem)))
+            .body(markdown(&detail)),
+        // shown in the browser for printing, it must not run anything
+        FileFormat::Html => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((
+                CONTENT_SECURITY_POLICY,
+                "default-src 'none'; style-src 'unsafe-inline'",
+            ))
+            .body(html(&detail)),
+    };
+    Ok(response)
+}
+
+fn parse_json(body: &[u8]) -> Result<Vec<SequenceFile>, actix_web::Error> {
+    let document: SequenceDocument = serde_json::from_slice(body).map_err(|error| {
+        actix_web::error::ErrorBadRequest(format!("invalid sequence file: {}", error))
+    })?;
+    if document.format != FORMAT {
+        return Err(actix_web::error::ErrorUnprocessableEntity(format!(
+            "format has to be {}",
+            FORMAT
+        )));
+    }
+    if document.version != VERSION {
+        return Err(actix_web::error::ErrorUnprocessableEntity(format!(
+            "version {} isn't supported, only {}",
+            document.version, VERSION
+        )));
+    }
+    Ok(document.sequences)
+}
+
+fn parse_csv(body: &[u8], name: Option<&str>) -> Result<Vec<SequenceFile>, actix_web::Error> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => return Err(actix_web::error::ErrorBadRequest("a csv import needs ?name=")),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    let mut steps = Vec::new();
+    for row in reader.deserialize::<CsvStep>() {
+        let row = row.map_err(|error| {
+            actix_web::error::ErrorBadRequest(format!("invalid csv: {}", error))
+        })?;
+        steps.push(StepFile {
+            pose_id: row.pose_id,
+            pose_name: row.pose_name.map(unquote_spreadsheet_text),
+            duration_seconds: row.duration_seconds,
+            breaths: row.breaths,
+            side: row.side,
+            notes: row.notes.map(unquote_spreadsheet_text).unwrap_or_default(),
+        });
+    }
+    Ok(vec![SequenceFile {
+        name,
+        description: String::new(),
+        steps,
+    }])
+}
+
+// Creates every sequence of the file with the steps whose pose was found. Nothing is created if
+// a sequence is invalid otherwise.
+#[actix_web::post("/sequences/import")]
+pub async fn import_sequences(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let files = match file_format(query.format.as_deref())? {
+        FileFormat::Json => parse_json(&body)?,
+        FileFormat::Csv => parse_csv(&body, query.name.as_deref())?,
+        _ => return Ok(HttpResponse::BadRequest().body("imports are json or csv")),
+    };
+    if files.len() > MAX_IMPORT_SEQUENCES {
+        return Ok(HttpResponse::BadRequest().body("too many sequences in one import"));
+    }
+
+    // ids first, names for the steps without a known id
+    let pose_ids: Vec<i32> = files
+        .iter()
+        .flat_map(|file| file.steps.iter().filter_map(|step| step.pose_id))
+        .collect();
+    let missing_ids = db
+        .missing_poses(&pose_ids, false)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let known_id = |step: &StepFile| {
+        step.pose_id
+            .filter(|pose_id| !missing_ids.contains(pose_id))
+    };
+    let names: Vec<String> = files
+        .iter()
+        .flat_map(|file| file.steps.iter())
+        .filter(|step| known_id(step).is_none())
+        .filter_map(|step| step.pose_name.as_deref().map(str::trim))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    let by_name: HashMap<String, i32> = db
+        .match_pose_names(&names)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .map(|found| (found.name, found.pose_id))
+        .collect();
+
+    let mut unmatched = Vec::new();
+    let mut step_lists: Vec<Vec<StepRequest>> = Vec::new();
+    for file in &files {
+        let mut steps = Vec::new();
+        for (index, step) in file.steps.iter().enumerate() {
+            let pose_id = known_id(step).or_else(|| {
+                step.pose_name
+                    .as_deref()
+                    .and_then(|name| by_name.get(name.trim()).copied())
+            });
+            match pose_id {
+                Some(pose_id) => steps.push(StepRequest {
+                    pose_id,
+                    duration_seconds: step.duration_seconds,
+                    breaths: step.breaths,
+                    side: step.side.clone(),
+                    notes: step.notes.clone(),
+                }),
+                None => unmatched.push(UnmatchedStep {
+                    sequence: file.name.clone(),
+                    position: index + 1,
+                    pose_id: step.pose_id,
+                    pose_name: step.pose_name.clone(),
+                }),
+            }
+        }
+        step_lists.push(steps);
+    }
+
+    let mut prepared = Vec::new();
+    for (file, steps) in files.iter().zip(&step_lists) {
+        let name = sequence_name(&file.name)?;
+        match new_steps(&db, steps).await? {
+            Ok(steps) => prepared.push((name, file.description.trim(), steps)),
+            Err(response) => return Ok(response),
+        }
+    }
+    let mut imported = Vec::new();
+    for (name, description, steps) in prepared {
+        let sequence_id = db
+            .create_sequence(user.user_id, name, description, &steps)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        imported.push(ImportedSequence {
+            sequence_id,
+            name: name.to_string(),
+            step_count: steps.len(),
+        });
+    }
+    tracing::info!(
+        "user {} imported {} sequences, {} steps unmatched",
+        user.user_id,
+        imported.len(),
+        unmatched.len()
+    );
+    Ok(HttpResponse::Created().json(ImportReport {
+        imported,
+        unmatched,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formulas_are_quoted_for_spreadsheets() {
+        assert_eq!(spreadsheet_text("=HYPERLINK(\"http://x\")"), "'=HYPERLINK(\"http://x\")");
+        assert_eq!(spreadsheet_text("+1"), "'+1");
+        assert_eq!(spreadsheet_text("-breathe"), "'-breathe");
+        assert_eq!(spreadsheet_text("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(spreadsheet_text("Downward Dog"), "Downward Dog");
+        assert_eq!(spreadsheet_text(""), "");
+    }
+
+    #[test]
+    fn quoted_text_imports_as_it_was() {
+        for text in ["=1+1", "-breathe", "Downward Dog", "'quoted on purpose", ""] {
+            assert_eq!(unquote_spreadsheet_text(spreadsheet_text(text)), text);
+        }
+    }
+}
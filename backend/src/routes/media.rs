@@ -0,0 +1,294 @@
+// Pose images: editors upload one per pose as multipart form data (field `image`), jpeg, png or
+// webp. Thumbnails in the configured widths are made right away. Clients only ever see signed
+// urls, which run out after media.url_ttl_minutes.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{MediaRecord, NewMedia, PoseWriteError, YogaDatabase};
+use crate::media::images::{self, UploadError};
+use crate::media::{self, Media};
+use crate::routes::poses::{if_match_version, may_edit_poses, pose_response, write_error};
+use actix_multipart::Multipart;
+use actix_web::{
+    http::header::{CACHE_CONTROL, CONTENT_LENGTH},
+    web, HttpRequest, HttpResponse,
+};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// the multipart envelope around the image
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PoseImage {
+    pub url: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    // narrowest first, only those narrower than the original
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Thumbnail {
+    pub width: i32,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct SignedQuery {
+    expires: u64,
+    signature: String,
+}
+
+fn pose_image(media: &Media, record: &MediaRecord) -> PoseImage {
+    PoseImage {
+        url: media.url(&media::original_key(record.media_id, &record.content_type)),
+        content_type: record.content_type.clone(),
+        width: record.width,
+        height: record.height,
+        thumbnails: record
+            .thumbnail_widths
+            .iter()
+            .map(|width| Thumbnail {
+                width: *width,
+                url: media.url(&media::thumbnail_key(record.media_id, *width)),
+            })
+            .collect(),
+    }
+}
+
+// The images with freshly signed urls, by media id.
+pub async fn pose_images(
+    db: &YogaDatabase,
+    media: &Media,
+    media_ids: &[Uuid],
+) -> Result<HashMap<Uuid, PoseImage>, actix_web::Error> {
+    if media_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let records = db
+        .get_media(media_ids)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(records
+        .iter()
+        .map(|record| (record.media_id, pose_image(media, record)))
+        .collect())
+}
+
+// storage errors are only logged, a leftover file harms nobody
+async fn remove_files(media: &Media, keys: &[String]) {
+    for key in keys {
+        if let Err(error) = media.delete(key).await {
+            tracing::error!("failed to delete media {}: {}", key, error);
+        }
+    }
+}
+
+async fn remove_media(media: &Media, record: Option<MediaRecord>) {
+    if let Some(record) = record {
+        let keys = media::media_keys(
+            record.media_id,
+            &record.content_type,
+            &record.thumbnail_widths,
+        );
+        remove_files(media, &keys).await;
+    }
+}
+
+// The bytes of the `image` field, or the response refusing them.
+async fn image_field(
+    mut payload: Multipart,
+    max_bytes: usize,
+) -> Result<Result<Vec<u8>, HttpResponse>, actix_web::Error> {
+    while let Some(mut field) = payload.try_next().await? {
+        if field.name() != "image" {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.try_next().await? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Ok(Err(HttpResponse::PayloadTooLarge()
+                    .body(format!("an image is at most {} bytes", max_bytes))));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        return Ok(Ok(bytes));
+    }
+    Ok(Err(HttpResponse::BadRequest().body("no image field")))
+}
+
+// Replaces the pose's image, If-Match like any other change of a pose.
+#[actix_web::put("/poses/{pose_id}/image")]
+pub async fn upload_pose_image(
+    request: HttpRequest,
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+    payload: Multipart,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_edit_poses(&user)?;
+    let version = if_match_version(&request)?;
+    let pose_id = path.into_inner();
+    // no use taking in the image for a write that fails anyway
+    match db
+        .get_pose(pose_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(pose) if pose.version == version => (),
+        Some(_) => return Ok(write_error(PoseWriteError::VersionMismatch)),
+        None => return Ok(write_error(PoseWriteError::NotFound)),
+    }
+    // refused before a byte of it is read if the client says so itself
+    let declared_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared_length.is_some_and(|length| length > media.max_upload_bytes + MULTIPART_OVERHEAD) {
+        return Ok(HttpResponse::PayloadTooLarge().body(format!(
+            "an image is at most {} bytes",
+            media.max_upload_bytes
+        )));
+    }
+    let bytes = match image_field(payload, media.max_upload_bytes).await? {
+        Ok(bytes) => bytes,
+        Err(response) => return Ok(response),
+    };
+
+    let max_dimension = media.max_dimension;
+    let widths = media.thumbnail_widths.clone();
+    let processed = web::block(move || {
+        images::process(&bytes, max_dimension, &widths).map(|image| (image, bytes))
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let (image, bytes) = match processed {
+        Ok(processed) => processed,
+        Err(error @ UploadError::UnsupportedType) => {
+            return Ok(HttpResponse::UnsupportedMediaType().body(error.to_string()))
+        }
+        Err(error) => return Ok(HttpResponse::UnprocessableEntity().body(error.to_string())),
+    };
+
+    let media_id = Uuid::new_v4();
+    let byte_size = bytes.len() as i32;
+    let thumbnail_widths: Vec<i32> = image
+        .thumbnails
+        .iter()
+        .map(|(width, _)| *width as i32)
+        .collect();
+    let mut uploads = vec![(
+        media::original_key(media_id, image.content_type),
+        image.content_type,
+        bytes,
+    )];
+    for (width, thumbnail) in image.thumbnails {
+        uploads.push((
+            media::thumbnail_key(media_id, width as i32),
+            "image/jpeg",
+            thumbnail,
+        ));
+    }
+    let mut stored = Vec::new();
+    for (key, content_type, bytes) in uploads {
+        if let Err(error) = media.put(&key, content_type, bytes).await {
+            tracing::error!("failed to store media {}: {}", key, error);
+            remove_files(&media, &stored).await;
+            return Ok(HttpResponse::InternalServerError().finish());
+        }
+        stored.push(key);
+    }
+
+    let new_media = NewMedia {
+        media_id,
+        content_type: image.content_type,
+        byte_size,
+        width: image.width as i32,
+        height: image.height as i32,
+        thumbnail_widths: &thumbnail_widths,
+        uploaded_by: user.user_id,
+    };
+    match db.set_pose_image(pose_id, version, Some(&new_media)).await {
+        Ok((pose, previous)) => {
+            tracing::info!(
+                "user {} uploaded image {} for pose {}",
+                user.user_id,
+                media_id,
+                pose_id
+            );
+            remove_media(&media, previous).await;
+            pose_response(&db, &media, HttpResponse::Ok(), pose).await
+        }
+        Err(error) => {
+            remove_files(&media, &stored).await;
+            Ok(write_error(error))
+        }
+    }
+}
+
+#[actix_web::delete("/poses/{pose_id}/image")]
+pub async fn delete_pose_image(
+    request: HttpRequest,
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_edit_poses(&user)?;
+    let version = if_match_version(&request)?;
+    let pose_id = path.into_inner();
+    match db.set_pose_image(pose_id, version, None).await {
+        Ok((pose, previous)) => {
+            tracing::info!(
+                "user {} removed the image of pose {}",
+                user.user_id,
+                pose_id
+            );
+            remove_media(&media, previous).await;
+            pose_response(&db, &media, HttpResponse::Ok(), pose).await
+        }
+        Err(error) => Ok(write_error(error)),
+    }
+}
+
+// The urls of the local storage end up here. No session needed, the signature is the
+// permission.
+#[actix_web::get("/media/{key:.*}")]
+pub async fn serve_media(
+    media: web::Data<Media>,
+    path: web::Path<String>,
+    query: web::Query<SignedQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let key = path.into_inner();
+    if !media::valid_key(&key) || !media.verify(&key, query.expires, &query.signature) {
+        return Ok(HttpResponse::Forbidden().body("invalid or expired media url"));
+    }
+    let bytes = match media.get(&key).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(HttpResponse::NotFound().finish()),
+        Err(error) => {
+            tracing::error!("failed to read media {}: {}", key, error);
+            return Ok(HttpResponse::InternalServerError().finish());
+        }
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0);
+    Ok(HttpResponse::Ok()
+        .content_type(media::content_type_of(&key))
+        // a media key never gets other content, only the url runs out
+        .insert_header((
+            CACHE_CONTROL,
+            format!("private, max-age={}", query.expires.saturating_sub(now)),
+        ))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(bytes))
+}
@@ -1,8 +1,43 @@
+pub mod account;
+pub mod admin;
+pub mod annotations;
+pub mod authorize;
+pub mod device;
+pub mod impersonation;
+pub mod interchange;
+pub mod media;
 pub mod oauth;
+pub mod oauth_token;
 pub mod poses;
+pub mod practice;
+pub mod security_events;
+pub mod sequences;
+pub mod sharing;
+pub mod stats;
+pub mod tokens;
+pub mod well_known;
 
 use actix_web::HttpResponse;
 
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 200;
+
+// ?page=&per_page= of the list endpoints
+#[derive(serde::Deserialize)]
+pub struct Paging {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl Paging {
+    // (page, per_page), pages start at 1
+    pub fn resolve(&self) -> (i64, i64) {
+        let page = self.page.unwrap_or(1).max(1);
+        let per_page = self.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        (page, per_page)
+    }
+}
+
 #[actix_web::get("/health_check")]
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
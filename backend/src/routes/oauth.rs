@@ -1,23 +1,58 @@
-use std::collections::HashMap;
-
-use crate::auth::{AuthClientType, GoogleAuth, GoogleClaims, GoogleClient, VerifyTokenError};
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::fusion::FusionProvider;
+use crate::auth::github::{github_http_client, GitHubProvider};
+use crate::auth::oidc::{verify_id_token, OidcProvider};
+use crate::auth::{AuthClientType, IdTokenFields, GoogleClaims, OidcClient, VerifyTokenError};
+use crate::database::YogaDatabase;
+use crate::routes::tokens::{issue_token_family, refresh_cookie, REFRESH_COOKIE, REFRESH_COOKIE_PATH};
 use crate::session_state::TypedSession;
-use crate::{auth::AuthName, YogaAppData};
+use crate::YogaAppData;
 use actix_web::{
     cookie::{
         time::{Duration, OffsetDateTime},
         Cookie, SameSite,
     },
     http::header::ContentType,
-    web, HttpResponse,
-};
-use jsonwebtoken::{Algorithm, DecodingKey, Validation, TokenData};
-use oauth2::{
-    basic::{BasicClient, BasicTokenType},
-    AccessToken, TokenIntrospectionResponse,
+    web, HttpRequest, HttpResponse,
 };
-use oauth2::{AuthorizationCode, CsrfToken, PkceCodeChallenge, Scope};
-use oauth2::{EmptyExtraTokenFields, PkceCodeVerifier, StandardTokenResponse, TokenResponse};
+use jsonwebtoken::{Algorithm, Validation};
+use oauth2::{basic::BasicTokenType, StandardRevocableToken};
+use oauth2::{AuthorizationCode, ClientId, CsrfToken, PkceCodeChallenge, RequestTokenError, Scope};
+use oauth2::{PkceCodeVerifier, StandardTokenResponse, TokenResponse};
+use uuid::Uuid;
+
+const AFTER_LOGOUT_URL: &str = "https://baeuerlin.net";
+// Google issues id_tokens under both spellings
+const GOOGLE_ISSUERS: [&str; 2] = ["accounts.google.com", "https://accounts.google.com"];
+
+#[derive(serde::Serialize)]
+pub struct LoginProviderInfo {
+    id: String,
+    display_name: String,
+    icon: Option<String>,
+    login_url: String,
+}
+
+// The providers that are enabled, the login page shows a button for each.
+#[actix_web::get("/auth/providers")]
+pub async fn login_providers(
+    request: HttpRequest,
+    app_data: web::Data<YogaAppData>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut providers = Vec::with_capacity(app_data.login_providers.len());
+    for provider in app_data.login_providers.iter() {
+        let login_url = request
+            .url_for("request_login_uri", [&provider.name])
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        providers.push(LoginProviderInfo {
+            id: provider.name.clone(),
+            display_name: provider.display_name.clone(),
+            icon: provider.icon.clone(),
+            login_url: login_url.into(),
+        });
+    }
+    Ok(HttpResponse::Ok().json(providers))
+}
 
 #[actix_web::get("/client-login/{service}")]
 pub async fn request_login_uri(
@@ -25,11 +60,12 @@ pub async fn request_login_uri(
     session: TypedSession,
     path: web::Path<String>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let oauth_provider: AuthName = match path.into_inner().as_str().try_into() {
-        Ok(name) => name,
-        Err(_) => {
-            tracing::error!("couldn't convert str into AuthName");
-            return Ok(HttpResponse::InternalServerError().body("oauth provider not in map"));
+    let provider_name = path.into_inner();
+    let client = match app_data.oauth_clients.get(&provider_name) {
+        Some(client) => client,
+        None => {
+            tracing::error!("login provider {} is not enabled", provider_name);
+            return Ok(HttpResponse::NotFound().body("oauth provider not in map"));
         }
     };
 
@@ -41,68 +77,50 @@ pub async fn request_login_uri(
     let (pkce_challenge, pkce_verifier): (PkceCodeChallenge, PkceCodeVerifier) =
         PkceCodeChallenge::new_random_sha256();
     session.set_pkce_verifier(pkce_verifier)?;
+    session.insert_oauth_provider(&provider_name)?;
 
     // Generate the full authorization URL and a cross site request forgery token
-    let (auth_url, csrf_token) = match oauth_provider {
-        AuthName::Google => match app_data.oauth_clients.get(&oauth_provider) {
-            Some(client) => {
-                session.insert_oauth_provider(AuthName::Google)?;
-                if let AuthClientType::Google(google_client) = client {
-                    google_client
-                        .authorize_url(CsrfToken::new_random)
-                        .add_scope(Scope::new("openid".to_string()))
-                        .add_scope(Scope::new("profile".to_string()))
-                        .add_scope(Scope::new("email".to_string()))
-                        .add_extra_param("access_type", "offline")
-                        .set_pkce_challenge(pkce_challenge)
-                        .url()
-                } else {
-                    panic!();
-                }
-            }
-            None => {
-                return Ok(HttpResponse::InternalServerError().body("oauth provider not in map"))
-            }
-        },
-        AuthName::Fusion => {
-            match app_data.oauth_clients.get(&oauth_provider) {
-                Some(client) => {
-                    session.insert_oauth_provider(AuthName::Fusion)?;
-                    if let AuthClientType::Basic(fusion_client) = client {
-                        fusion_client
-                            .authorize_url(CsrfToken::new_random)
-                            .add_scope(Scope::new("read".to_string()))
-                            .add_scope(Scope::new("write".to_string()))
-                            .add_scope(Scope::new("offline".to_string())) // refresh tokens
-                            .set_pkce_challenge(pkce_challenge)
-                            .url()
-                    } else {
-                        panic!();
-                    }
-                }
-                None => {
-                    return Ok(HttpResponse::InternalServerError().body("oauth provider not in map"))
-                }
-            }
-        }
-        AuthName::GitHub => match app_data.oauth_clients.get(&oauth_provider) {
-            Some(client) => {
-                session.insert_oauth_provider(AuthName::GitHub)?;
-                if let AuthClientType::Basic(github_client) = client {
-                    github_client
-                        .authorize_url(CsrfToken::new_random)
-                        .add_scope(Scope::new("read".to_string()))
-                        .add_scope(Scope::new("write".to_string()))
-                        .set_pkce_challenge(pkce_challenge)
-                        .url()
-                } else {
-                    panic!();
-                }
-            }
-            None => {
-                return Ok(HttpResponse::InternalServerError().body("oauth provider not in map"))
-            }
-        },
+    let (auth_url, csrf_token) = match client {
+        AuthClientType::Google(google_client) => google_client
+            .authorize_url(CsrfToken::new_random)
+            .add_scope(Scope::new("openid".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .add_scope(Scope::new("email".to_string()))
+            .add_extra_param("access_type", "offline")
+            .set_pkce_challenge(pkce_challenge)
+            .url(),
+        AuthClientType::Fusion(fusion) => fusion
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scope(Scope::new("openid".to_string()))
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .add_scope(Scope::new("offline_access".to_string())) // refresh tokens
+            .set_pkce_challenge(pkce_challenge)
+            .url(),
+        // read:user for /user, user:email for the private emails in /user/emails
+        AuthClientType::GitHub(github) => github
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scope(Scope::new("read:user".to_string()))
+            .add_scope(Scope::new("user:email".to_string()))
+            .set_pkce_challenge(pkce_challenge)
+            .url(),
+        AuthClientType::Oidc(oidc) => oidc
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(oidc.scopes.iter().cloned().map(Scope::new))
+            .set_pkce_challenge(pkce_challenge)
+            .url(),
+        // Apple only gives the email with form_post, see oauth_form_post_redirect
+        AuthClientType::Apple(apple) => apple
+            .oidc
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(apple.oidc.scopes.iter().cloned().map(Scope::new))
+            .add_extra_param("response_mode", "form_post")
+            .set_pkce_challenge(pkce_challenge)
+            .url(),
     };
 
     // Save the state token to verify later.
@@ -147,8 +165,73 @@ fn oauth_client<'a>(
 pub async fn logout(
     session: TypedSession,
     app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
 ) -> Result<HttpResponse, actix_web::Error> {
     let token = session.get_access_token()?;
+    let provider = session.get_oauth_provider()?;
+    let user_id = session.get_user_id()?;
+
+    // GitHub tokens don't expire, take back the grant so the token in the session is useless
+    if let (Some(token), Some(AuthClientType::GitHub(github))) =
+        (&token, oauth_client(&session, &app_data))
+    {
+        match github.revoke_grant(token.secret()).await {
+            Ok(()) => {
+                AuthEvent::new(AuthEventKind::TokenRevoked)
+                    .user(user_id)
+                    .provider(provider.as_deref())
+                    .detail("github grant")
+                    .record(&db, &client_info)
+                    .await
+            }
+            Err(error) => tracing::error!("failed to revoke github grant {}", error),
+        }
+    }
+
+    // FusionAuth and some OpenID Connect providers keep their own SSO session, the browser has
+    // to end it there
+    let id_token = session.get_id_token()?;
+    let provider_logout = match oauth_client(&session, &app_data) {
+        Some(AuthClientType::Fusion(fusion)) => {
+            Some(fusion.logout_redirect(id_token.as_deref(), AFTER_LOGOUT_URL))
+        }
+        Some(AuthClientType::Oidc(oidc)) => oidc.logout_redirect(id_token.as_deref(), AFTER_LOGOUT_URL),
+        _ => None,
+    };
+
+    // our refresh tokens for this login stop working too
+    if let Some(session_id) = session.get_session_id()? {
+        db.revoke_refresh_family(session_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        AuthEvent::new(AuthEventKind::TokenRevoked)
+            .user(user_id)
+            .provider(provider.as_deref())
+            .detail("refresh tokens")
+            .record(&db, &client_info)
+            .await;
+    }
+    // an impersonation started from this login ends with it
+    if let (Some(impersonation), Some(admin_id)) = (session.get_impersonation()?, user_id) {
+        let ended = db
+            .end_impersonation(impersonation.impersonation_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if ended {
+            AuthEvent::new(AuthEventKind::ImpersonationStopped)
+                .user(Some(impersonation.user_id))
+                .actor(admin_id)
+                .detail("logout")
+                .record(&db, &client_info)
+                .await;
+        }
+    }
+    AuthEvent::new(AuthEventKind::Logout)
+        .user(user_id)
+        .provider(provider.as_deref())
+        .record(&db, &client_info)
+        .await;
 
     // Since we are using session-based authentication a user is logged in if there is a valid
     // user id associated with the user_id key in the session state. To log out it is engough to
@@ -180,58 +263,72 @@ pub async fn logout(
             }
         }
     */
+    let mut refresh = Cookie::named(REFRESH_COOKIE);
+    refresh.set_path(REFRESH_COOKIE_PATH);
+    refresh.make_removal();
     Ok(HttpResponse::SeeOther()
-        .insert_header((actix_web::http::header::LOCATION, "https://baeuerlin.net"))
+        .insert_header((
+            actix_web::http::header::LOCATION,
+            provider_logout.unwrap_or_else(|| AFTER_LOGOUT_URL.to_string()),
+        ))
+        .cookie(refresh)
         .finish())
 }
 
-async fn introspect(
-    access_token: &AccessToken,
-    session: TypedSession,
-    app_data: web::Data<YogaAppData>,
-) -> Result<(), actix_web::Error> {
-    match oauth_client(&session, &app_data) {
-        Some(client) => match client {
-            AuthClientType::Google(google) => match google.introspect(access_token) {
-                Ok(request) => {
-                    match request
-                        .request_async(oauth2::reqwest::async_http_client)
-                        .await
-                    {
-                        Ok(response) => {
-                            let what = response.scopes();
-                        }
-                        Err(error) => {
-                            tracing::error!("introspection request failed {}", error);
-                        }
-                    }
-                }
-                Err(error) => {
-                    tracing::error!("introspection creation failed {}", error);
-                }
-            },
-            AuthClientType::Basic(basic) => match basic.introspect(access_token) {
-                Ok(request) => {
-                    match request
-                        .request_async(oauth2::reqwest::async_http_client)
-                        .await
-                    {
-                        Ok(response) => {
-                            let what = response.scopes();
-                        }
-                        Err(error) => {
-                            tracing::error!("introspection request failed {}", error);
-                        }
-                    }
-                }
+// Takes back what the user granted us at the provider of this login, when the account is
+// deleted. GitHub through its grant api, Google and FusionAuth through their RFC 7009 revocation
+// endpoint, the other providers have none configured and their tokens just expire.
+// True if something was revoked.
+pub async fn revoke_provider_tokens(
+    session: &TypedSession,
+    app_data: &YogaAppData,
+) -> Result<bool, actix_web::Error> {
+    let access_token = session.get_access_token()?;
+    let refresh_token = session.get_refresh_token()?;
+    let client = match session.get_oauth_provider()? {
+        Some(provider_name) => app_data.oauth_clients.get(&provider_name),
+        None => None,
+    };
+    // a refresh token stands for the whole grant
+    let revocable = match (refresh_token, access_token.clone()) {
+        (Some(refresh_token), _) => Some(StandardRevocableToken::RefreshToken(refresh_token)),
+        (None, Some(access_token)) => Some(StandardRevocableToken::AccessToken(access_token)),
+        (None, None) => None,
+    };
+    let revoked = match (client, access_token, revocable) {
+        (Some(AuthClientType::GitHub(github)), Some(access_token), _) => {
+            match github.revoke_grant(access_token.secret()).await {
+                Ok(()) => true,
                 Err(error) => {
-                    tracing::error!("introspection creation failed {}", error);
+                    tracing::error!("failed to revoke github grant {}", error);
+                    false
                 }
-            },
-        },
-        None => {}
+            }
+        }
+        (Some(AuthClientType::Google(client)), _, Some(token)) => revoke_token(client, token).await,
+        (Some(AuthClientType::Fusion(fusion)), _, Some(token)) => {
+            revoke_token(&fusion.client, token).await
+        }
+        _ => false,
+    };
+    Ok(revoked)
+}
+
+async fn revoke_token(client: &OidcClient, token: StandardRevocableToken) -> bool {
+    let request = match client.revoke_token(token) {
+        Ok(request) => request,
+        Err(error) => {
+            tracing::error!("no revocation endpoint {}", error);
+            return false;
+        }
+    };
+    match request.request_async(oauth2::reqwest::async_http_client).await {
+        Ok(()) => true,
+        Err(error) => {
+            tracing::error!("token revocation failed {}", error);
+            false
+        }
     }
-    Ok(())
 }
 
 #[derive(serde::Deserialize)]
@@ -240,11 +337,46 @@ pub struct LoginRedirect {
     state: String,
 }
 
+#[derive(serde::Deserialize)]
+pub struct FormPostRedirect {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+// response_mode=form_post (Apple). The provider's page POSTs here cross site, so the browser
+// leaves out our SameSite=Lax session cookie. A 303 turns it into a top level GET to
+// oauth_login_redirect, which gets the cookie and finishes the login.
+#[actix_web::post("/oauth-redirect")]
+pub async fn oauth_form_post_redirect(
+    request: HttpRequest,
+    app_data: web::Data<YogaAppData>,
+    form: web::Form<FormPostRedirect>,
+) -> HttpResponse {
+    let location = match (&form.code, &form.state) {
+        (Some(code), Some(state)) => format!(
+            "{}?code={}&state={}",
+            request.path(),
+            urlencoding::encode(code),
+            urlencoding::encode(state)
+        ),
+        _ => {
+            // user_cancelled_authorize, ...
+            tracing::info!("form_post login ended with {:?}", form.error);
+            app_data.login_url.clone()
+        }
+    };
+    HttpResponse::SeeOther()
+        .insert_header((actix_web::http::header::LOCATION, location))
+        .finish()
+}
+
 #[actix_web::get("/oauth-redirect")]
 pub async fn oauth_login_redirect(
     app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
     login: web::Query<LoginRedirect>,
-    //request: HttpRequest,
+    client_info: ClientInfo,
     session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     //tracing::info!("oauth_login_redirect");
@@ -261,47 +393,65 @@ pub async fn oauth_login_redirect(
     if let (Ok(Some(state)), Ok(Some(verifier))) =
         (session.get_state(), session.get_pkce_verifier())
     {
+        let provider = session.get_oauth_provider().ok().flatten();
         // verify the states are the same
         if login.state != *state.secret() {
             tracing::info!("State doesn't match. Something is terribly wrong.");
             // we may have been intercepted hacked or bamboozled
-            // also need to send something back better than this
+            AuthEvent::new(AuthEventKind::StateMismatch)
+                .provider(provider.as_deref())
+                .record(&db, &client_info)
+                .await;
+            session.purge();
             let response = HttpResponse::SeeOther()
                 .insert_header((actix_web::http::header::LOCATION, "/"))
                 .finish();
             return Ok(response);
         }
 
+        let provider = match provider {
+            Some(provider) => provider,
+            None => return Ok(HttpResponse::BadRequest().body("no login in progress")),
+        };
+        let app_data_ref = app_data.clone();
+        let attempt = LoginAttempt { app_data, db, session, provider, client_info };
+
         // OAuth flow
         // 6. The client then contacts the authorization server directly (not using the resource
         //    owners browser). Securely sends its client id, client secret, authorization code,
-        match oauth_client(&session, &app_data) {
-            Some(oauth_client) => match oauth_client {
-                AuthClientType::Basic(basic) => {
-                    return basic_exchange(
-                        app_data.clone(),
-                        session,
-                        login.code.clone(),
-                        verifier,
-                        basic,
-                    )
-                    .await;
-                }
-                AuthClientType::Google(google) => {
-                    return google_exchange(
-                        app_data.clone(),
-                        session,
-                        login.code.clone(),
-                        verifier,
-                        google,
-                    )
-                    .await;
-                }
-            },
+        let code = login.code.clone();
+        return match app_data_ref.oauth_clients.get(&attempt.provider) {
+            Some(AuthClientType::Fusion(fusion)) => {
+                fusion_exchange(attempt, code, verifier, fusion).await
+            }
+            Some(AuthClientType::GitHub(github)) => {
+                github_exchange(attempt, code, verifier, github).await
+            }
+            Some(AuthClientType::Oidc(oidc)) => {
+                oidc_exchange(attempt, code, verifier, oidc, &oidc.client).await
+            }
+            Some(AuthClientType::Apple(apple)) => {
+                let client = match apple.token_client() {
+                    Ok(client) => client,
+                    Err(error) => {
+                        tracing::error!("failed to sign apple client secret {}", error);
+                        return attempt
+                            .fail(HttpResponse::InternalServerError().finish(), "client secret")
+                            .await;
+                    }
+                };
+                oidc_exchange(attempt, code, verifier, &apple.oidc, &client).await
+            }
+            Some(AuthClientType::Google(google)) => {
+                google_exchange(attempt, code, verifier, google).await
+            }
             None => {
-                panic!();
+                tracing::error!("oauth provider {} not in map", attempt.provider);
+                attempt
+                    .fail(HttpResponse::BadRequest().body("unknown login provider"), "unknown provider")
+                    .await
             }
-        }
+        };
     }
     let error_str = session_error(session);
     // this is going to be the error response
@@ -344,76 +494,295 @@ fn session_error(session: TypedSession) -> String {
     error_str
 }
 
-async fn basic_exchange(
+// A login coming back from a provider, everything needed to finish it and to audit it.
+struct LoginAttempt {
     app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
     session: TypedSession,
+    // the name from configuration, "google", "github", ...
+    provider: String,
+    client_info: ClientInfo,
+}
+
+impl LoginAttempt {
+    // Records the failed login and forgets the half finished one.
+    async fn fail(self, response: HttpResponse, detail: &str) -> Result<HttpResponse, actix_web::Error> {
+        AuthEvent::new(AuthEventKind::LoginFailed)
+            .provider(Some(&self.provider))
+            .detail(detail)
+            .record(&self.db, &self.client_info)
+            .await;
+        self.session.purge();
+        Ok(response)
+    }
+
+    // Everything the providers have in common once we know which user_profile logged in.
+    // Issue our own access token, that is what the frontend gets, never the provider's token.
+    async fn complete(self, user_id: Uuid) -> Result<HttpResponse, actix_web::Error> {
+        let LoginAttempt { app_data, db, session, provider, client_info } = self;
+        let standing = db
+            .get_user_standing(user_id, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if standing.map(|standing| standing.disabled).unwrap_or(false) {
+            tracing::info!("disabled user {} tried to log in", user_id);
+            AuthEvent::new(AuthEventKind::LoginFailed)
+                .user(Some(user_id))
+                .provider(Some(&provider))
+                .detail("account disabled")
+                .record(&db, &client_info)
+                .await;
+            session.purge();
+            return Ok(HttpResponse::Forbidden().body("account disabled"));
+        }
+        let (session_id, tokens) = issue_token_family(&app_data, &db, user_id, None, None).await?;
+
+        session.insert_user_id(user_id)?;
+        session.insert_session_id(session_id)?;
+        // does this belong here? it belongs somewhere
+        session.renew();
+
+        // logging in within the grace period takes back a deletion
+        let restored = db
+            .restore_user(user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if restored {
+            AuthEvent::new(AuthEventKind::AccountRestored)
+                .user(Some(user_id))
+                .provider(Some(&provider))
+                .record(&db, &client_info)
+                .await;
+        }
+
+        AuthEvent::new(AuthEventKind::Login)
+            .user(Some(user_id))
+            .provider(Some(&provider))
+            .record(&db, &client_info)
+            .await;
+
+        // one of our registered clients sent the user to /oauth/authorize, go back there
+        let after_login_url = match session.take_return_to()? {
+            Some(return_to) => return_to,
+            None => app_data.after_login_url.clone(),
+        };
+
+        // back to frontend
+        let cookie = Cookie::build("access_token", tokens.access_token)
+            .path("/")
+            .same_site(SameSite::Strict)
+            .expires(OffsetDateTime::now_utc().checked_add(Duration::seconds(
+                tokens.expires_in as i64,
+            )))
+            .finish();
+
+        let mut response = HttpResponse::Found();
+        if let Some(refresh_token) = &tokens.refresh_token {
+            response.cookie(refresh_cookie(&app_data, refresh_token));
+        }
+        Ok(response
+            .append_header((actix_web::http::header::LOCATION, after_login_url))
+            .content_type(ContentType::html())
+            .cookie(cookie)
+            .finish())
+    }
+}
+
+async fn fusion_exchange(
+    login: LoginAttempt,
     code: String,
     verifier: PkceCodeVerifier,
-    basic: &BasicClient,
+    fusion: &FusionProvider,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let token_response = basic
+    let token = match fusion
+        .client
         .exchange_code(AuthorizationCode::new(code))
         .set_pkce_verifier(verifier)
         .request_async(oauth2::reqwest::async_http_client)
-        .await;
+        .await
+    {
+        Ok(token) => token,
+        Err(RequestTokenError::ServerResponse(error)) => {
+            tracing::error!("fusionauth token error {}", error);
+            let response = HttpResponse::Unauthorized().body(format!("FusionAuth login failed: {}", error));
+            return login.fail(response, "token exchange").await;
+        }
+        Err(error) => {
+            tracing::error!("fusionauth token request failed {}", error);
+            return login
+                .fail(HttpResponse::BadGateway().body("FusionAuth login failed"), "token request")
+                .await;
+        }
+    };
 
-    if let Ok(token) = token_response {
-        // this is the happy path
-        return receive_token(app_data, token, session).await;
-    } else {
-        // TODO error_str.push_str("<p>did not exchage code for token_response</p>")
-        panic!()
+    let id_token = &token.extra_fields().id_token;
+    let claims = match fusion.verify_id_token(id_token).await {
+        Ok(claims) => claims,
+        Err(error) => {
+            tracing::error!("fusionauth id_token error {}", error);
+            return login
+                .fail(HttpResponse::Unauthorized().body("id_token verification failed"), "id_token")
+                .await;
+        }
+    };
+    let email = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => {
+            tracing::error!("fusionauth email missing or not verified");
+            return login
+                .fail(HttpResponse::Unauthorized().body("email not verified"), "email not verified")
+                .await;
+        }
+    };
+
+    let roles = match fusion.fetch_roles(token.access_token().secret(), &claims.sub).await {
+        Ok(roles) => roles,
+        Err(error) => {
+            tracing::error!("fusionauth userinfo error {}", error);
+            return login
+                .fail(HttpResponse::Unauthorized().body(error.to_string()), "userinfo")
+                .await;
+        }
+    };
+
+    login.session.set_access_token(token.access_token().clone())?;
+    if let Some(refresh) = token.refresh_token() {
+        login.session.set_refresh_token(refresh.clone())?;
     }
+    // the id_token_hint for FusionAuth's logout
+    login.session.set_id_token(id_token.clone())?;
+
+    let user_id = login
+        .db
+        .user_for_identity(&login.provider, &claims.sub, &email)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    login
+        .db
+        .sync_provider_roles(user_id, &login.provider, &roles)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    login.complete(user_id).await
 }
 
-async fn receive_token(
-    app_data: web::Data<YogaAppData>,
-    token: StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>,
-    session: TypedSession,
+async fn oidc_exchange(
+    login: LoginAttempt,
+    code: String,
+    verifier: PkceCodeVerifier,
+    oidc: &OidcProvider,
+    client: &OidcClient,
 ) -> Result<HttpResponse, actix_web::Error> {
-    // oauth flow
-    // 8. The client doesn't understand the token but can use it to send requests to the resource server
-
-    //tracing::info!("matts fun token:\n{:#?}", token);
-
-    // The access token issued by the authorization server.
-    let jwt = token.access_token();
-    session.set_access_token(jwt.clone())?;
+    let token = match client
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(verifier)
+        .request_async(oauth2::reqwest::async_http_client)
+        .await
+    {
+        Ok(token) => token,
+        Err(RequestTokenError::ServerResponse(error)) => {
+            tracing::error!("{} token error {}", login.provider, error);
+            let response = HttpResponse::Unauthorized().body(format!("login failed: {}", error));
+            return login.fail(response, "token exchange").await;
+        }
+        Err(error) => {
+            tracing::error!("{} token request failed {}", login.provider, error);
+            return login
+                .fail(HttpResponse::BadGateway().body("login failed"), "token request")
+                .await;
+        }
+    };
 
-    //let extra = token.extra_fields();
-    //let token_type = token.token_type();
-    //let expires_in = token.expires_in();
+    let id_token = &token.extra_fields().id_token;
+    let claims = match oidc.verify_id_token(id_token).await {
+        Ok(claims) => claims,
+        Err(error) => {
+            tracing::error!("{} id_token error {}", login.provider, error);
+            return login
+                .fail(HttpResponse::Unauthorized().body("id_token verification failed"), "id_token")
+                .await;
+        }
+    };
+    let email = match oidc.verified_email(&claims) {
+        Some(email) => email,
+        None => {
+            tracing::error!("{} email missing or not verified", login.provider);
+            return login
+                .fail(HttpResponse::Unauthorized().body("email not verified"), "email not verified")
+                .await;
+        }
+    };
 
+    login.session.set_access_token(token.access_token().clone())?;
     if let Some(refresh) = token.refresh_token() {
-        session.set_refresh_token(refresh.clone())?;
+        login.session.set_refresh_token(refresh.clone())?;
     }
+    login.session.set_id_token(id_token.clone())?;
+
+    let user_id = login
+        .db
+        .user_for_identity(&login.provider, &claims.sub, &email)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    login.complete(user_id).await
+}
+
+async fn github_exchange(
+    login: LoginAttempt,
+    code: String,
+    verifier: PkceCodeVerifier,
+    github: &GitHubProvider,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token_response = github
+        .client
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(verifier)
+        .request_async(github_http_client)
+        .await;
 
-    // does this belong here? it belongs somewhere
-    session.renew();
+    let token = match token_response {
+        Ok(token) => token,
+        Err(RequestTokenError::ServerResponse(error)) => {
+            // bad_verification_code, incorrect_client_credentials, ...
+            tracing::error!("github token error {}", error);
+            let response = HttpResponse::Unauthorized().body(format!("GitHub login failed: {}", error));
+            return login.fail(response, "token exchange").await;
+        }
+        Err(error) => {
+            tracing::error!("github token request failed {}", error);
+            return login
+                .fail(HttpResponse::BadGateway().body("GitHub login failed"), "token request")
+                .await;
+        }
+    };
+    login.session.set_access_token(token.access_token().clone())?;
 
-    let after_login_url = app_data.after_login_url.clone();
-    //let what = introspect(jwt, session, app_data).await?;
+    let identity = match github.fetch_identity(token.access_token().secret()).await {
+        Ok(identity) => identity,
+        Err(error) => {
+            tracing::error!("github identity error {}", error);
+            return login
+                .fail(HttpResponse::Unauthorized().body(error.to_string()), "identity")
+                .await;
+        }
+    };
+    tracing::info!("github login {}", identity.login);
 
-    // back to frontend
-    let cookie = Cookie::build("access_token", jwt.secret())
-        .path("/")
-        .same_site(SameSite::Strict)
-        .expires(OffsetDateTime::now_utc().checked_add(Duration::minutes(60)))
-        .finish();
+    let user_id = login
+        .db
+        .user_for_identity(&login.provider, &identity.id.to_string(), &identity.email)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    Ok(HttpResponse::Found()
-        .append_header((actix_web::http::header::LOCATION, after_login_url))
-        .content_type(ContentType::html())
-        .cookie(cookie)
-        .finish())
+    login.complete(user_id).await
 }
 
 async fn google_exchange(
-    app_data: web::Data<YogaAppData>,
-    session: TypedSession,
+    login: LoginAttempt,
     code: String,
     verifier: PkceCodeVerifier,
-    google: &GoogleClient,
+    google: &OidcClient,
 ) -> Result<HttpResponse, actix_web::Error> {
     let token_response = google
         .exchange_code(AuthorizationCode::new(code))
@@ -423,19 +792,22 @@ async fn google_exchange(
 
     // OAuth flow
     // 7. The authorization server verifies the data and respondes with an access token
-    if let Ok(token) = token_response {
+    match token_response {
         // this is the happy path
-        return receive_google_token(app_data, token, session).await;
-    } else {
-        // TODO error_str.push_str("<p>did not exchage code for token_response</p>")
-        panic!()
+        Ok(token) => receive_google_token(login, token, google.client_id()).await,
+        Err(error) => {
+            tracing::error!("google token request failed {}", error);
+            login
+                .fail(HttpResponse::Unauthorized().body("Google login failed"), "token exchange")
+                .await
+        }
     }
 }
 
 async fn receive_google_token(
-    app_data: web::Data<YogaAppData>,
-    token: StandardTokenResponse<GoogleAuth, BasicTokenType>,
-    session: TypedSession,
+    login: LoginAttempt,
+    token: StandardTokenResponse<IdTokenFields, BasicTokenType>,
+    client_id: &ClientId,
 ) -> Result<HttpResponse, actix_web::Error> {
     // oauth flow
     // 8. The client doesn't understand the token but can use it to send requests to the resource server
@@ -444,46 +816,39 @@ async fn receive_google_token(
 
     // The access token issued by the authorization server.
     let jwt = token.access_token();
-    session.set_access_token(jwt.clone())?;
+    login.session.set_access_token(jwt.clone())?;
 
-    let extra: &GoogleAuth = token.extra_fields();
-    match verify_google_id_token(&extra.id_token).await {
-        Ok(claims) => {
-            tracing::info!("verify reqwest ok {:#?}", claims);
-        }
+    let extra: &IdTokenFields = token.extra_fields();
+    let claims = match verify_google_id_token(&extra.id_token, client_id).await {
+        Ok(claims) => claims,
         Err(error) => {
             tracing::error!("verify reqwest error {}", error);
+            return login
+                .fail(HttpResponse::Unauthorized().body("id_token verification failed"), "id_token")
+                .await;
         }
+    };
+    if !claims.email_verified {
+        tracing::error!("google email not verified");
+        return login
+            .fail(HttpResponse::Unauthorized().body("email not verified"), "email not verified")
+            .await;
     }
 
     //let token_type = token.token_type();
     //let expires_in = token.expires_in();
 
-    match token.refresh_token() {
-        Some(refresh) => {
-            session.set_refresh_token(refresh.clone())?;
-        }
-        None => {}
+    if let Some(refresh) = token.refresh_token() {
+        login.session.set_refresh_token(refresh.clone())?;
     }
 
-    let after_login_url = app_data.after_login_url.clone();
-    //let what = introspect(jwt, session, app_data).await?;
-    
-    // does this belong here? it belongs somewhere
-    session.renew();
-
-    // back to frontend
-    let cookie = Cookie::build("access_token", jwt.secret())
-        .path("/")
-        .same_site(SameSite::Strict)
-        .expires(OffsetDateTime::now_utc().checked_add(Duration::minutes(60)))
-        .finish();
+    let user_id = login
+        .db
+        .user_for_identity(&login.provider, &claims.sub, &claims.email)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    Ok(HttpResponse::Found()
-        .append_header((actix_web::http::header::LOCATION, after_login_url))
-        .content_type(ContentType::html())
-        .cookie(cookie)
-        .finish())
+    login.complete(user_id).await
 }
 
 // An ID Token is a JWT (JSON Web Token), that is, a cryptographically signed Base64-encoded JSON object.
@@ -499,55 +864,15 @@ async fn receive_google_token(
 
 // kid = the ID of the key used to sign this token
 // the id_token header should have a kid indicating the correct key in the jwks
-
-async fn verify_google_id_token(id_token: &str) -> Result<GoogleClaims, VerifyTokenError> {
-    let jwks = reqwest::get("https://www.googleapis.com/oauth2/v3/certs")
-        .await?
-        .json::<HashMap<String, Vec<HashMap<String, String>>>>()
-        .await?;
-    let header = jsonwebtoken::decode_header(&id_token).unwrap();
-    if let Some(token_kid) = header.kid {
-        let jwks_keys = jwks.get("keys").unwrap();
-        for key in jwks_keys {
-            match key.get("kid") {
-                Some(kid) => {
-                    if kid == &token_kid {
-                        let modulus = key.get("n").unwrap();
-                        let exponent = key.get("e").unwrap();
-                        match jsonwebtoken::decode::<GoogleClaims>(
-                            &id_token,
-                            &DecodingKey::from_rsa_components(modulus, exponent).expect("this to work"),
-                            &Validation::new(Algorithm::RS256),
-                        ) {
-                            Ok(token) => {
-                                let token: TokenData<GoogleClaims> = token;
-                                return Ok(token.claims);
-                            }
-                            Err(err) => {
-                                tracing::error!("jsonwebtoken error {}", err);
-                                return Err(VerifyTokenError::JsonwebTokenError(err));
-                            }
-                        };
-                    }
-                }
-                None => {
-                }
-            }
-        }
-        return Err(VerifyTokenError::KidNotFound);
-    } else {
-        tracing::error!("id_token has no kid in header");
-        return Err(VerifyTokenError::NoKid);
-    }
+// Google signs id_tokens for every client, only ones minted for ours are accepted.
+// https://developers.google.com/identity/openid-connect/openid-connect#validatinganidtoken
+
+async fn verify_google_id_token(
+    id_token: &str,
+    client_id: &ClientId,
+) -> Result<GoogleClaims, VerifyTokenError> {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_audience(&[client_id.as_str()]);
+    validation.set_issuer(&GOOGLE_ISSUERS);
+    verify_id_token(id_token, "https://www.googleapis.com/oauth2/v3/certs", &validation).await
 }
-
-
-    /*
-    use base64::{Engine as _, alphabet, engine::{self, general_purpose}};
-    let bytes = engine::GeneralPurpose::new(
-        &alphabet::URL_SAFE,
-        general_purpose::NO_PAD)
-        .decode(id_token).unwrap();
-    println!("BYTES!!!\n{:?}", bytes);
-    */
-
@@ -0,0 +1,298 @@
+// The token endpoint for clients of ours (not the providers we log in with).
+// RFC 6749 section 3.2, requests are form encoded, responses are json.
+
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::opaque_token::hash_opaque_token;
+use crate::database::{CodeRedemption, OAuthClient, YogaDatabase};
+use crate::rate_limit::{too_many_requests, RateLimiter};
+use crate::routes::device::{device_code_grant, DEVICE_CODE_GRANT};
+use crate::routes::tokens::{
+    issue_token_family, rotate_refresh_token, TokenErrorResponse, TokenResponse,
+};
+use crate::YogaAppData;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+
+#[derive(serde::Deserialize)]
+pub struct TokenRequest {
+    grant_type: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    device_code: Option<String>,
+    refresh_token: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    scope: Option<String>,
+}
+
+fn bad_request(error: TokenErrorResponse) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(error)
+}
+
+fn invalid_client() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Basic"))
+        .json(TokenErrorResponse::new("invalid_client"))
+}
+
+// client_secret_basic, RFC 6749 2.3.1, id and secret are form url encoded before base64
+fn basic_credentials(request: &HttpRequest) -> Option<(String, String)> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (id, secret) = decoded.split_once(':')?;
+    Some((
+        urlencoding::decode(id).ok()?.into_owned(),
+        urlencoding::decode(secret).ok()?.into_owned(),
+    ))
+}
+
+pub enum ClientAuthentication {
+    Authenticated(OAuthClient),
+    // a confidential client presented a secret that isn't its own
+    WrongSecret,
+    // missing credentials, an unknown client or a confidential one without its secret
+    Failed,
+}
+
+// Identifies the client from the basic auth header or the form (client_secret_post).
+// Confidential clients must present their secret, public clients only their id.
+pub async fn authenticate_client(
+    request: &HttpRequest,
+    form: &TokenRequest,
+    db: &YogaDatabase,
+) -> Result<ClientAuthentication, actix_web::Error> {
+    let (client_id, client_secret) = match basic_credentials(request) {
+        Some((id, secret)) => (id, Some(secret)),
+        None => match &form.client_id {
+            Some(id) => (id.clone(), form.client_secret.clone()),
+            None => return Ok(ClientAuthentication::Failed),
+        },
+    };
+    let client = match db
+        .get_oauth_client(&client_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(client) => client,
+        None => return Ok(ClientAuthentication::Failed),
+    };
+    match (&client.client_secret_hash, client_secret) {
+        (None, _) => Ok(ClientAuthentication::Authenticated(client)),
+        (Some(expected), Some(secret)) => {
+            let presented = hash_opaque_token(&secret);
+            if presented.len() == expected.len()
+                && openssl::memcmp::eq(presented.as_bytes(), expected.as_bytes())
+            {
+                Ok(ClientAuthentication::Authenticated(client))
+            } else {
+                Ok(ClientAuthentication::WrongSecret)
+            }
+        }
+        (Some(_), None) => Ok(ClientAuthentication::Failed),
+    }
+}
+
+#[actix_web::post("/token")]
+pub async fn token(
+    request: HttpRequest,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    limiter: web::Data<RateLimiter>,
+    client_info: ClientInfo,
+    form: web::Form<TokenRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let presented_client_id = basic_credentials(&request)
+        .map(|(id, _)| id)
+        .or_else(|| form.client_id.clone());
+    // Lockouts are per ip address and client, somebody guessing secrets elsewhere doesn't lock
+    // the client out for everybody.
+    let lockout_key = presented_client_id.as_ref().map(|client_id| {
+        format!("{}:{}", limiter.client_ip(&request).unwrap_or_default(), client_id)
+    });
+    if let Some(lockout_key) = &lockout_key {
+        if let Err(retry_after) = limiter.check_lockout(lockout_key).await {
+            return Ok(too_many_requests(retry_after));
+        }
+    }
+    let client = match authenticate_client(&request, &form, &db).await? {
+        ClientAuthentication::Authenticated(client) => client,
+        failure => {
+            // only wrong secrets count towards the lockout, anybody can present a client_id
+            if let (ClientAuthentication::WrongSecret, Some(lockout_key)) = (&failure, &lockout_key) {
+                limiter.record_failure(lockout_key).await;
+            }
+            AuthEvent::new(AuthEventKind::ClientAuthFailed)
+                .client(presented_client_id.as_deref())
+                .record(&db, &client_info)
+                .await;
+            return Ok(invalid_client());
+        }
+    };
+    if client.client_secret_hash.is_some() {
+        if let Some(lockout_key) = &lockout_key {
+            limiter.record_success(lockout_key).await;
+        }
+        // A public client's requests come from all of its users, they are only limited per ip.
+        // Device codes are polled at the interval we hand out and answered with slow_down, and
+        // refresh tokens are single use, so those grants aren't limited per client either.
+        let throttled = !matches!(form.grant_type.as_str(), DEVICE_CODE_GRANT | "refresh_token");
+        if throttled {
+            if let Err(retry_after) = limiter.check_account(&client.client_id).await {
+                return Ok(too_many_requests(retry_after));
+            }
+        }
+    }
+    if !client.allows_grant(&form.grant_type) {
+        return Ok(bad_request(TokenErrorResponse::new("unauthorized_client")));
+    }
+
+    match form.grant_type.as_str() {
+        DEVICE_CODE_GRANT => {
+            device_code_grant(&app_data, &db, &client.client_id, form.device_code.as_deref()).await
+        }
+        "authorization_code" => authorization_code_grant(&app_data, &db, &client, &form).await,
+        "client_credentials" => client_credentials_grant(&app_data, &client, form.scope.as_deref()),
+        "refresh_token" => {
+            let refresh_token = match form.refresh_token {
+                Some(refresh_token) => refresh_token,
+                None => {
+                    return Ok(bad_request(TokenErrorResponse::describe(
+                        "invalid_request",
+                        "refresh_token is required",
+                    )))
+                }
+            };
+            match rotate_refresh_token(
+                &app_data,
+                &db,
+                &refresh_token,
+                Some(&client.client_id),
+                &client_info,
+            )
+            .await?
+            {
+                Ok(tokens) => Ok(HttpResponse::Ok()
+                    .insert_header((header::CACHE_CONTROL, "no-store"))
+                    .json(tokens)),
+                Err(error) => Ok(bad_request(error)),
+            }
+        }
+        _ => Ok(bad_request(TokenErrorResponse::new("unsupported_grant_type"))),
+    }
+}
+
+async fn authorization_code_grant(
+    app_data: &YogaAppData,
+    db: &YogaDatabase,
+    client: &OAuthClient,
+    form: &TokenRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (code, redirect_uri, code_verifier) =
+        match (&form.code, &form.redirect_uri, &form.code_verifier) {
+            (Some(code), Some(redirect_uri), Some(code_verifier)) => {
+                (code, redirect_uri, code_verifier)
+            }
+            _ => {
+                return Ok(bad_request(TokenErrorResponse::describe(
+                    "invalid_request",
+                    "code, redirect_uri and code_verifier are required",
+                )))
+            }
+        };
+
+    let request = match db
+        .redeem_authorization_code(&hash_opaque_token(code), &client.client_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        CodeRedemption::Valid(request) => request,
+        CodeRedemption::Reused => {
+            tracing::warn!("authorization code reused by client {}", client.client_id);
+            return Ok(bad_request(TokenErrorResponse::new("invalid_grant")));
+        }
+        CodeRedemption::Invalid => return Ok(bad_request(TokenErrorResponse::new("invalid_grant"))),
+    };
+    if &request.redirect_uri != redirect_uri {
+        return Ok(bad_request(TokenErrorResponse::describe("invalid_grant", "redirect_uri mismatch")));
+    }
+    // RFC 7636 4.6, S256 is the only method we accept
+    let challenge = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(code_verifier.as_bytes()));
+    if challenge != request.code_challenge {
+        return Ok(bad_request(TokenErrorResponse::describe("invalid_grant", "code_verifier mismatch")));
+    }
+
+    let (family_id, mut tokens) = issue_token_family(
+        app_data,
+        db,
+        request.user_id,
+        Some(&client.client_id),
+        Some(&request.scope),
+    )
+    .await?;
+    db.set_authorization_family(request.request_id, family_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let scopes: Vec<&str> = request.scope.split_whitespace().collect();
+    if scopes.contains(&"openid") {
+        let email = if scopes.contains(&"email") {
+            db.get_user_email(request.user_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+        } else {
+            None
+        };
+        let id_token = app_data
+            .token_issuer
+            .issue_id_token(request.user_id, &client.client_id, request.nonce, email)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        tokens.id_token = Some(id_token);
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(tokens))
+}
+
+// RFC 6749 4.4, a machine client asks for a token for itself. Only confidential clients,
+// there is no user and no refresh token, the client just asks again.
+fn client_credentials_grant(
+    app_data: &YogaAppData,
+    client: &OAuthClient,
+    scope: Option<&str>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if client.client_secret_hash.is_none() {
+        return Ok(bad_request(TokenErrorResponse::new("unauthorized_client")));
+    }
+    // without a scope the client gets everything it is allowed
+    let scope = match scope {
+        Some(scope) => scope.to_string(),
+        None => client.allowed_scopes.join(" "),
+    };
+    if !client.allows_scopes(&scope) {
+        return Ok(bad_request(TokenErrorResponse::new("invalid_scope")));
+    }
+    let access_token = app_data
+        .token_issuer
+        .issue_client_access_token(&client.client_id, &scope)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    tracing::info!("client_credentials token for {}", client.client_id);
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(TokenResponse {
+            access_token,
+            token_type: "Bearer",
+            expires_in: app_data.token_issuer.access_token_ttl(),
+            refresh_token: None,
+            scope: Some(scope),
+            id_token: None,
+        }))
+}
@@ -1,46 +1,495 @@
-use crate::session_state::TypedSession;
-use actix_web::HttpResponse;
+// The pose catalog. Everybody with the poses scope reads it, editors and admins change it.
+// Every change bumps the pose's version, which is handed out as ETag and has to come back in
+// If-Match with PATCH and DELETE, so nobody overwrites a change they haven't seen.
+
+use crate::auth::extractor::{AuthenticatedUser, Principal};
+use crate::auth::Role;
+use crate::media::Media;
+use crate::routes::media::{pose_images, PoseImage};
+use crate::database::{
+    NewPose, PoseChanges, PoseFilter, PoseMatch, PoseRecord, PoseSort, PoseWriteError,
+    YogaDatabase,
+};
+use actix_web::{
+    http::header::{ETAG, IF_MATCH, LOCATION},
+    web, HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
 
 // also defined in /Users/matt/prog/rust/bevy_things/yoga_matt/frontend/src/api/poses.rs
 #[derive(Serialize, Deserialize)]
 pub struct PoseInfo {
     pub id: i32,
+    // English
     pub name: String,
+    pub sanskrit_name: String,
+    pub category: String,
+    pub difficulty: String,
+    pub description: String,
+    pub cues: Vec<String>,
+    pub contraindications: Vec<String>,
+    pub image: Option<PoseImage>,
+    #[serde(skip)]
+    image_id: Option<Uuid>,
+    pub version: i32,
+    // the signed in user's own, never anybody else's
+    pub favorite: bool,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl From<PoseRecord> for PoseInfo {
+    fn from(pose: PoseRecord) -> Self {
+        Self {
+            id: pose.pose_id,
+            name: pose.name,
+            sanskrit_name: pose.sanskrit_name,
+            category: pose.category,
+            difficulty: pose.difficulty,
+            description: pose.description,
+            cues: pose.cues,
+            contraindications: pose.contraindications,
+            image: None,
+            image_id: pose.image_id,
+            version: pose.version,
+            favorite: false,
+            tags: Vec::new(),
+            note: None,
+        }
+    }
+}
+
+impl From<PoseMatch> for PoseInfo {
+    fn from(found: PoseMatch) -> Self {
+        Self { favorite: found.favorite, tags: found.tags, ..Self::from(found.pose) }
+    }
+}
+
+// Fills in the images, with urls signed just now.
+pub async fn attach_images(
+    db: &YogaDatabase,
+    media: &Media,
+    poses: &mut [PoseInfo],
+) -> Result<(), actix_web::Error> {
+    let media_ids: Vec<Uuid> = poses.iter().filter_map(|pose| pose.image_id).collect();
+    let images = pose_images(db, media, &media_ids).await?;
+    for pose in poses.iter_mut() {
+        pose.image = pose.image_id.and_then(|media_id| images.get(&media_id).cloned());
+    }
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PoseListResponse {
     poses: Vec<PoseInfo>,
+    // all poses matching the query, not just this page
+    total: i64,
+    // pass as ?cursor= for the next page, None on the last one
+    next_cursor: Option<String>,
+}
+
+// GET /poses?q=&category=&difficulty=&favorites=&tag=&sort=&cursor=&limit=
+#[derive(Deserialize)]
+pub struct PoseQuery {
+    q: Option<String>,
+    category: Option<String>,
+    difficulty: Option<String>,
+    // only the user's favorites, or the poses they tagged so
+    #[serde(default)]
+    favorites: bool,
+    tag: Option<String>,
+    sort: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+// Where a page ended. Only good for the same sort, the filters are the client's business.
+#[derive(Serialize, Deserialize)]
+struct PoseCursor {
+    sort: String,
+    key: String,
+    id: i32,
 }
 
+impl PoseCursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+// Every word of the search as a prefix, so "down do" finds downdog. Accents are dropped in the
+// database, on both sides.
+fn search_query(q: &str) -> Option<String> {
+    let words: Vec<String> = q
+        .split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" & "))
+    }
+}
+
+// the catalog is fine for machine clients too, if they were given the scope
+fn may_read_poses(principal: &Principal) -> Result<(), actix_web::Error> {
+    if !principal.has_scope("poses") {
+        return Err(actix_web::error::ErrorForbidden("poses scope required"));
+    }
+    Ok(())
+}
 
 #[actix_web::get("/poses")]
 pub async fn look_at_poses(
-    session: TypedSession,
+    principal: Principal,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    query: web::Query<PoseQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    tracing::info!("look_at_poses");
-    match session.get_access_token() {
-        Ok(maybe_token) => {
-            match maybe_token {
-                Some(_) => {
-                    let poses = vec![
-                        PoseInfo { id: 0, name: "updog".to_string() },
-                        PoseInfo { id: 1, name: "downdog".to_string() },
-                        PoseInfo { id: 2, name: "yoganidrasana".to_string() },
-                    ];
-                    tracing::info!("we have a token");
-                    Ok(HttpResponse::Ok().json(PoseListResponse { poses }))
-                }
-                None => {
-                    tracing::info!("no token");
-                    Ok(HttpResponse::Unauthorized().into())
-                }
+    may_read_poses(&principal)?;
+    let query = query.into_inner();
+    let user_id = match &principal {
+        Principal::User(user) => Some(user.user_id),
+        Principal::Client(_) => None,
+    };
+    if user_id.is_none() && (query.favorites || query.tag.is_some()) {
+        return Ok(HttpResponse::BadRequest().body("favorites and tags are for users"));
+    }
+    let sort = match query.sort.as_deref() {
+        Some(sort) => sort
+            .parse::<PoseSort>()
+            .map_err(|_| actix_web::error::ErrorBadRequest("unknown sort"))?,
+        None => PoseSort::default(),
+    };
+    let filter = PoseFilter {
+        query: query.q.as_deref().and_then(search_query),
+        category: query.category.as_deref().map(valid_category).transpose()?.map(str::to_string),
+        difficulty: query
+            .difficulty
+            .as_deref()
+            .map(valid_difficulty)
+            .transpose()?
+            .map(str::to_string),
+        sort,
+        user_id,
+        favorites_only: query.favorites,
+        tag: query.tag.as_deref().map(|tag| tag.trim().to_lowercase()),
+    };
+    let cursor = match query.cursor.as_deref() {
+        Some(cursor) => match PoseCursor::decode(cursor) {
+            Some(cursor) if cursor.sort == sort.as_ref() => Some(cursor),
+            _ => return Ok(HttpResponse::BadRequest().body("invalid cursor")),
+        },
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    // one more than asked for tells whether there is another page
+    let mut matches = db
+        .search_poses(
+            &filter,
+            cursor.as_ref().map(|cursor| (cursor.key.as_str(), cursor.id)),
+            limit + 1,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let next_cursor = if matches.len() as i64 > limit {
+        matches.truncate(limit as usize);
+        matches.last().map(|last| {
+            PoseCursor {
+                sort: sort.as_ref().to_string(),
+                key: last.sort_key.clone(),
+                id: last.pose.pose_id,
             }
+            .encode()
+        })
+    } else {
+        None
+    };
+    let total = db
+        .count_poses(&filter)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut poses: Vec<PoseInfo> = matches.into_iter().map(PoseInfo::from).collect();
+    attach_images(&db, &media, &mut poses).await?;
+    Ok(HttpResponse::Ok().json(PoseListResponse { poses, total, next_cursor }))
+}
+
+#[actix_web::get("/poses/{pose_id}")]
+pub async fn look_at_pose(
+    principal: Principal,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_read_poses(&principal)?;
+    let pose = match db
+        .get_pose(path.into_inner())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(pose) => pose,
+        None => return Ok(HttpResponse::NotFound().body("no such pose")),
+    };
+    let version = pose.version;
+    let info = match &principal {
+        Principal::User(user) => annotated(&db, &media, user.user_id, pose).await?,
+        Principal::Client(_) => {
+            let mut info = PoseInfo::from(pose);
+            attach_images(&db, &media, std::slice::from_mut(&mut info)).await?;
+            info
         }
-        Err(err) => {
-            tracing::info!("error getting access token from session {}", err);
-            Ok(HttpResponse::InternalServerError().into())
+    };
+    Ok(HttpResponse::Ok().insert_header((ETAG, etag(version))).json(info))
+}
+
+// the pose with the user's favorite flag, tags and note on it
+pub async fn annotated(
+    db: &YogaDatabase,
+    media: &Media,
+    user_id: Uuid,
+    pose: PoseRecord,
+) -> Result<PoseInfo, actix_web::Error> {
+    let annotations = db
+        .get_pose_annotations(user_id, pose.pose_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut info = PoseInfo {
+        favorite: annotations.favorite,
+        tags: annotations.tags,
+        note: annotations.note,
+        ..PoseInfo::from(pose)
+    };
+    attach_images(db, media, std::slice::from_mut(&mut info)).await?;
+    Ok(info)
+}
+
+// the values the pose table accepts
+#[derive(strum_macros::EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum PoseCategory {
+    Standing,
+    Seated,
+    ForwardBend,
+    Backbend,
+    Twist,
+    Inversion,
+    Balance,
+    HipOpener,
+    Restorative,
+}
+
+#[derive(strum_macros::EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum Difficulty {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+#[derive(Deserialize)]
+pub struct CreatePose {
+    name: String,
+    sanskrit_name: String,
+    category: String,
+    difficulty: String,
+    description: String,
+    #[serde(default)]
+    cues: Vec<String>,
+    #[serde(default)]
+    contraindications: Vec<String>,
+}
+
+// only the fields to change
+#[derive(Deserialize)]
+pub struct PatchPose {
+    name: Option<String>,
+    sanskrit_name: Option<String>,
+    category: Option<String>,
+    difficulty: Option<String>,
+    description: Option<String>,
+    cues: Option<Vec<String>>,
+    contraindications: Option<Vec<String>>,
+}
+
+pub(crate) fn may_edit_poses(user: &AuthenticatedUser) -> Result<(), actix_web::Error> {
+    user.require_any_role(&[Role::Editor, Role::Admin])?;
+    if !user.has_scope("poses") {
+        return Err(actix_web::error::ErrorForbidden("poses scope required"));
+    }
+    Ok(())
+}
+
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+// The version the client last read. Writing without one is refused (428), a write must not
+// silently win over somebody else's.
+pub(crate) fn if_match_version(request: &HttpRequest) -> Result<i32, actix_web::Error> {
+    let value = match request.headers().get(IF_MATCH) {
+        Some(value) => value,
+        None => return Err(actix_web::error::ErrorPreconditionRequired("If-Match required")),
+    };
+    value
+        .to_str()
+        .ok()
+        .map(|value| value.trim().trim_start_matches("W/").trim_matches('"'))
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| actix_web::error::ErrorPreconditionFailed("unknown version in If-Match"))
+}
+
+fn required<'a>(field: &str, value: &'a str) -> Result<&'a str, actix_web::Error> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(format!("{} is required", field)));
+    }
+    Ok(value)
+}
+
+fn valid_category(category: &str) -> Result<&str, actix_web::Error> {
+    category
+        .parse::<PoseCategory>()
+        .map(|_| category)
+        .map_err(|_| actix_web::error::ErrorBadRequest("unknown category"))
+}
+
+fn valid_difficulty(difficulty: &str) -> Result<&str, actix_web::Error> {
+    difficulty
+        .parse::<Difficulty>()
+        .map(|_| difficulty)
+        .map_err(|_| actix_web::error::ErrorBadRequest("unknown difficulty"))
+}
+
+// blank lines of cues and contraindications are dropped
+fn non_blank(lines: &[String]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub(crate) fn write_error(error: PoseWriteError) -> HttpResponse {
+    match error {
+        PoseWriteError::NotFound => HttpResponse::NotFound().body(error.to_string()),
+        PoseWriteError::VersionMismatch => HttpResponse::PreconditionFailed().body(error.to_string()),
+        PoseWriteError::NameTaken => HttpResponse::Conflict().body(error.to_string()),
+        PoseWriteError::DatabaseError(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub(crate) async fn pose_response(
+    db: &YogaDatabase,
+    media: &Media,
+    mut response: actix_web::HttpResponseBuilder,
+    pose: PoseRecord,
+) -> Result<HttpResponse, actix_web::Error> {
+    let version = pose.version;
+    let mut info = PoseInfo::from(pose);
+    attach_images(db, media, std::slice::from_mut(&mut info)).await?;
+    Ok(response.insert_header((ETAG, etag(version))).json(info))
+}
+
+#[actix_web::post("/poses")]
+pub async fn create_pose(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    body: web::Json<CreatePose>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_edit_poses(&user)?;
+    let cues = non_blank(&body.cues);
+    let contraindications = non_blank(&body.contraindications);
+    let pose = NewPose {
+        name: required("name", &body.name)?,
+        sanskrit_name: required("sanskrit_name", &body.sanskrit_name)?,
+        category: valid_category(body.category.trim())?,
+        difficulty: valid_difficulty(body.difficulty.trim())?,
+        description: required("description", &body.description)?,
+        cues: &cues,
+        contraindications: &contraindications,
+    };
+    match db.insert_pose(&pose).await {
+        Ok(pose) => {
+            tracing::info!("user {} created pose {} {}", user.user_id, pose.pose_id, pose.name);
+            let mut response = HttpResponse::Created();
+            response.insert_header((LOCATION, format!("/api/v1/poses/{}", pose.pose_id)));
+            pose_response(&db, &media, response, pose).await
+        }
+        Err(error) => Ok(write_error(error)),
+    }
+}
+
+#[actix_web::patch("/poses/{pose_id}")]
+pub async fn update_pose(
+    request: HttpRequest,
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    media: web::Data<Media>,
+    path: web::Path<i32>,
+    body: web::Json<PatchPose>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_edit_poses(&user)?;
+    let version = if_match_version(&request)?;
+    let pose_id = path.into_inner();
+    let cues = body.cues.as_deref().map(non_blank);
+    let contraindications = body.contraindications.as_deref().map(non_blank);
+    let changes = PoseChanges {
+        name: body.name.as_deref().map(|name| required("name", name)).transpose()?,
+        sanskrit_name: body
+            .sanskrit_name
+            .as_deref()
+            .map(|name| required("sanskrit_name", name))
+            .transpose()?,
+        category: body.category.as_deref().map(|c| valid_category(c.trim())).transpose()?,
+        difficulty: body.difficulty.as_deref().map(|d| valid_difficulty(d.trim())).transpose()?,
+        description: body
+            .description
+            .as_deref()
+            .map(|description| required("description", description))
+            .transpose()?,
+        cues: cues.as_deref(),
+        contraindications: contraindications.as_deref(),
+    };
+    match db.update_pose(pose_id, version, &changes).await {
+        Ok(pose) => {
+            tracing::info!("user {} updated pose {} to version {}", user.user_id, pose_id, pose.version);
+            pose_response(&db, &media, HttpResponse::Ok(), pose).await
+        }
+        Err(error) => Ok(write_error(error)),
+    }
+}
+
+#[actix_web::delete("/poses/{pose_id}")]
+pub async fn delete_pose(
+    request: HttpRequest,
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_edit_poses(&user)?;
+    let version = if_match_version(&request)?;
+    let pose_id = path.into_inner();
+    match db.delete_pose(pose_id, version).await {
+        Ok(()) => {
+            tracing::info!("user {} deleted pose {}", user.user_id, pose_id);
+            Ok(HttpResponse::NoContent().finish())
         }
+        Err(error) => Ok(write_error(error)),
     }
 }
@@ -0,0 +1,204 @@
+// The practice log: what the user did and when, following one of their sequences or ad hoc.
+
+use std::collections::HashMap;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{
+    NewPractice, NewPracticeStep, PracticeRecord, PracticeStepRecord, YogaDatabase,
+};
+use crate::routes::sequences::MissingPoses;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const MAX_STEPS: usize = 500;
+const DEFAULT_HISTORY_DAYS: i64 = 30;
+const MAX_HISTORY_DAYS: i64 = 366;
+
+#[derive(Serialize)]
+pub struct PracticeDetail {
+    #[serde(flatten)]
+    pub practice: PracticeRecord,
+    pub steps: Vec<PracticeStepRecord>,
+}
+
+#[derive(Serialize)]
+pub struct PracticeHistory {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    sessions: Vec<PracticeDetail>,
+}
+
+#[derive(Deserialize)]
+pub struct PracticeStepRequest {
+    pose_id: i32,
+    actual_seconds: i32,
+    side: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PracticeRequest {
+    // None for an ad hoc practice
+    sequence_id: Option<Uuid>,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    effort: Option<i32>,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    steps: Vec<PracticeStepRequest>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+fn may_log_practice(user: &AuthenticatedUser) -> Result<(), actix_web::Error> {
+    if !user.has_scope("practice") {
+        return Err(actix_web::error::ErrorForbidden("practice scope required"));
+    }
+    Ok(())
+}
+
+fn validate_practice(practice: &PracticeRequest) -> Result<(), actix_web::Error> {
+    if practice.ended_at < practice.started_at {
+        return Err(actix_web::error::ErrorBadRequest("ended_at is before started_at"));
+    }
+    // a little leeway for clocks that are ahead
+    if practice.ended_at > Utc::now() + Duration::minutes(5) {
+        return Err(actix_web::error::ErrorBadRequest("ended_at is in the future"));
+    }
+    if practice.ended_at - practice.started_at > Duration::hours(24) {
+        return Err(actix_web::error::ErrorBadRequest("a practice lasts at most a day"));
+    }
+    if let Some(effort) = practice.effort {
+        if !(1..=10).contains(&effort) {
+            return Err(actix_web::error::ErrorBadRequest("effort is 1 to 10"));
+        }
+    }
+    if practice.steps.len() > MAX_STEPS {
+        return Err(actix_web::error::ErrorBadRequest("too many steps"));
+    }
+    for step in &practice.steps {
+        if step.actual_seconds < 0 {
+            return Err(actix_web::error::ErrorBadRequest("actual_seconds can't be negative"));
+        }
+        if !matches!(step.side.as_deref(), None | Some("left") | Some("right") | Some("both")) {
+            return Err(actix_web::error::ErrorBadRequest("side is left, right or both"));
+        }
+    }
+    Ok(())
+}
+
+// The user's practice started within [from, to), latest first, with its steps.
+pub async fn practice_history(
+    db: &YogaDatabase,
+    user_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<PracticeDetail>, actix_web::Error> {
+    let sessions = db
+        .list_practice(user_id, from, to)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let practice_ids: Vec<Uuid> = sessions.iter().map(|session| session.practice_id).collect();
+    let steps = db
+        .get_practice_steps(&practice_ids)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut steps_by_practice: HashMap<Uuid, Vec<PracticeStepRecord>> = HashMap::new();
+    for step in steps {
+        steps_by_practice.entry(step.practice_id).or_default().push(step);
+    }
+    Ok(sessions
+        .into_iter()
+        .map(|practice| {
+            let steps = steps_by_practice.remove(&practice.practice_id).unwrap_or_default();
+            PracticeDetail { practice, steps }
+        })
+        .collect())
+}
+
+#[actix_web::post("/practice")]
+pub async fn log_practice(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    body: web::Json<PracticeRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_log_practice(&user)?;
+    validate_practice(&body)?;
+    if let Some(sequence_id) = body.sequence_id {
+        if db
+            .get_sequence(user.user_id, sequence_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .is_none()
+        {
+            return Ok(HttpResponse::UnprocessableEntity().body("no such sequence"));
+        }
+    }
+    // a pose deleted from the catalog since can still have been practiced
+    let pose_ids: Vec<i32> = body.steps.iter().map(|step| step.pose_id).collect();
+    let missing = db
+        .missing_poses(&pose_ids, true)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !missing.is_empty() {
+        return Ok(HttpResponse::UnprocessableEntity()
+            .json(MissingPoses { error: "unknown poses", pose_ids: missing }));
+    }
+
+    let practice = NewPractice {
+        sequence_id: body.sequence_id,
+        started_at: body.started_at,
+        ended_at: body.ended_at,
+        effort: body.effort,
+        notes: body.notes.trim(),
+    };
+    let steps: Vec<NewPracticeStep> = body
+        .steps
+        .iter()
+        .map(|step| NewPracticeStep {
+            pose_id: step.pose_id,
+            actual_seconds: step.actual_seconds,
+            side: step.side.as_deref(),
+        })
+        .collect();
+    let practice_id = db
+        .insert_practice(user.user_id, &practice, &steps)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let practice = db
+        .get_practice(user.user_id, practice_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("logged practice is gone"))?;
+    let steps = db
+        .get_practice_steps(&[practice_id])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Created().json(PracticeDetail { practice, steps }))
+}
+
+// ?from=&to= as RFC 3339, the last 30 days by default.
+#[actix_web::get("/practice")]
+pub async fn practice_log(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_log_practice(&user)?;
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - Duration::days(DEFAULT_HISTORY_DAYS));
+    if from >= to {
+        return Ok(HttpResponse::BadRequest().body("from has to be before to"));
+    }
+    if to - from > Duration::days(MAX_HISTORY_DAYS) {
+        return Ok(HttpResponse::BadRequest().body("at most a year at a time"));
+    }
+    let sessions = practice_history(&db, user.user_id, from, to).await?;
+    Ok(HttpResponse::Ok().json(PracticeHistory { from, to, sessions }))
+}
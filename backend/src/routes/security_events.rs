@@ -0,0 +1,79 @@
+// The security audit log, a user's own events and the admin query over everyone's.
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{AuthEventFilter, AuthEventRecord, YogaDatabase};
+use crate::routes::Paging;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct AuthEventPage {
+    events: Vec<AuthEventRecord>,
+    page: i64,
+    per_page: i64,
+    has_more: bool,
+}
+
+async fn event_page(
+    db: &YogaDatabase,
+    filter: &AuthEventFilter,
+    paging: &Paging,
+) -> Result<AuthEventPage, actix_web::Error> {
+    let (page, per_page) = paging.resolve();
+    // one more than asked for tells whether there is another page
+    let mut events = db
+        .list_auth_events(filter, per_page + 1, (page - 1) * per_page)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let has_more = events.len() as i64 > per_page;
+    events.truncate(per_page as usize);
+    Ok(AuthEventPage { events, page, per_page, has_more })
+}
+
+#[actix_web::get("/me/security-events")]
+pub async fn my_security_events(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    paging: web::Query<Paging>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // ip addresses and user agents, a registered client needs its own scope for them
+    if !user.has_scope("security_events") {
+        return Ok(HttpResponse::Forbidden().body("security_events scope required"));
+    }
+    let filter = AuthEventFilter { user_id: Some(user.user_id), ..Default::default() };
+    Ok(HttpResponse::Ok().json(event_page(&db, &filter, &paging).await?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AuthEventQuery {
+    user_id: Option<Uuid>,
+    event_type: Option<String>,
+    provider: Option<String>,
+    ip: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    // not a flattened Paging, serde_urlencoded can't flatten numbers
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[actix_web::get("/admin/auth-events")]
+pub async fn auth_events(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    query: web::Query<AuthEventQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require_admin()?;
+    let query = query.into_inner();
+    let filter = AuthEventFilter {
+        user_id: query.user_id,
+        event_type: query.event_type,
+        provider: query.provider,
+        ip: query.ip,
+        from: query.from,
+        to: query.to,
+    };
+    let paging = Paging { page: query.page, per_page: query.per_page };
+    Ok(HttpResponse::Ok().json(event_page(&db, &filter, &paging).await?))
+}
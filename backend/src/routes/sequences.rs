@@ -0,0 +1,315 @@
+// Practice sequences users build from the pose catalog. A sequence only exists for its owner,
+// anyone else gets a 404 for it.
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{
+    NewSequenceStep, SequenceRecord, SequenceStepRecord, SequenceSummary, YogaDatabase,
+};
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const MAX_STEPS: usize = 200;
+// an hour in one pose, or as many breaths, is plenty
+const MAX_HOLD_SECONDS: i32 = 3600;
+const MAX_HOLD_BREATHS: i32 = 600;
+
+#[derive(Serialize)]
+pub struct SequenceListResponse {
+    sequences: Vec<SequenceSummary>,
+}
+
+#[derive(Serialize)]
+pub struct SequenceDetail {
+    #[serde(flatten)]
+    pub sequence: SequenceRecord,
+    pub steps: Vec<SequenceStepRecord>,
+}
+
+#[derive(Deserialize)]
+pub struct StepRequest {
+    pub pose_id: i32,
+    pub duration_seconds: Option<i32>,
+    pub breaths: Option<i32>,
+    // left, right or both
+    pub side: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+}
+
+// body of POST /sequences and PUT /sequences/{id}, the steps in order
+#[derive(Deserialize)]
+pub struct SequenceRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub steps: Vec<StepRequest>,
+}
+
+#[derive(Deserialize)]
+pub struct ReorderRequest {
+    step_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct DuplicateRequest {
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MissingPoses {
+    pub error: &'static str,
+    pub pose_ids: Vec<i32>,
+}
+
+pub(crate) fn may_use_sequences(user: &AuthenticatedUser) -> Result<(), actix_web::Error> {
+    if !user.has_scope("sequences") {
+        return Err(actix_web::error::ErrorForbidden("sequences scope required"));
+    }
+    Ok(())
+}
+
+fn validate_step(step: &StepRequest) -> Result<(), actix_web::Error> {
+    match (step.duration_seconds, step.breaths) {
+        (Some(_), Some(_)) => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "a step is held for duration_seconds or breaths, not both",
+            ))
+        }
+        (Some(value), None) | (None, Some(value)) if value <= 0 => {
+            return Err(actix_web::error::ErrorBadRequest("hold must be positive"))
+        }
+        (Some(seconds), None) if seconds > MAX_HOLD_SECONDS => {
+            return Err(actix_web::error::ErrorBadRequest("a step is held at most 3600 seconds"))
+        }
+        (None, Some(breaths)) if breaths > MAX_HOLD_BREATHS => {
+            return Err(actix_web::error::ErrorBadRequest("a step is held at most 600 breaths"))
+        }
+        _ => (),
+    }
+    match step.side.as_deref() {
+        None | Some("left") | Some("right") | Some("both") => Ok(()),
+        Some(_) => Err(actix_web::error::ErrorBadRequest("side is left, right or both")),
+    }
+}
+
+// The steps ready for the database, or the response refusing them. Every pose has to be in
+// the catalog.
+pub async fn new_steps<'a>(
+    db: &YogaDatabase,
+    steps: &'a [StepRequest],
+) -> Result<Result<Vec<NewSequenceStep<'a>>, HttpResponse>, actix_web::Error> {
+    if steps.len() > MAX_STEPS {
+        return Ok(Err(HttpResponse::BadRequest().body("too many steps")));
+    }
+    for step in steps {
+        validate_step(step)?;
+    }
+    let pose_ids: Vec<i32> = steps.iter().map(|step| step.pose_id).collect();
+    let missing = db
+        .missing_poses(&pose_ids, false)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !missing.is_empty() {
+        return Ok(Err(HttpResponse::UnprocessableEntity()
+            .json(MissingPoses { error: "unknown poses", pose_ids: missing })));
+    }
+    Ok(Ok(steps
+        .iter()
+        .map(|step| NewSequenceStep {
+            pose_id: step.pose_id,
+            duration_seconds: step.duration_seconds,
+            breaths: step.breaths,
+            side: step.side.as_deref(),
+            notes: step.notes.trim(),
+        })
+        .collect()))
+}
+
+pub(crate) fn sequence_name(name: &str) -> Result<&str, actix_web::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("name is required"));
+    }
+    Ok(name)
+}
+
+pub async fn sequence_detail(
+    db: &YogaDatabase,
+    user_id: Uuid,
+    sequence_id: Uuid,
+) -> Result<Option<SequenceDetail>, actix_web::Error> {
+    let sequence = match db
+        .get_sequence(user_id, sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(sequence) => sequence,
+        None => return Ok(None),
+    };
+    let steps = db
+        .get_sequence_steps(sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(Some(SequenceDetail { sequence, steps }))
+}
+
+async fn detail_response(
+    db: &YogaDatabase,
+    user_id: Uuid,
+    sequence_id: Uuid,
+    mut response: actix_web::HttpResponseBuilder,
+) -> Result<HttpResponse, actix_web::Error> {
+    match sequence_detail(db, user_id, sequence_id).await? {
+        Some(detail) => Ok(response.json(detail)),
+        None => Ok(HttpResponse::NotFound().body("no such sequence")),
+    }
+}
+
+#[actix_web::get("/sequences")]
+pub async fn list_sequences(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let sequences = db
+        .list_sequences(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(SequenceListResponse { sequences }))
+}
+
+#[actix_web::post("/sequences")]
+pub async fn create_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    body: web::Json<SequenceRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let name = sequence_name(&body.name)?;
+    let steps = match new_steps(&db, &body.steps).await? {
+        Ok(steps) => steps,
+        Err(response) => return Ok(response),
+    };
+    let sequence_id = db
+        .create_sequence(user.user_id, name, body.description.trim(), &steps)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut response = HttpResponse::Created();
+    response.insert_header((LOCATION, format!("/api/v1/sequences/{}", sequence_id)));
+    detail_response(&db, user.user_id, sequence_id, response).await
+}
+
+#[actix_web::get("/sequences/{sequence_id}")]
+pub async fn get_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    detail_response(&db, user.user_id, path.into_inner(), HttpResponse::Ok()).await
+}
+
+// Replaces the whole sequence, steps included.
+#[actix_web::put("/sequences/{sequence_id}")]
+pub async fn replace_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+    body: web::Json<SequenceRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let sequence_id = path.into_inner();
+    let name = sequence_name(&body.name)?;
+    let steps = match new_steps(&db, &body.steps).await? {
+        Ok(steps) => steps,
+        Err(response) => return Ok(response),
+    };
+    let replaced = db
+        .replace_sequence(user.user_id, sequence_id, name, body.description.trim(), &steps)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !replaced {
+        return Ok(HttpResponse::NotFound().body("no such sequence"));
+    }
+    detail_response(&db, user.user_id, sequence_id, HttpResponse::Ok()).await
+}
+
+#[actix_web::post("/sequences/{sequence_id}/reorder")]
+pub async fn reorder_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+    body: web::Json<ReorderRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let sequence_id = path.into_inner();
+    if db
+        .get_sequence(user.user_id, sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound().body("no such sequence"));
+    }
+    let reordered = db
+        .reorder_sequence_steps(sequence_id, &body.step_ids)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !reordered {
+        return Ok(HttpResponse::BadRequest().body("step_ids must list every step once"));
+    }
+    detail_response(&db, user.user_id, sequence_id, HttpResponse::Ok()).await
+}
+
+#[actix_web::post("/sequences/{sequence_id}/duplicate")]
+pub async fn duplicate_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+    body: Option<web::Json<DuplicateRequest>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let sequence_id = path.into_inner();
+    let original = match db
+        .get_sequence(user.user_id, sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(original) => original,
+        None => return Ok(HttpResponse::NotFound().body("no such sequence")),
+    };
+    let name = match body.and_then(|body| body.into_inner().name) {
+        Some(name) => sequence_name(&name)?.to_string(),
+        None => format!("{} (copy)", original.name),
+    };
+    let copy_id = match db
+        .duplicate_sequence(user.user_id, sequence_id, &name)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(copy_id) => copy_id,
+        None => return Ok(HttpResponse::NotFound().body("no such sequence")),
+    };
+    let mut response = HttpResponse::Created();
+    response.insert_header((LOCATION, format!("/api/v1/sequences/{}", copy_id)));
+    detail_response(&db, user.user_id, copy_id, response).await
+}
+
+#[actix_web::delete("/sequences/{sequence_id}")]
+pub async fn delete_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let deleted = db
+        .delete_sequence(user.user_id, path.into_inner())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !deleted {
+        return Ok(HttpResponse::NotFound().body("no such sequence"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
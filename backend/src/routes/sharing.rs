@@ -0,0 +1,182 @@
+// Sharing sequences, say a teacher's with their students. An unlisted or public sequence has a
+// share token, anyone with it reads the sequence at /shared/{token} without logging in and
+// users copy it into their own sequences. Public ones are listed at /shared as well.
+// Replacing the token, or making the sequence private, breaks every link handed out so far.
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::opaque_token::new_opaque_token;
+use crate::database::{
+    PublicSequenceSummary, SequenceRecord, SequenceStepRecord, SharedSequenceRecord, YogaDatabase,
+};
+use crate::routes::sequences::{may_use_sequences, sequence_detail, sequence_name};
+use crate::routes::Paging;
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(strum_macros::AsRefStr, strum_macros::EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum Visibility {
+    Private,
+    Unlisted,
+    Public,
+}
+
+#[derive(Deserialize)]
+pub struct ShareRequest {
+    visibility: String,
+}
+
+#[derive(Deserialize)]
+pub struct CopyRequest {
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ShareInfo {
+    visibility: String,
+    share_token: Option<String>,
+}
+
+impl From<SequenceRecord> for ShareInfo {
+    fn from(sequence: SequenceRecord) -> Self {
+        Self { visibility: sequence.visibility, share_token: sequence.share_token }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SharedSequence {
+    #[serde(flatten)]
+    sequence: SharedSequenceRecord,
+    steps: Vec<SequenceStepRecord>,
+}
+
+#[derive(Serialize)]
+pub struct PublicSequencePage {
+    sequences: Vec<PublicSequenceSummary>,
+    page: i64,
+    per_page: i64,
+    has_more: bool,
+}
+
+#[actix_web::put("/sequences/{sequence_id}/share")]
+pub async fn share_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+    body: web::Json<ShareRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let visibility = body.visibility.parse::<Visibility>().map_err(|_| {
+        actix_web::error::ErrorBadRequest("visibility is private, unlisted or public")
+    })?;
+    match db
+        .set_sequence_visibility(
+            user.user_id,
+            path.into_inner(),
+            visibility.as_ref(),
+            &new_opaque_token(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(sequence) => Ok(HttpResponse::Ok().json(ShareInfo::from(sequence))),
+        None => Ok(HttpResponse::NotFound().body("no such sequence")),
+    }
+}
+
+// A new share token, the links with the old one are dead.
+#[actix_web::post("/sequences/{sequence_id}/share/revoke")]
+pub async fn revoke_share_token(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let sequence_id = path.into_inner();
+    if let Some(sequence) = db
+        .replace_share_token(user.user_id, sequence_id, &new_opaque_token())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        tracing::info!(
+            "user {} replaced the share token of sequence {}",
+            user.user_id,
+            sequence_id
+        );
+        return Ok(HttpResponse::Ok().json(ShareInfo::from(sequence)));
+    }
+    match db
+        .get_sequence(user.user_id, sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(_) => Ok(HttpResponse::Conflict().body("the sequence is private, there is no link")),
+        None => Ok(HttpResponse::NotFound().body("no such sequence")),
+    }
+}
+
+// ?page=&per_page=, no login needed
+#[actix_web::get("/shared")]
+pub async fn public_sequences(
+    db: web::Data<YogaDatabase>,
+    query: web::Query<Paging>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (page, per_page) = query.resolve();
+    // one more than asked for tells whether there is another page
+    let mut sequences = db
+        .list_public_sequences(per_page + 1, (page - 1) * per_page)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let has_more = sequences.len() as i64 > per_page;
+    sequences.truncate(per_page as usize);
+    Ok(HttpResponse::Ok().json(PublicSequencePage { sequences, page, per_page, has_more }))
+}
+
+// read only and without login, an unknown, replaced or unshared token is a 404
+#[actix_web::get("/shared/{token}")]
+pub async fn shared_sequence(
+    db: web::Data<YogaDatabase>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sequence = match db
+        .get_shared_sequence(&path.into_inner())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(sequence) => sequence,
+        None => return Ok(HttpResponse::NotFound().body("no such shared sequence")),
+    };
+    let steps = db
+        .get_sequence_steps(sequence.sequence_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(SharedSequence { sequence, steps }))
+}
+
+// Into the user's own sequences, private, optional {"name"}.
+#[actix_web::post("/shared/{token}/copy")]
+pub async fn copy_shared_sequence(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    path: web::Path<String>,
+    body: Option<web::Json<CopyRequest>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    may_use_sequences(&user)?;
+    let name = body.and_then(|body| body.into_inner().name);
+    let name = name.as_deref().map(sequence_name).transpose()?;
+    let copy_id = match db
+        .copy_shared_sequence(user.user_id, &path.into_inner(), name)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(copy_id) => copy_id,
+        None => return Ok(HttpResponse::NotFound().body("no such shared sequence")),
+    };
+    match sequence_detail(&db, user.user_id, copy_id).await? {
+        Some(detail) => Ok(HttpResponse::Created()
+            .insert_header((LOCATION, format!("/api/v1/sequences/{}", copy_id)))
+            .json(detail)),
+        None => Err(actix_web::error::ErrorInternalServerError("copied sequence is gone")),
+    }
+}
@@ -0,0 +1,76 @@
+// What the practice log adds up to, for the dashboard. Computed by Postgres in the user's
+// timezone and kept for a while, logging practice or changing the timezone starts over.
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::database::{PracticeStats, YogaDatabase};
+use actix_web::{web, HttpResponse};
+
+const WEEKS: i32 = 12;
+const TOP_POSES: i64 = 5;
+const CACHE_MINUTES: i64 = 60;
+
+#[derive(serde::Deserialize)]
+pub struct TimezoneRequest {
+    // IANA name, Europe/Vienna
+    timezone: String,
+}
+
+#[actix_web::get("/me/stats")]
+pub async fn my_stats(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !user.has_scope("practice") {
+        return Ok(HttpResponse::Forbidden().body("practice scope required"));
+    }
+    let timezone = db
+        .get_user_timezone(user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("no such user"))?;
+
+    let cached = db
+        .get_cached_stats(user.user_id, &timezone, chrono::Duration::minutes(CACHE_MINUTES))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if let Some(stats) = cached {
+        // stored by us, json already
+        return Ok(HttpResponse::Ok().content_type("application/json").body(stats));
+    }
+
+    let stats: PracticeStats = db
+        .compute_practice_stats(user.user_id, &timezone, WEEKS, TOP_POSES)
+        .await
+        .map_err(|error| {
+            tracing::error!("failed to compute practice stats {:?}", error);
+            actix_web::error::ErrorInternalServerError(error)
+        })?;
+    match serde_json::to_string(&stats) {
+        Ok(json) => {
+            if let Err(error) = db.store_cached_stats(user.user_id, &timezone, &json).await {
+                tracing::error!("failed to cache practice stats {}", error);
+            }
+        }
+        Err(error) => tracing::error!("failed to serialize practice stats {}", error),
+    }
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[actix_web::put("/me/timezone")]
+pub async fn set_timezone(
+    user: AuthenticatedUser,
+    db: web::Data<YogaDatabase>,
+    body: web::Json<TimezoneRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if user.client_id.is_some() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let updated = db
+        .set_user_timezone(user.user_id, body.timezone.trim())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !updated {
+        return Ok(HttpResponse::BadRequest().body("unknown timezone"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,257 @@
+use crate::auth::audit::{AuthEvent, AuthEventKind, ClientInfo};
+use crate::auth::opaque_token::{hash_opaque_token, new_opaque_token};
+use crate::csrf::csrf_token;
+use crate::database::{RefreshOutcome, YogaDatabase};
+use crate::YogaAppData;
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    web, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+pub const REFRESH_COOKIE: &str = "refresh_token";
+// the refresh cookie is only ever sent to the refresh endpoint
+pub const REFRESH_COOKIE_PATH: &str = "/api/v1/auth";
+
+#[derive(serde::Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenErrorResponse {
+    pub error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl TokenErrorResponse {
+    pub fn new(error: &'static str) -> Self {
+        Self { error, error_description: None }
+    }
+
+    pub fn describe(error: &'static str, description: &str) -> Self {
+        Self { error, error_description: Some(description.to_string()) }
+    }
+}
+
+// Starts a new refresh token family (one per login) and signs an access token for it.
+// The family id doubles as the session id in the access token.
+// `client_id` and `scope` are set for tokens held by one of our registered clients.
+pub async fn issue_token_family(
+    app_data: &YogaAppData,
+    db: &YogaDatabase,
+    user_id: Uuid,
+    client_id: Option<&str>,
+    scope: Option<&str>,
+) -> Result<(Uuid, TokenResponse), actix_web::Error> {
+    let family_id = Uuid::new_v4();
+    let refresh_token = new_opaque_token();
+    db.insert_refresh_token(
+        family_id,
+        user_id,
+        &hash_opaque_token(&refresh_token),
+        Utc::now() + app_data.token_issuer.refresh_token_ttl(),
+        client_id,
+        scope,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let response = access_token_response(
+        app_data,
+        db,
+        user_id,
+        family_id,
+        client_id,
+        scope,
+        Some(refresh_token),
+    )
+    .await?;
+    Ok((family_id, response))
+}
+
+pub async fn access_token_response(
+    app_data: &YogaAppData,
+    db: &YogaDatabase,
+    user_id: Uuid,
+    family_id: Uuid,
+    client_id: Option<&str>,
+    scope: Option<&str>,
+    refresh_token: Option<String>,
+) -> Result<TokenResponse, actix_web::Error> {
+    // every token for a user is made here, a disabled user gets none
+    let standing = db
+        .get_user_standing(user_id, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if standing.map(|standing| standing.disabled).unwrap_or(true) {
+        return Err(actix_web::error::ErrorForbidden("account disabled"));
+    }
+    let roles = db
+        .get_user_roles(user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let access_token = app_data
+        .token_issuer
+        .issue_delegated_access_token(user_id, family_id, roles, client_id, scope)
+        .map_err(|error| {
+            tracing::error!("failed to issue access token {}", error);
+            actix_web::error::ErrorInternalServerError(error)
+        })?;
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: app_data.token_issuer.access_token_ttl(),
+        refresh_token,
+        scope: scope.map(str::to_string),
+        id_token: None,
+    })
+}
+
+pub fn refresh_cookie(app_data: &YogaAppData, refresh_token: &str) -> Cookie<'static> {
+    Cookie::build(REFRESH_COOKIE, refresh_token.to_string())
+        .path(REFRESH_COOKIE_PATH)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::seconds(
+            app_data.token_issuer.refresh_token_ttl().num_seconds(),
+        ))
+        .finish()
+}
+
+// Exchanges a refresh token for a new access token and a new refresh token.
+// A refresh token can be used exactly once. Replaying one revokes every token of its family,
+// the client then has to log in again.
+pub async fn rotate_refresh_token(
+    app_data: &YogaAppData,
+    db: &YogaDatabase,
+    refresh_token: &str,
+    client_id: Option<&str>,
+    client_info: &ClientInfo,
+) -> Result<Result<TokenResponse, TokenErrorResponse>, actix_web::Error> {
+    let new_refresh_token = new_opaque_token();
+    let outcome = db
+        .rotate_refresh_token(
+            &hash_opaque_token(refresh_token),
+            &hash_opaque_token(&new_refresh_token),
+            Utc::now() + app_data.token_issuer.refresh_token_ttl(),
+            client_id,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match outcome {
+        RefreshOutcome::Rotated { user_id, family_id, client_id, scope } => {
+            let response = access_token_response(
+                app_data,
+                db,
+                user_id,
+                family_id,
+                client_id.as_deref(),
+                scope.as_deref(),
+                Some(new_refresh_token),
+            )
+            .await?;
+            AuthEvent::new(AuthEventKind::Refresh)
+                .user(Some(user_id))
+                .client(client_id.as_deref())
+                .record(db, client_info)
+                .await;
+            Ok(Ok(response))
+        }
+        RefreshOutcome::Reused { user_id, family_id } => {
+            tracing::warn!(
+                "refresh token reused for user {} family {}, family revoked",
+                user_id,
+                family_id
+            );
+            AuthEvent::new(AuthEventKind::RefreshReused)
+                .user(Some(user_id))
+                .client(client_id)
+                .detail("family revoked")
+                .record(db, client_info)
+                .await;
+            Ok(Err(TokenErrorResponse::describe("invalid_grant", "refresh token reused")))
+        }
+        RefreshOutcome::Expired => Ok(Err(TokenErrorResponse::describe(
+            "invalid_grant",
+            "refresh token expired",
+        ))),
+        RefreshOutcome::NotFound => Ok(Err(TokenErrorResponse::new("invalid_grant"))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+// Browsers send the refresh token in the http only cookie set at login, mobile and cli clients
+// that can't keep cookies send it in the body.
+#[actix_web::post("/auth/refresh")]
+pub async fn refresh(
+    request: HttpRequest,
+    app_data: web::Data<YogaAppData>,
+    db: web::Data<YogaDatabase>,
+    client_info: ClientInfo,
+    body: Option<web::Json<RefreshRequest>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let from_cookie = body.is_none();
+    let refresh_token = match body {
+        Some(body) => body.into_inner().refresh_token,
+        None => match request.cookie(REFRESH_COOKIE) {
+            Some(cookie) => cookie.value().to_string(),
+            None => {
+                return Ok(HttpResponse::BadRequest()
+                    .json(TokenErrorResponse::describe("invalid_request", "no refresh token")))
+            }
+        },
+    };
+
+    match rotate_refresh_token(&app_data, &db, &refresh_token, None, &client_info).await? {
+        Ok(response) => {
+            let mut builder = HttpResponse::Ok();
+            if from_cookie {
+                if let Some(refresh_token) = &response.refresh_token {
+                    builder.cookie(refresh_cookie(&app_data, refresh_token));
+                }
+                let response = TokenResponse { refresh_token: None, ..response };
+                return Ok(builder.json(response));
+            }
+            Ok(builder.json(response))
+        }
+        Err(error) => {
+            let mut builder = HttpResponse::BadRequest();
+            if from_cookie {
+                let mut cookie = Cookie::named(REFRESH_COOKIE);
+                cookie.set_path(REFRESH_COOKIE_PATH);
+                cookie.make_removal();
+                builder.cookie(cookie);
+            }
+            Ok(builder.json(error))
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CsrfResponse {
+    csrf_token: String,
+}
+
+// The token to send in x-csrf-token, for clients that can't read the csrf_token cookie.
+#[actix_web::get("/auth/csrf")]
+pub async fn csrf(request: HttpRequest) -> HttpResponse {
+    match csrf_token(&request) {
+        Some(csrf_token) => HttpResponse::Ok().json(CsrfResponse { csrf_token }),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,68 @@
+use crate::YogaAppData;
+use actix_web::{web, HttpResponse};
+
+// The public keys our access tokens are signed with, for services that verify them.
+#[actix_web::get("/.well-known/jwks.json")]
+pub async fn jwks(app_data: web::Data<YogaAppData>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=3600"))
+        .json(app_data.token_issuer.jwks())
+}
+
+#[derive(serde::Serialize)]
+pub struct OpenIdConfiguration {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+    jwks_uri: String,
+    device_authorization_endpoint: String,
+    response_types_supported: Vec<&'static str>,
+    grant_types_supported: Vec<&'static str>,
+    subject_types_supported: Vec<&'static str>,
+    id_token_signing_alg_values_supported: Vec<&'static str>,
+    scopes_supported: Vec<&'static str>,
+    token_endpoint_auth_methods_supported: Vec<&'static str>,
+    code_challenge_methods_supported: Vec<&'static str>,
+    claims_supported: Vec<&'static str>,
+}
+
+// OpenID Connect Discovery 1.0, for our registered clients
+#[actix_web::get("/.well-known/openid-configuration")]
+pub async fn openid_configuration(app_data: web::Data<YogaAppData>) -> HttpResponse {
+    let issuer = app_data.token_issuer.issuer().to_string();
+    HttpResponse::Ok()
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=3600"))
+        .json(OpenIdConfiguration {
+            authorization_endpoint: format!("{}/oauth/authorize", issuer),
+            token_endpoint: format!("{}/oauth/token", issuer),
+            userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+            jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+            device_authorization_endpoint: format!("{}/oauth/device/code", issuer),
+            issuer,
+            response_types_supported: vec!["code"],
+            grant_types_supported: vec![
+                "authorization_code",
+                "refresh_token",
+                "client_credentials",
+                "urn:ietf:params:oauth:grant-type:device_code",
+            ],
+            subject_types_supported: vec!["public"],
+            id_token_signing_alg_values_supported: vec![app_data.token_issuer.algorithm_name()],
+            scopes_supported: vec![
+                "openid",
+                "email",
+                "poses",
+                "sequences",
+                "practice",
+                "security_events",
+            ],
+            token_endpoint_auth_methods_supported: vec![
+                "client_secret_basic",
+                "client_secret_post",
+                "none",
+            ],
+            code_challenge_methods_supported: vec!["S256"],
+            claims_supported: vec!["sub", "iss", "aud", "exp", "iat", "nonce", "email", "email_verified"],
+        })
+}
@@ -5,10 +5,16 @@ use oauth2::{CsrfToken, PkceCodeVerifier, AccessToken, RefreshToken};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
-use crate::auth::AuthName;
-
 pub struct TypedSession(Session);
 
+// Set while the admin logged in with this session is acting as another user.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ImpersonationMarker {
+    pub impersonation_id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
 impl TypedSession {
     const STATE_KEY: &'static str = "oauth_state";
     const PKCE_VERIFIER_KEY: &'static str = "oauth_code_verifier";
@@ -16,11 +22,15 @@ impl TypedSession {
     const REFRESH_KEY: &'static str = "refresh_token";
     const USER_ID_KEY: &'static str = "user_id";
     const OAUTH_PROVIDER_KEY: &'static str = "oauth_provider";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const RETURN_TO_KEY: &'static str = "return_to";
+    const ID_TOKEN_KEY: &'static str = "id_token";
+    const IMPERSONATION_KEY: &'static str = "impersonation";
 
-    pub fn insert_oauth_provider(&self, provider: AuthName) -> Result<(), SessionInsertError> {
+    pub fn insert_oauth_provider(&self, provider: &str) -> Result<(), SessionInsertError> {
         self.0.insert(Self::OAUTH_PROVIDER_KEY, provider)
     }
-    pub fn get_oauth_provider(&self) -> Result<Option<AuthName>, SessionGetError> {
+    pub fn get_oauth_provider(&self) -> Result<Option<String>, SessionGetError> {
         self.0.get(Self::OAUTH_PROVIDER_KEY)
     }
 
@@ -31,6 +41,24 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    // where to send the browser after login instead of the frontend,
+    // set when one of our registered clients sent a user to /oauth/authorize
+    pub fn set_return_to(&self, url: String) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::RETURN_TO_KEY, url)
+    }
+    pub fn take_return_to(&self) -> Result<Option<String>, SessionGetError> {
+        let url = self.0.get(Self::RETURN_TO_KEY)?;
+        self.0.remove(Self::RETURN_TO_KEY);
+        Ok(url)
+    }
+
     // logout
     pub fn purge(&self) {
         self.0.purge()
@@ -70,6 +98,24 @@ impl TypedSession {
     pub fn get_refresh_token(&self) -> Result<Option<RefreshToken>, SessionGetError> {
         self.0.get(Self::REFRESH_KEY)
     }
+
+    // the provider's id_token, sent back as id_token_hint when logging out there
+    pub fn set_id_token(&self, id_token: String) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::ID_TOKEN_KEY, id_token)
+    }
+    pub fn get_id_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::ID_TOKEN_KEY)
+    }
+
+    pub fn set_impersonation(&self, marker: ImpersonationMarker) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::IMPERSONATION_KEY, marker)
+    }
+    pub fn get_impersonation(&self) -> Result<Option<ImpersonationMarker>, SessionGetError> {
+        self.0.get(Self::IMPERSONATION_KEY)
+    }
+    pub fn end_impersonation(&self) {
+        self.0.remove(Self::IMPERSONATION_KEY);
+    }
 }
 
 impl FromRequest for TypedSession {
@@ -0,0 +1,74 @@
+use gloo_console::log;
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use super::csrf::protect;
+use super::errors::ApiError;
+use crate::API_BASE_URL;
+
+// defined in backend/src/routes/authorize.rs
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsentInfo {
+    pub request_id: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub scope: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ConsentDecision {
+    request_id: String,
+    approve: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsentRedirect {
+    pub redirect_to: String,
+}
+
+pub async fn get_consent_request(token: &str, request_id: &str) -> Result<ConsentInfo, ApiError> {
+    let response = Request::new(&format!("{}/oauth/consent/{}", API_BASE_URL, request_id))
+        .method(reqwasm::http::Method::GET)
+        .header("x-auth-token", token)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response.json::<ConsentInfo>().await.map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("get_consent_request reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
+
+pub async fn decide_consent(token: &str, request_id: &str, approve: bool) -> Result<ConsentRedirect, ApiError> {
+    let body = serde_json::to_string(&ConsentDecision {
+        request_id: request_id.to_string(),
+        approve,
+    })
+    .map_err(|_| ApiError::Unknown)?;
+    let request = Request::new(&format!("{}/oauth/consent", API_BASE_URL))
+        .method(reqwasm::http::Method::POST)
+        .header("x-auth-token", token)
+        .header("content-type", "application/json")
+        .body(body);
+    let response = protect(request).await.send().await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response.json::<ConsentRedirect>().await.map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("decide_consent reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
@@ -0,0 +1,60 @@
+use gloo_console::log;
+use gloo_utils::document;
+use reqwasm::http::Request;
+use serde::Deserialize;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlDocument;
+use crate::API_BASE_URL;
+
+// defined in backend/src/csrf.rs
+// Requests that change something have to echo the csrf_token cookie in this header.
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "x-csrf-token";
+
+#[derive(Deserialize, Debug)]
+struct CsrfResponse {
+    csrf_token: String,
+}
+
+pub fn read_cookie(name: &str) -> Option<String> {
+    let document = document().unchecked_into::<HtmlDocument>();
+    let cookie_string = document.cookie().ok()?;
+    for raw_cookie in cookie_string.split("; ") {
+        if let Some((key, value)) = raw_cookie.split_once('=') {
+            if key == name {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+// The backend sets the cookie with any response, before the first one ask for it.
+async fn csrf_token() -> Option<String> {
+    if let Some(token) = read_cookie(CSRF_COOKIE) {
+        return Some(token);
+    }
+    let response = Request::new(&format!("{}/auth/csrf", API_BASE_URL))
+        .method(reqwasm::http::Method::GET)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.ok() => response
+            .json::<CsrfResponse>()
+            .await
+            .ok()
+            .map(|body| body.csrf_token),
+        _ => {
+            log!("csrf_token reqwasm err");
+            None
+        }
+    }
+}
+
+// Every POST, PATCH, PUT and DELETE goes through here.
+pub async fn protect(request: Request) -> Request {
+    match csrf_token().await {
+        Some(token) => request.header(CSRF_HEADER, &token),
+        None => request,
+    }
+}
@@ -0,0 +1,73 @@
+use gloo_console::log;
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use super::csrf::protect;
+use super::errors::ApiError;
+use crate::API_BASE_URL;
+
+// defined in backend/src/routes/device.rs
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeviceRequestInfo {
+    pub user_code: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub scope: String,
+    pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DeviceDecision {
+    user_code: String,
+    approve: bool,
+}
+
+pub async fn get_device_request(token: &str, user_code: &str) -> Result<DeviceRequestInfo, ApiError> {
+    let response = Request::new(&format!("{}/device/{}", API_BASE_URL, user_code))
+        .method(reqwasm::http::Method::GET)
+        .header("x-auth-token", token)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response
+                    .json::<DeviceRequestInfo>()
+                    .await
+                    .map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("get_device_request reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
+
+pub async fn decide_device_request(token: &str, user_code: &str, approve: bool) -> Result<(), ApiError> {
+    let body = serde_json::to_string(&DeviceDecision {
+        user_code: user_code.to_string(),
+        approve,
+    })
+    .map_err(|_| ApiError::Unknown)?;
+    let request = Request::new(&format!("{}/device", API_BASE_URL))
+        .method(reqwasm::http::Method::POST)
+        .header("x-auth-token", token)
+        .header("content-type", "application/json")
+        .body(body);
+    let response = protect(request).await.send().await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return Ok(());
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("decide_device_request reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
@@ -3,6 +3,8 @@
 pub enum ApiError {
     #[error("Expired or missing auth token")]
     NotAuthenticated,
+    #[error("Not found")]
+    NotFound,
     #[error("Unknown network error")]
     Unknown,
     #[error("channel error")]
@@ -0,0 +1,67 @@
+use gloo_console::log;
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use super::csrf::protect;
+use super::errors::ApiError;
+use crate::API_BASE_URL;
+
+// defined in backend/src/routes/impersonation.rs
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImpersonationStatus {
+    pub active: bool,
+    pub email: Option<String>,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImpersonationStopped {
+    // the admin's own token, when the impersonation was started from this browser
+    pub access_token: Option<String>,
+}
+
+pub async fn get_impersonation(token: &str) -> Result<ImpersonationStatus, ApiError> {
+    let response = Request::new(&format!("{}/impersonation", API_BASE_URL))
+        .method(reqwasm::http::Method::GET)
+        .header("x-auth-token", token)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response
+                    .json::<ImpersonationStatus>()
+                    .await
+                    .map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("get_impersonation reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
+
+pub async fn stop_impersonation(token: &str) -> Result<ImpersonationStopped, ApiError> {
+    let request = Request::new(&format!("{}/impersonation/stop", API_BASE_URL))
+        .method(reqwasm::http::Method::POST)
+        .header("x-auth-token", token);
+    let response = protect(request).await.send().await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response
+                    .json::<ImpersonationStopped>()
+                    .await
+                    .map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("stop_impersonation reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
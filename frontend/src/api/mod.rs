@@ -1,2 +1,8 @@
+pub mod consent;
+pub mod csrf;
+pub mod device;
+pub mod impersonation;
 pub mod poses;
+pub mod providers;
+pub mod stats;
 pub mod errors;
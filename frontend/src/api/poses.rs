@@ -10,16 +10,55 @@ use crate::API_BASE_URL;
 pub struct PoseInfo {
     pub id: i32,
     pub name: String,
+    pub sanskrit_name: String,
+    pub category: String,
+    pub difficulty: String,
+    pub description: String,
+    pub cues: Vec<String>,
+    pub contraindications: Vec<String>,
+    pub image: Option<PoseImage>,
+    pub version: i32,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+// signed urls, good for an hour
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoseImage {
+    pub url: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Thumbnail {
+    pub width: i32,
+    pub url: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PoseListResponse {
     pub poses: Vec<PoseInfo>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
 }
 
-pub async fn get_poses(token: &str) -> Result<PoseListResponse, ApiError> {
-    log!("begin get_tasks request");
-    let response = Request::new(&format!("{}/poses", API_BASE_URL))
+const PAGE_SIZE: i64 = 20;
+
+// one page, the first without a cursor
+pub async fn get_poses(token: &str, cursor: Option<&str>) -> Result<PoseListResponse, ApiError> {
+    log!("begin get_poses request");
+    let url = match cursor {
+        Some(cursor) => format!("{}/poses?limit={}&cursor={}", API_BASE_URL, PAGE_SIZE, cursor),
+        None => format!("{}/poses?limit={}", API_BASE_URL, PAGE_SIZE),
+    };
+    let response = Request::new(&url)
         .method(reqwasm::http::Method::GET)
         .header("x-auth-token", token)
         .send()
@@ -0,0 +1,33 @@
+use gloo_console::log;
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use super::errors::ApiError;
+use crate::API_BASE_URL;
+
+// defined in backend/src/routes/oauth.rs
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoginProvider {
+    pub id: String,
+    pub display_name: String,
+    pub icon: Option<String>,
+    pub login_url: String,
+}
+
+pub async fn get_login_providers() -> Result<Vec<LoginProvider>, ApiError> {
+    let response = Request::new(&format!("{}/auth/providers", API_BASE_URL))
+        .method(reqwasm::http::Method::GET)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response
+                    .json::<Vec<LoginProvider>>()
+                    .await
+                    .map_err(|_| ApiError::Unknown);
+            }
+        }
+        Err(_) => log!("get_login_providers reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
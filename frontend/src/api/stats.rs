@@ -0,0 +1,64 @@
+use gloo_console::log;
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use super::errors::ApiError;
+use crate::API_BASE_URL;
+
+// defined in backend/src/database/stats.rs
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PracticeStats {
+    pub timezone: String,
+    pub session_count: i64,
+    pub total_seconds: i64,
+    pub weeks: Vec<WeekStats>,
+    pub current_streak_days: i64,
+    pub longest_streak_days: i64,
+    pub top_poses: Vec<PoseStats>,
+    pub categories: Vec<CategoryStats>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WeekStats {
+    pub week_start: String,
+    pub sessions: i64,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoseStats {
+    pub pose_id: i32,
+    pub pose_name: String,
+    pub times: i64,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CategoryStats {
+    pub category: String,
+    pub times: i64,
+    pub seconds: i64,
+}
+
+pub async fn get_stats(token: &str) -> Result<PracticeStats, ApiError> {
+    let response = Request::new(&format!("{}/me/stats", API_BASE_URL))
+        .method(reqwasm::http::Method::GET)
+        .header("x-auth-token", token)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            if response.ok() {
+                return response
+                    .json::<PracticeStats>()
+                    .await
+                    .map_err(|_| ApiError::Unknown);
+            }
+            if response.status() == 401 {
+                return Err(ApiError::NotAuthenticated);
+            }
+            return Err(ApiError::NotFound);
+        }
+        Err(_) => log!("get_stats reqwasm err"),
+    }
+    Err(ApiError::Unknown)
+}
@@ -1,10 +1,13 @@
 use stylist::{css, yew::styled_component};
 use yew::prelude::*;
+use yewdux::prelude::*;
 
+use crate::api::impersonation::{get_impersonation, stop_impersonation, ImpersonationStatus};
 use crate::components::atoms::bb_button::BBButton;
 use crate::components::atoms::link::BBLink;
 use crate::contexts::{use_theme, ThemeKind};
 use crate::router::Route;
+use crate::store::PoseStore;
 
 #[styled_component]
 pub fn Navbar() -> Html {
@@ -37,6 +40,24 @@ pub fn Navbar() -> Html {
           .nav-right button {
             margin-left: 10px;
           }
+
+          .impersonation {
+            display: flex;
+            justify-content: center;
+            align-items: center;
+            position: fixed;
+            top: 50px;
+            left: 0px;
+            right: 0px;
+            height: 30px;
+            background-color: darkorange;
+            color: black;
+            z-index: 100;
+          }
+
+          .impersonation button {
+            margin-left: 10px;
+          }
         "#,
         bg = theme.background_color.clone(),
         ft_color = theme.font_color.clone(),
@@ -52,6 +73,58 @@ pub fn Navbar() -> Html {
     };
     let switch_theme = Callback::from(move |_| theme.set(other_theme.clone()));
 
+    // an admin acting as another user always sees whom they are acting as
+    let (store, dispatch) = use_store::<PoseStore>();
+    let impersonation = use_state(ImpersonationStatus::default);
+    {
+        let impersonation = impersonation.clone();
+        use_effect_with_deps(
+            move |token: &String| {
+                let token = token.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    if token.is_empty() {
+                        impersonation.set(ImpersonationStatus::default());
+                        return;
+                    }
+                    match get_impersonation(&token).await {
+                        Ok(status) => impersonation.set(status),
+                        Err(_) => impersonation.set(ImpersonationStatus::default()),
+                    }
+                });
+                || ()
+            },
+            store.token.clone(),
+        );
+    }
+    let stop = {
+        let token = store.token.clone();
+        Callback::from(move |_| {
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(stopped) = stop_impersonation(&token).await {
+                    let admin_token = stopped.access_token.unwrap_or_default();
+                    dispatch.reduce_mut(move |store| {
+                        store.token = admin_token;
+                        store.poses = Vec::new();
+                    });
+                }
+            });
+        })
+    };
+    let banner = if impersonation.active {
+        let until = impersonation.expires_at.clone().unwrap_or_default();
+        let email = impersonation.email.clone().unwrap_or_default();
+        html! {
+            <div class="impersonation">
+                <span>{ format!("Viewing as {} until {}", email, until) }</span>
+                <BBButton onclick={stop} label="Stop" />
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
     // <button class={style} onclick={switch_theme} id="yew-sample-button">{"Switch to "}{theme_str}</button>
 
     html! {
@@ -59,9 +132,11 @@ pub fn Navbar() -> Html {
             <nav class="header">
                 <BBLink text="Home" route={Route::Home} />
                 <BBLink text="Portfolio" route={Route::Portfolio} />
+                <BBLink text="Dashboard" route={Route::Dashboard} />
                 <BBLink text="Login" route={Route::Login} />
                 <BBButton onclick={switch_theme} label={theme_str} />
             </nav>
+            { banner }
         </div>
     }
 }
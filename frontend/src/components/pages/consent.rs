@@ -0,0 +1,101 @@
+use gloo_utils::window;
+use serde::Deserialize;
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewdux::prelude::*;
+
+use crate::api::consent::{decide_consent, get_consent_request, ConsentInfo};
+use crate::components::atoms::bb_button::BBButton;
+use crate::components::pages::login::access_token_cookie;
+use crate::store::PoseStore;
+
+#[derive(Deserialize, Default)]
+struct ConsentQuery {
+    request_id: Option<String>,
+}
+
+// One of our other apps wants the user to "log in with yogamat".
+// The backend sends the browser here with the pending request id, the user approves or denies
+// and we go back to the app.
+#[function_component]
+pub fn Consent() -> Html {
+    let (store, dispatch) = use_store::<PoseStore>();
+    let request_id = use_location()
+        .and_then(|location| location.query::<ConsentQuery>().ok())
+        .unwrap_or_default()
+        .request_id
+        .unwrap_or_default();
+    let info = use_state(|| None::<ConsentInfo>);
+    let message = use_state(String::new);
+
+    // the user may have just logged in on the way here, without visiting login-success
+    let token = if store.token.is_empty() {
+        access_token_cookie().unwrap_or_default()
+    } else {
+        store.token.clone()
+    };
+    if store.token.is_empty() && !token.is_empty() {
+        let token = token.clone();
+        dispatch.reduce_mut(move |store| store.token = token);
+    }
+
+    {
+        let info = info.clone();
+        let message = message.clone();
+        let token = token.clone();
+        use_effect_with_deps(
+            move |request_id: &String| {
+                let request_id = request_id.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match get_consent_request(&token, &request_id).await {
+                        Ok(consent) => info.set(Some(consent)),
+                        Err(err) => message.set(err.to_string()),
+                    }
+                });
+                || ()
+            },
+            request_id.clone(),
+        );
+    }
+
+    let decide = |approve: bool| {
+        let token = token.clone();
+        let request_id = request_id.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let token = token.clone();
+            let request_id = request_id.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match decide_consent(&token, &request_id, approve).await {
+                    Ok(redirect) => {
+                        window().location().set_href(&redirect.redirect_to).ok();
+                    }
+                    Err(err) => message.set(err.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+        <>
+            <h1>{"Authorize"}</h1>
+            {
+                match &*info {
+                    Some(info) => html! {
+                        <div>
+                            <p>{ format!("{} wants to use your yogamat account", info.client_name) }</p>
+                            <ul>
+                                { info.scope.split_whitespace().map(|scope| html! { <li>{scope}</li> }).collect::<Html>() }
+                            </ul>
+                            <BBButton label="Allow" onclick={decide(true)} />
+                            <BBButton label="Deny" onclick={decide(false)} />
+                        </div>
+                    },
+                    None => html! {},
+                }
+            }
+            <p>{(*message).clone()}</p>
+        </>
+    }
+}
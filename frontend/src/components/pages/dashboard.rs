@@ -0,0 +1,110 @@
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewdux::prelude::*;
+
+use crate::api::stats::{get_stats, PracticeStats};
+use crate::{router::Route, store::PoseStore};
+
+// the widest bar of the charts, in px
+const BAR_WIDTH: i64 = 300;
+
+fn minutes(seconds: i64) -> String {
+    format!("{} min", seconds / 60)
+}
+
+fn bar(value: i64, max: i64) -> Html {
+    let width = if max > 0 { value * BAR_WIDTH / max } else { 0 };
+    html! {
+        <span style={format!("display: inline-block; height: 10px; background-color: steelblue; width: {}px; margin-right: 8px;", width)}></span>
+    }
+}
+
+// What the practice log adds up to.
+#[function_component]
+pub fn Dashboard() -> Html {
+    let (store, _dispatch) = use_store::<PoseStore>();
+    let stats = use_state(|| None::<PracticeStats>);
+    let message = use_state(String::new);
+
+    {
+        let stats = stats.clone();
+        let message = message.clone();
+        use_effect_with_deps(
+            move |token: &String| {
+                let token = token.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    if token.is_empty() {
+                        return;
+                    }
+                    match get_stats(&token).await {
+                        Ok(loaded) => stats.set(Some(loaded)),
+                        Err(err) => message.set(err.to_string()),
+                    }
+                });
+                || ()
+            },
+            store.token.clone(),
+        );
+    }
+
+    if store.token.is_empty() {
+        return html! {
+            <>
+                <h1>{"Dashboard"}</h1>
+                <p>{"Log in to see your practice."}</p>
+                <Link<Route> to={Route::Login}>{"Login"}</Link<Route>>
+            </>
+        };
+    }
+
+    let stats = match &*stats {
+        Some(stats) => stats,
+        None => {
+            return html! {
+                <>
+                    <h1>{"Dashboard"}</h1>
+                    <p>{(*message).clone()}</p>
+                </>
+            }
+        }
+    };
+    let busiest_week = stats.weeks.iter().map(|week| week.seconds).max().unwrap_or(0);
+    let busiest_category = stats.categories.iter().map(|c| c.seconds).max().unwrap_or(0);
+
+    html! {
+        <>
+            <h1>{"Dashboard"}</h1>
+            <p>{ format!("{} sessions, {} in total", stats.session_count, minutes(stats.total_seconds)) }</p>
+            <p>{ format!("streak: {} days, longest {} days ({})", stats.current_streak_days, stats.longest_streak_days, stats.timezone) }</p>
+
+            <h2>{"Weeks"}</h2>
+            {
+                stats.weeks.iter().map(|week| html! {
+                    <div>
+                        {bar(week.seconds, busiest_week)}
+                        { format!("{}: {} sessions, {}", week.week_start, week.sessions, minutes(week.seconds)) }
+                    </div>
+                }).collect::<Html>()
+            }
+
+            <h2>{"Most practiced"}</h2>
+            <ol>
+                {
+                    stats.top_poses.iter().map(|pose| html! {
+                        <li>{ format!("{}: {} times, {}", pose.pose_name, pose.times, minutes(pose.seconds)) }</li>
+                    }).collect::<Html>()
+                }
+            </ol>
+
+            <h2>{"Balance"}</h2>
+            {
+                stats.categories.iter().map(|category| html! {
+                    <div>
+                        {bar(category.seconds, busiest_category)}
+                        { format!("{}: {}", category.category, minutes(category.seconds)) }
+                    </div>
+                }).collect::<Html>()
+            }
+        </>
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Deserialize;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewdux::prelude::*;
+
+use crate::api::device::{decide_device_request, get_device_request, DeviceRequestInfo};
+use crate::api::errors::ApiError;
+use crate::components::atoms::bb_button::BBButton;
+use crate::{router::Route, store::PoseStore};
+
+#[derive(Deserialize, Default)]
+struct DeviceQuery {
+    user_code: Option<String>,
+}
+
+// The verification page of the device authorization grant.
+// The cli shows a code, the logged in user enters it here and approves or denies the device.
+#[function_component]
+pub fn DeviceVerification() -> Html {
+    let (store, _dispatch) = use_store::<PoseStore>();
+    let location = use_location();
+    let initial_code = location
+        .and_then(|location| location.query::<DeviceQuery>().ok())
+        .unwrap_or_default()
+        .user_code
+        .unwrap_or_default();
+    let user_code = use_state(move || initial_code);
+    let request = use_state(|| None::<DeviceRequestInfo>);
+    let message = use_state(String::new);
+
+    if store.token.is_empty() {
+        return html! {
+            <>
+                <h1>{"Connect a device"}</h1>
+                <p>{"Log in first, then come back to this page."}</p>
+                <Link<Route> to={Route::Login}>{"Login"}</Link<Route>>
+            </>
+        };
+    }
+
+    let oninput = {
+        let user_code = user_code.clone();
+        Callback::from(move |event: InputEvent| {
+            if let Some(input) = event.target().and_then(|t| t.dyn_into::<HtmlInputElement>().ok()) {
+                user_code.set(input.value());
+            }
+        })
+    };
+
+    let lookup = {
+        let token = store.token.clone();
+        let user_code = user_code.clone();
+        let request = request.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let token = token.clone();
+            let user_code = (*user_code).clone();
+            let request = request.clone();
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match get_device_request(&token, &user_code).await {
+                    Ok(info) => {
+                        message.set(String::new());
+                        request.set(Some(info));
+                    }
+                    Err(ApiError::NotAuthenticated) => message.set("Your login expired, log in again.".to_string()),
+                    Err(_) => message.set("That code is unknown or expired.".to_string()),
+                }
+            });
+        })
+    };
+
+    let decide = |approve: bool| {
+        let token = store.token.clone();
+        let request = request.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let token = token.clone();
+            let request = request.clone();
+            let message = message.clone();
+            let user_code = match &*request {
+                Some(info) => info.user_code.clone(),
+                None => return,
+            };
+            wasm_bindgen_futures::spawn_local(async move {
+                match decide_device_request(&token, &user_code, approve).await {
+                    Ok(()) if approve => message.set("Device connected, you can go back to it now.".to_string()),
+                    Ok(()) => message.set("Device denied.".to_string()),
+                    Err(err) => message.set(err.to_string()),
+                }
+                request.set(None);
+            });
+        })
+    };
+
+    html! {
+        <>
+            <h1>{"Connect a device"}</h1>
+            <p>{"Enter the code shown on your device."}</p>
+            <input type="text" value={(*user_code).clone()} {oninput} />
+            <BBButton label="Continue" onclick={lookup} />
+            {
+                match &*request {
+                    Some(info) => html! {
+                        <div>
+                            <p>{ format!("{} wants to access your account", info.client_name) }</p>
+                            if !info.scope.is_empty() {
+                                <p>{ format!("scope: {}", info.scope) }</p>
+                            }
+                            <BBButton label="Approve" onclick={decide(true)} />
+                            <BBButton label="Deny" onclick={decide(false)} />
+                        </div>
+                    },
+                    None => html! {},
+                }
+            }
+            <p>{(*message).clone()}</p>
+        </>
+    }
+}
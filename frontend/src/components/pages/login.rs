@@ -1,19 +1,34 @@
 use gloo_console::log;
 use yew::prelude::*;
 use yew_router::prelude::*;
-use gloo_utils::{window, document};
-use wasm_bindgen::JsCast;
-use web_sys::HtmlDocument;
+use gloo_utils::window;
 use yewdux::prelude::*;
-use crate::{API_BASE_URL, router::Route, store::PoseStore};
+use crate::api::csrf::read_cookie;
+use crate::api::providers::{get_login_providers, LoginProvider};
+use crate::{router::Route, store::PoseStore};
 use crate::contexts::use_theme;
 use stylist::{yew::styled_component, css};
 
 #[function_component]
 pub fn Login() -> Html {
-    let login_google_url = format!("{}/client-login/google", API_BASE_URL);
-    let login_fusion_url = format!("{}/client-login/fusion", API_BASE_URL);
-    let login_github_url = format!("{}/client-login/github", API_BASE_URL);
+    let providers = use_state(|| None::<Vec<LoginProvider>>);
+    let message = use_state(String::new);
+    {
+        let providers = providers.clone();
+        let message = message.clone();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match get_login_providers().await {
+                        Ok(list) => providers.set(Some(list)),
+                        Err(err) => message.set(err.to_string()),
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
 
     let theme = use_theme();
     let link_style = css!(r#"
@@ -23,41 +38,58 @@ pub fn Login() -> Html {
     "#,
         link_color = theme.link_color.clone(),
     );
+    let icon_style = css!(r#"
+        height: 16px;
+        width: 16px;
+        margin-right: 6px;
+        vertical-align: middle;
+    "#);
+
+    let buttons = match &*providers {
+        Some(list) if list.is_empty() => html! { <p>{"No login providers are enabled."}</p> },
+        Some(list) => html! {
+            <ul>
+                { for list.iter().map(|provider| html! {
+                    <li key={provider.id.clone()}>
+                        <a href={provider.login_url.clone()} class={link_style.clone()}>
+                            if let Some(icon) = &provider.icon {
+                                <img src={icon.clone()} alt="" class={icon_style.clone()} />
+                            }
+                            {format!("Login {}", provider.display_name)}
+                        </a>
+                    </li>
+                }) }
+            </ul>
+        },
+        None => html! {},
+    };
 
     html! {
         <>
             <h1>{"Login Page"}</h1>
-            <ul>
-                <li><a href={login_google_url} class={link_style.clone()}>{"Login Google"}</a></li>
-                <li><a href={login_fusion_url} class={link_style.clone()}>{"Login Fusion"}</a></li>
-                <li><a href={login_github_url} class={link_style}>{"Login GitHub"}</a></li>
-            </ul>
+            {buttons}
+            <p>{(*message).clone()}</p>
         </>
     }
 }
 
+// The backend puts our access token in a cookie when a login completes.
+pub fn access_token_cookie() -> Option<String> {
+    read_cookie("access_token")
+}
+
 #[function_component]
 pub fn LoginSuccess() -> Html {
 
-    let document = document().unchecked_into::<HtmlDocument>();
     let navigator = use_navigator().unwrap();
     let (_store, dispatch) = use_store::<PoseStore>();
 
-    if let Ok(cookie_string) = document.cookie() {
-        for raw_cookie in cookie_string.split("; ") {
-            //gloo_console::log!("cookie: {}", raw_cookie);
-            if let Some((key, value)) = raw_cookie.split_once('=') {
-                if key == "access_token" {
-                    gloo_console::log!("found access_token");
-                    navigator.push(&Route::Home);
-                    dispatch.reduce_mut(|mut store| {
-                        store.token = value.to_string();
-                    });
-                    break;
-                }
-            }
-        }
-        
+    if let Some(token) = access_token_cookie() {
+        gloo_console::log!("found access_token");
+        navigator.push(&Route::Home);
+        dispatch.reduce_mut(|mut store| {
+            store.token = token;
+        });
     } else {
         gloo_console::log!("no cookie");
         navigator.push(&Route::Login);
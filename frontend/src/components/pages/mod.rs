@@ -1,3 +1,6 @@
 pub mod home;
 pub mod portfolio;
 pub mod login;
+pub mod device;
+pub mod consent;
+pub mod dashboard;
@@ -2,35 +2,76 @@ use gloo_console::log;
 use yew::prelude::*;
 use yewdux::prelude::*;
 
-use crate::{store::PoseStore, api::poses::PoseInfo};
+use crate::components::atoms::bb_button::BBButton;
+use crate::{store::PoseStore, api::poses::{PoseImage, PoseInfo}};
 
 #[function_component]
 pub fn Portfolio() -> Html {
     let (store, dispatch) = use_store::<PoseStore>();
-    let store_clone = store.clone();
-    wasm_bindgen_futures::spawn_local(async move {
-        let token = store_clone.token.clone();
-        match crate::api::poses::get_poses(&token).await {
-            Ok(pose_response) => {
-                dispatch.reduce_mut(|store| store.poses = pose_response.poses);
-            }
-            Err(err) => {
-                log!("Portfolio() get_poses failed {}", err.to_string());
+    // cursors of the pages seen so far, the last one is the page shown
+    let cursors = use_state(|| vec![None::<String>]);
+    let next_cursor = use_state(|| None::<String>);
+    let total = use_state(|| 0_i64);
+
+    {
+        let token = store.token.clone();
+        let next_cursor = next_cursor.clone();
+        let total = total.clone();
+        use_effect_with_deps(
+            move |cursor: &Option<String>| {
+                let cursor = cursor.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match crate::api::poses::get_poses(&token, cursor.as_deref()).await {
+                        Ok(pose_response) => {
+                            next_cursor.set(pose_response.next_cursor);
+                            total.set(pose_response.total);
+                            dispatch.reduce_mut(|store| store.poses = pose_response.poses);
+                        }
+                        Err(err) => {
+                            log!("Portfolio() get_poses failed {}", err.to_string());
+                        },
+                    }
+                });
+                || ()
             },
-        }
-    });
+            cursors.last().cloned().flatten(),
+        );
+    }
     log!("poses: {}", store.poses.len());
 
-    /*
-    let tasks: Vec<TaskInfo> = use_store::<StoreType>()
-        .state()
-        .map(|store| store.tasks.clone())
-        .unwrap_or_default();
-    */
+    let previous = {
+        let cursors = cursors.clone();
+        Callback::from(move |_| {
+            if cursors.len() > 1 {
+                let mut earlier = (*cursors).clone();
+                earlier.pop();
+                cursors.set(earlier);
+            }
+        })
+    };
+    let next = {
+        let cursors = cursors.clone();
+        let next_cursor = next_cursor.clone();
+        Callback::from(move |_| {
+            if let Some(cursor) = (*next_cursor).clone() {
+                let mut later = (*cursors).clone();
+                later.push(Some(cursor));
+                cursors.set(later);
+            }
+        })
+    };
+
     html! {
         <>
             <h1>{"Amazing Projects"}</h1>
+            <p>{ format!("page {}, {} poses", cursors.len(), *total) }</p>
             {pose_data(&store.poses)}
+            if cursors.len() > 1 {
+                <BBButton label="Previous" onclick={previous} />
+            }
+            if next_cursor.is_some() {
+                <BBButton label="Next" onclick={next} />
+            }
         </>
     }
 }
@@ -39,10 +80,30 @@ fn pose_data(poses: &Vec<PoseInfo>) -> Html {
     html! {
         poses.into_iter().map(|pose| {
             html!{
-                <p>{ format!("id: {} name: {}", pose.id, pose.name) }</p>
+                <div>
+                    <h3>{ format!("{} ({})", pose.name, pose.sanskrit_name) }</h3>
+                    if let Some(image) = &pose.image {
+                        <img src={ thumbnail_url(image) } alt={ pose.name.clone() } />
+                    }
+                    if pose.favorite {
+                        <p>{ "\u{2605} favorite" }</p>
+                    }
+                    <p>{ format!("{} / {}", pose.category, pose.difficulty) }</p>
+                    if !pose.tags.is_empty() {
+                        <p>{ pose.tags.join(", ") }</p>
+                    }
+                    <p>{ &pose.description }</p>
+                </div>
             }
         }).collect::<Html>()
     }
 }
 
-
+// the narrowest thumbnail is plenty for the list
+fn thumbnail_url(image: &PoseImage) -> String {
+    image
+        .thumbnails
+        .first()
+        .map(|thumbnail| thumbnail.url.clone())
+        .unwrap_or_else(|| image.url.clone())
+}
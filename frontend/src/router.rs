@@ -5,6 +5,9 @@ use crate::components::pages::home::Home;
 use crate::components::pages::portfolio::Portfolio;
 use crate::components::pages::login::LoginSuccess;
 use crate::components::pages::login::Login;
+use crate::components::pages::device::DeviceVerification;
+use crate::components::pages::consent::Consent;
+use crate::components::pages::dashboard::Dashboard;
 
 #[derive(Clone, Routable, PartialEq)]
 pub enum Route {
@@ -16,6 +19,12 @@ pub enum Route {
     LoginSuccess,
     #[at("/login")]
     Login,
+    #[at("/device")]
+    Device,
+    #[at("/consent")]
+    Consent,
+    #[at("/dashboard")]
+    Dashboard,
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -28,5 +37,8 @@ pub fn switch(routes: Route) -> Html {
         Route::Portfolio => html! { <Portfolio /> },
         Route::Login => html! { <Login /> },
         Route::LoginSuccess => html! { <LoginSuccess /> },
+        Route::Device => html! { <DeviceVerification /> },
+        Route::Consent => html! { <Consent /> },
+        Route::Dashboard => html! { <Dashboard /> },
     }
 }